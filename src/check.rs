@@ -0,0 +1,852 @@
+use crate::expr::Expr;
+use crate::lint::{always_leaves, ungroup};
+use crate::scanner::{LiteralValue, Span, Token, TokenType};
+use crate::statement::Statement;
+use crate::types::Type;
+use std::collections::HashMap;
+use std::fmt::Display;
+
+#[derive(Debug, Clone)]
+pub struct TypeError {
+    pub span: Span,
+    pub message: String,
+}
+
+impl Display for TypeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: error: {}", self.span, self.message)
+    }
+}
+
+/// Walks a parsed program without running it and reports every operation
+/// that would fail for the types its operands can have.
+///
+/// Checking is gradual: a variable declared with a `: type` annotation has
+/// that type, a `const` has the type of its value, and anything else is
+/// `any`, which is never an error. Mixing `any` with a known type is only an
+/// error when no value of `any` could make it work.
+///
+/// Where more is known about a variable it has a narrower type: after a
+/// `!= nil` test it can't be nil, and after an assignment it has the type of
+/// the value assigned, until paths through the program meet again or a loop
+/// or function might have changed it.
+pub fn check(statements: &[Statement]) -> Vec<TypeError> {
+    let mut checker = Checker {
+        scopes: vec![HashMap::new()],
+        errors: vec![],
+        span: Span { line: 1, column: 1 },
+        returns: None,
+    };
+    for stmt in statements {
+        checker.statement(stmt);
+    }
+    checker.errors
+}
+
+/// A variable's declared type, and the type it is known to have at the
+/// point being checked.
+#[derive(Debug, Clone, Copy)]
+struct Var {
+    declared: Type,
+    current: Type,
+}
+
+type Scopes = Vec<HashMap<String, Var>>;
+
+struct Checker {
+    scopes: Scopes,
+    errors: Vec<TypeError>,
+    /// Where to report an error in an expression that has no span of its
+    /// own: the innermost statement or operator around it.
+    span: Span,
+    /// The annotated return type of the function being checked.
+    returns: Option<Type>,
+}
+
+/// Whether a value of type `actual` can be used where `expected` is needed.
+fn compatible(expected: Type, actual: Type) -> bool {
+    actual == Type::ANY || expected.contains(actual)
+}
+
+/// Joins what is known about variables on another path through the program
+/// into `scopes`, for where the two paths meet.
+fn merge(scopes: &mut Scopes, other: &Scopes) {
+    for (scope, other) in scopes.iter_mut().zip(other) {
+        for (name, var) in scope.iter_mut() {
+            if let Some(other) = other.get(name) {
+                var.current = var.current.union(other.current);
+            }
+        }
+    }
+}
+
+/// The variable `condition` compares with nil, and whether the comparison is
+/// true when the variable is nil.
+fn nil_test(condition: &Expr) -> Option<(&str, bool)> {
+    let Expr::Binary { left, op, right } = ungroup(condition) else {
+        return None;
+    };
+    let when_nil = match op.token_type {
+        TokenType::EQUALEQUAL => true,
+        TokenType::BANGEQUAL => false,
+        _ => return None,
+    };
+    let nil = |expr: &Expr| {
+        matches!(
+            ungroup(expr),
+            Expr::LiteralExpr {
+                literal: LiteralValue::Nil
+            }
+        )
+    };
+    match (ungroup(left), ungroup(right)) {
+        (Expr::Var { identifier, .. }, other) | (other, Expr::Var { identifier, .. })
+            if nil(other) =>
+        {
+            Some((identifier, when_nil))
+        }
+        _ => None,
+    }
+}
+
+/// Adds the names of the variables `stmt` assigns to `names`, including in
+/// the functions it defines, which may run at any time.
+fn assigned<'a>(stmt: &'a Statement, names: &mut Vec<&'a str>) {
+    match stmt {
+        Statement::Expression { expression, .. }
+        | Statement::Print { expression, .. }
+        | Statement::Var { expression, .. }
+        | Statement::Throw { expression, .. }
+        | Statement::Assert {
+            expression_a: expression,
+            ..
+        } => assigned_in(expression, names),
+        Statement::Return { value, .. } | Statement::Yield { value, .. } => {
+            if let Some(value) = value {
+                assigned_in(value, names);
+            }
+        }
+        Statement::Try {
+            body,
+            catch,
+            finally,
+            ..
+        } => {
+            assigned(body, names);
+            if let Some(catch) = catch {
+                assigned(&catch.body, names);
+            }
+            if let Some(finally) = finally {
+                assigned(finally, names);
+            }
+        }
+        Statement::Match { subject, arms, .. } => {
+            assigned_in(subject, names);
+            for arm in arms {
+                if let Some(guard) = &arm.guard {
+                    assigned_in(guard, names);
+                }
+                assigned(&arm.body, names);
+            }
+        }
+        Statement::Block { statements, .. } => {
+            for stmt in statements {
+                assigned(stmt, names);
+            }
+        }
+        Statement::If {
+            conditional,
+            happy_path,
+            sad_path,
+            ..
+        } => {
+            assigned_in(conditional, names);
+            assigned(happy_path, names);
+            if let Some(sad_path) = sad_path {
+                assigned(sad_path, names);
+            }
+        }
+        Statement::While {
+            predicate,
+            happy_path,
+            ..
+        } => {
+            assigned_in(predicate, names);
+            assigned(happy_path, names);
+        }
+        Statement::ForIn { iterable, body, .. } => {
+            assigned_in(iterable, names);
+            assigned(body, names);
+        }
+        Statement::Import { .. } | Statement::Enum { .. } | Statement::Break { .. } => {}
+    }
+}
+
+fn assigned_in<'a>(expr: &'a Expr, names: &mut Vec<&'a str>) {
+    match expr {
+        Expr::LiteralExpr { .. } | Expr::Var { .. } => {}
+        Expr::Assignment { name, value, .. } | Expr::Update { name, value, .. } => {
+            names.push(name);
+            assigned_in(value, names);
+        }
+        Expr::Grouping { expression } => assigned_in(expression, names),
+        Expr::Unary { right, .. } => assigned_in(right, names),
+        Expr::Get { object, .. } => assigned_in(object, names),
+        Expr::Binary { left, right, .. }
+        | Expr::Logical { left, right, .. }
+        | Expr::Coalesce { left, right, .. }
+        | Expr::Index {
+            object: left,
+            index: right,
+        } => {
+            assigned_in(left, names);
+            assigned_in(right, names);
+        }
+        Expr::Conditional {
+            condition,
+            then_branch,
+            else_branch,
+            ..
+        } => {
+            for expr in [condition, then_branch, else_branch] {
+                assigned_in(expr, names);
+            }
+        }
+        Expr::Call {
+            callee, arguments, ..
+        } => {
+            assigned_in(callee, names);
+            for argument in arguments {
+                assigned_in(argument, names);
+            }
+        }
+        Expr::Range {
+            start, end, step, ..
+        } => {
+            for bound in [Some(start), Some(end), step.as_ref()]
+                .into_iter()
+                .flatten()
+            {
+                assigned_in(bound, names);
+            }
+        }
+        Expr::Function { body, .. } => {
+            for stmt in body {
+                assigned(stmt, names);
+            }
+        }
+        Expr::Interpolation { parts } => {
+            for part in parts {
+                assigned_in(part, names);
+            }
+        }
+    }
+}
+
+/// The type `left op right` has for values of a single basic type each, or
+/// `None` if the operator fails for them.
+fn operation(op: &TokenType, left: Type, right: Type) -> Option<Type> {
+    let numbers = left == Type::NUM && right == Type::NUM;
+    let strings = left == Type::STR && right == Type::STR;
+    match op {
+        TokenType::EQUALEQUAL | TokenType::BANGEQUAL => Some(Type::BOOL),
+        TokenType::IS => (right == Type::OTHER).then_some(Type::BOOL),
+        TokenType::IN if strings || (right == Type::RANGE && left != Type::STR) => Some(Type::BOOL),
+        TokenType::PLUS if numbers => Some(Type::NUM),
+        TokenType::PLUS if strings => Some(Type::STR),
+        TokenType::STAR if left == Type::STR && right == Type::NUM => Some(Type::STR),
+        TokenType::LESS | TokenType::GREATER | TokenType::LESSEQUAL | TokenType::GREATEREQUAL
+            if numbers || strings =>
+        {
+            Some(Type::BOOL)
+        }
+        TokenType::MINUS
+        | TokenType::STAR
+        | TokenType::SLASH
+        | TokenType::PERCENT
+        | TokenType::DIV
+        | TokenType::STARSTAR
+        | TokenType::AMPERSAND
+        | TokenType::PIPE
+        | TokenType::CARET
+        | TokenType::LESSLESS
+        | TokenType::GREATERGREATER
+            if numbers =>
+        {
+            Some(Type::NUM)
+        }
+        _ => None,
+    }
+}
+
+/// The type of `left op right`, or `None` if it fails for some of the
+/// values the operands can have. With an `any` operand it only fails if it
+/// fails for every value.
+fn binary(op: &TokenType, left: Type, right: Type) -> Option<Type> {
+    let mut result = Type::NONE;
+    let mut failed = false;
+    for l in left.members() {
+        for r in right.members() {
+            match operation(op, l, r) {
+                Some(ty) => result = result.union(ty),
+                None => failed = true,
+            }
+        }
+    }
+    let gradual = left == Type::ANY || right == Type::ANY;
+    match (failed, gradual, result == Type::NONE) {
+        (true, false, _) | (_, true, true) => None,
+        _ => Some(result),
+    }
+}
+
+impl Checker {
+    fn error(&mut self, span: Span, message: String) {
+        self.errors.push(TypeError { span, message });
+    }
+
+    fn declare(&mut self, name: &str, ty: Type) {
+        if let Some(scope) = self.scopes.last_mut() {
+            let var = Var {
+                declared: ty,
+                current: ty,
+            };
+            scope.insert(name.to_string(), var);
+        }
+    }
+
+    fn var(&mut self, name: &str) -> Option<&mut Var> {
+        self.scopes
+            .iter_mut()
+            .rev()
+            .find_map(|scope| scope.get_mut(name))
+    }
+
+    /// The type a variable is known to have here, or `any` for one declared
+    /// outside the program, such as in a module.
+    fn lookup(&self, name: &str) -> Type {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .map_or(Type::ANY, |var| var.current)
+    }
+
+    /// Checks a value assigned to a variable against its declared type, and
+    /// narrows the variable to the value's type if it has one.
+    fn assign(&mut self, name: &str, ty: Type, span: Span) {
+        let Some(var) = self.var(name) else {
+            return;
+        };
+        let declared = var.declared;
+        let fits = compatible(declared, ty);
+        var.current = match fits && declared != Type::ANY && ty != Type::ANY {
+            true => ty,
+            false => declared,
+        };
+        if !fits {
+            self.error(
+                span,
+                format!("Cannot assign {} to '{}' of type {}", ty, name, declared),
+            );
+        }
+    }
+
+    /// Narrows the variables `condition` compares with nil to the types they
+    /// have where it is `truth`. `!`, `and` and `or` are looked through where
+    /// every operand's value is then known.
+    fn narrow(&mut self, condition: &Expr, truth: bool) {
+        match ungroup(condition) {
+            Expr::Unary { operator, right } if operator.token_type == TokenType::BANG => {
+                self.narrow(right, !truth)
+            }
+            Expr::Logical { left, op, right } if (op.token_type == TokenType::AND) == truth => {
+                self.narrow(left, truth);
+                self.narrow(right, truth);
+            }
+            condition => {
+                let Some((name, when_nil)) = nil_test(condition) else {
+                    return;
+                };
+                let Some(var) = self.var(name) else {
+                    return;
+                };
+                if var.current == Type::ANY {
+                    return;
+                }
+                if truth != when_nil {
+                    var.current = var.current.without(Type::NIL);
+                } else if var.current.contains(Type::NIL) {
+                    var.current = Type::NIL;
+                }
+            }
+        }
+    }
+
+    /// Checks a loop or `try`, which may run its parts any number of times
+    /// or stop partway, so the variables `stmt` assigns have their declared
+    /// types throughout it and after it.
+    fn repeated(&mut self, stmt: &Statement, check: impl FnOnce(&mut Self)) {
+        let mut names = vec![];
+        assigned(stmt, &mut names);
+        for name in names {
+            if let Some(var) = self.var(name) {
+                var.current = var.declared;
+            }
+        }
+        let before = self.scopes.clone();
+        check(self);
+        self.scopes = before;
+    }
+
+    fn logical_operand(&mut self, op: &Token, ty: Type) {
+        if !compatible(Type::BOOL, ty) {
+            self.error(
+                op.span(),
+                format!("Operands of '{}' must be bool, not {}", op.lexeme, ty),
+            );
+        }
+    }
+
+    fn condition(&mut self, condition: &Expr, what: &str, span: Span) {
+        self.span = span;
+        let ty = self.expression(condition);
+        if !compatible(Type::BOOL, ty) {
+            self.error(span, format!("{} must be bool, not {}", what, ty));
+        }
+    }
+
+    fn block(&mut self, statements: &[Statement]) {
+        self.scopes.push(HashMap::new());
+        for stmt in statements {
+            self.statement(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        self.span = stmt.span();
+        match stmt {
+            Statement::Expression { expression, .. } | Statement::Print { expression, .. } => {
+                self.expression(expression);
+            }
+            Statement::Var {
+                indentifier,
+                expression,
+                span,
+                constant,
+                annotation,
+                ..
+            } => {
+                let ty = self.expression(expression);
+                let declared = match (annotation, constant) {
+                    (Some(annotation), _) => *annotation,
+                    (None, true) => ty,
+                    (None, false) => Type::ANY,
+                };
+                self.declare(indentifier, declared);
+                self.assign(indentifier, ty, *span);
+            }
+            Statement::Import { name, .. } | Statement::Enum { name, .. } => {
+                self.declare(name, Type::OTHER)
+            }
+            Statement::Throw { expression, .. } => {
+                self.expression(expression);
+            }
+            Statement::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => self.repeated(stmt, |checker| {
+                checker.statement(body);
+                if let Some(catch) = catch {
+                    checker.scopes.push(HashMap::new());
+                    checker.declare(&catch.name, Type::ANY);
+                    checker.statement(&catch.body);
+                    checker.scopes.pop();
+                }
+                if let Some(finally) = finally {
+                    checker.statement(finally);
+                }
+            }),
+            Statement::Break { .. } => {}
+            Statement::Return { value, span } => {
+                let ty = match value {
+                    Some(value) => self.expression(value),
+                    None => Type::NIL,
+                };
+                if let Some(expected) = self.returns {
+                    if !compatible(expected, ty) {
+                        self.error(
+                            *span,
+                            format!(
+                                "Cannot return {} from a function returning {}",
+                                ty, expected
+                            ),
+                        );
+                    }
+                }
+            }
+            Statement::Yield { value, .. } => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Statement::Match { subject, arms, .. } => {
+                self.expression(subject);
+                let before = self.scopes.clone();
+                let mut after = before.clone();
+                for arm in arms {
+                    self.scopes = before.clone();
+                    self.scopes.push(HashMap::new());
+                    for (name, _) in arm.pattern.names() {
+                        self.declare(name, Type::ANY);
+                    }
+                    if let Some(guard) = &arm.guard {
+                        self.condition(guard, "Guard of a match arm", arm.span);
+                        self.narrow(guard, true);
+                    }
+                    self.statement(&arm.body);
+                    self.scopes.pop();
+                    if always_leaves(&arm.body).is_none() {
+                        merge(&mut after, &self.scopes);
+                    }
+                }
+                self.scopes = after;
+            }
+            Statement::Assert { expression_a, span } => {
+                self.condition(expression_a, "Condition of 'assert'", *span)
+            }
+            Statement::Block { statements, .. } => self.block(statements),
+            Statement::If {
+                conditional,
+                happy_path,
+                sad_path,
+                span,
+            } => {
+                self.condition(conditional, "Condition of 'if'", *span);
+                let before = self.scopes.clone();
+                self.narrow(conditional, true);
+                self.statement(happy_path);
+                let after_then = std::mem::replace(&mut self.scopes, before);
+                self.narrow(conditional, false);
+                if let Some(sad_path) = sad_path {
+                    self.statement(sad_path);
+                }
+                // A branch that always jumps away leaves what is known on
+                // the other one.
+                let then_leaves = always_leaves(happy_path).is_some();
+                let else_leaves = sad_path.as_deref().and_then(always_leaves).is_some();
+                match (then_leaves, else_leaves) {
+                    (true, false) => {}
+                    (false, true) => self.scopes = after_then,
+                    _ => merge(&mut self.scopes, &after_then),
+                }
+            }
+            Statement::While {
+                predicate,
+                happy_path,
+                span,
+            } => self.repeated(stmt, |checker| {
+                checker.condition(predicate, "Condition of 'while'", *span);
+                checker.narrow(predicate, true);
+                checker.statement(happy_path);
+            }),
+            Statement::ForIn {
+                variable,
+                iterable,
+                body,
+                span,
+            } => {
+                let ty = self.expression(iterable);
+                let iterable = Type::STR.union(Type::RANGE).union(Type::OTHER);
+                if !compatible(iterable, ty) {
+                    self.error(*span, format!("Cannot iterate over {}", ty));
+                }
+                let item = match ty {
+                    Type::STR => Type::STR,
+                    Type::RANGE => Type::NUM,
+                    _ => Type::ANY,
+                };
+                self.repeated(stmt, |checker| {
+                    checker.scopes.push(HashMap::new());
+                    checker.declare(&variable.lexeme, item);
+                    checker.statement(body);
+                    checker.scopes.pop();
+                });
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::LiteralExpr { literal } => Type::of(literal),
+            Expr::Grouping { expression } => self.expression(expression),
+            Expr::Var { identifier, .. } => self.lookup(identifier),
+            Expr::Assignment { name, value, span } => {
+                let ty = self.expression(value);
+                self.assign(name, ty, *span);
+                ty
+            }
+            Expr::Update {
+                name,
+                op,
+                value,
+                span,
+                ..
+            } => {
+                let current = self.lookup(name);
+                let value = self.expression(value);
+                let ty = self.binary(op.token_type.clone(), &op.lexeme, current, value, *span);
+                self.assign(name, ty, *span);
+                ty
+            }
+            Expr::Binary { left, op, right } => {
+                let left = self.expression(left);
+                let right = self.expression(right);
+                self.binary(op.token_type.clone(), &op.lexeme, left, right, op.span())
+            }
+            Expr::Logical { left, op, right } => {
+                // Both operands are always evaluated, so the right one
+                // isn't narrowed by the left.
+                let ty = self.expression(left);
+                self.logical_operand(op, ty);
+                let ty = self.expression(right);
+                self.logical_operand(op, ty);
+                Type::BOOL
+            }
+            Expr::Unary { operator, right } => {
+                let ty = self.expression(right);
+                if operator.token_type == TokenType::BANG {
+                    return Type::BOOL;
+                }
+                if !compatible(Type::NUM, ty) {
+                    self.error(
+                        operator.span(),
+                        format!("Cannot apply '{}' to {}", operator.lexeme, ty),
+                    );
+                }
+                Type::NUM
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+                span,
+            } => {
+                let outer = self.span;
+                self.condition(condition, "Condition of '?:'", *span);
+                self.span = outer;
+                let before = self.scopes.clone();
+                self.narrow(condition, true);
+                let then_type = self.expression(then_branch);
+                let after_then = std::mem::replace(&mut self.scopes, before);
+                self.narrow(condition, false);
+                let else_type = self.expression(else_branch);
+                merge(&mut self.scopes, &after_then);
+                then_type.union(else_type)
+            }
+            Expr::Coalesce { left, right, .. } => {
+                let left = self.expression(left);
+                let before = self.scopes.clone();
+                let right = self.expression(right);
+                merge(&mut self.scopes, &before);
+                left.without(Type::NIL).union(right)
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+                Type::ANY
+            }
+            Expr::Get { object, .. } => {
+                self.expression(object);
+                Type::ANY
+            }
+            Expr::Range {
+                start, end, step, ..
+            } => {
+                let bounds = [Some(start), Some(end), step.as_ref()];
+                for bound in bounds.into_iter().flatten() {
+                    let ty = self.expression(bound);
+                    if !compatible(Type::NUM, ty) {
+                        self.error(self.span, format!("Range bounds must be num, not {}", ty));
+                    }
+                }
+                Type::RANGE
+            }
+            Expr::Index { object, index } => {
+                let object = self.expression(object);
+                if !compatible(Type::STR, object) {
+                    self.error(
+                        self.span,
+                        format!("Only strings can be indexed, not {}", object),
+                    );
+                }
+                let index = self.expression(index);
+                if !compatible(Type::NUM.union(Type::RANGE), index) {
+                    self.error(
+                        self.span,
+                        format!("String index must be num or range, not {}", index),
+                    );
+                }
+                Type::STR
+            }
+            Expr::Function {
+                params,
+                body,
+                generator,
+                span,
+                annotations,
+                returns,
+            } => {
+                // The function may be called after anything in scope has
+                // been assigned, so only the declared types are known.
+                let outer = self.span;
+                let before = self.scopes.clone();
+                for var in self.scopes.iter_mut().flat_map(|scope| scope.values_mut()) {
+                    var.current = var.declared;
+                }
+                let outer_returns = std::mem::replace(&mut self.returns, *returns);
+                self.scopes.push(HashMap::new());
+                for (param, annotation) in params.iter().zip(annotations) {
+                    self.declare(&param.lexeme, annotation.unwrap_or(Type::ANY));
+                }
+                self.block(body);
+                self.scopes = before;
+                self.returns = outer_returns;
+                self.span = outer;
+                let ends = body.iter().find_map(always_leaves).is_none();
+                if let Some(returns) = returns {
+                    if ends && !generator && !returns.contains(Type::NIL) {
+                        self.error(
+                            *span,
+                            format!("Function returning {} can end without returning", returns),
+                        );
+                    }
+                }
+                Type::FUNCTION
+            }
+            Expr::Interpolation { parts } => {
+                for part in parts {
+                    self.expression(part);
+                }
+                Type::STR
+            }
+        }
+    }
+
+    /// The type of a binary operation, reporting it if it can fail. A
+    /// failed one is `any` so the error isn't reported again further out.
+    fn binary(&mut self, op: TokenType, lexeme: &str, left: Type, right: Type, span: Span) -> Type {
+        match binary(&op, left, right) {
+            Some(ty) => ty,
+            None => {
+                self.error(
+                    span,
+                    format!("Cannot apply '{}' to {} and {}", lexeme, left, right),
+                );
+                Type::ANY
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::check;
+    use crate::parser::Parser;
+    use crate::Scanner;
+
+    fn errors(source: &str) -> Vec<String> {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse();
+        assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+        check(&statements)
+            .into_iter()
+            .map(|e| e.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_check() {
+        let source = "var a: num = 1;\n\
+                      var b: str? = nil;\n\
+                      a = \"x\";\n\
+                      print \"a\" - 1;\n\
+                      if (a) print b;\n\
+                      print b + \"!\";\n\
+                      print (b ?? \"\") + \"!\";\n\
+                      var c = 1;\n\
+                      print c - \"a\";\n\
+                      const d = true;\n\
+                      print -d;\n\
+                      for (x in 0..3) print x * 2;\n\
+                      var f = (n) => n + a;\n\
+                      var g: num | str = a;\n";
+        assert_eq!(
+            vec![
+                "3:1: error: Cannot assign str to 'a' of type num",
+                "4:11: error: Cannot apply '-' to str and num",
+                "5:1: error: Condition of 'if' must be bool, not num",
+                "6:9: error: Cannot apply '+' to nil and str",
+                "9:9: error: Cannot apply '-' to any and str",
+                "11:7: error: Cannot apply '-' to bool",
+            ],
+            errors(source)
+        );
+        assert!(errors("var x = 1; x = \"s\"; print x;").is_empty());
+        assert_eq!(
+            vec![
+                "1:9: error: Cannot apply 'in' to num and str",
+                "2:11: error: Cannot apply 'in' to str and range",
+            ],
+            errors("print 3 in \"abc\";\nprint \"a\" in 0..3;\nprint 1 in 0..3;")
+        );
+
+        let tokens = Scanner::new("var x: integer = 1;").scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        parser.parse();
+        assert_eq!(1, parser.errors().len());
+    }
+
+    #[test]
+    fn test_narrowing() {
+        let source = "var f = fun (a: num?, b: str): num {\n\
+                      if (a != nil) print a + 1;\n\
+                      print a + 1;\n\
+                      if (a == nil) return 0;\n\
+                      print a * 2;\n\
+                      return b;\n\
+                      };\n\
+                      var y: num | str = 1;\n\
+                      print y - 1;\n\
+                      if (y > 0) y = \"s\";\n\
+                      print y - 1;\n\
+                      var m: num? = 1;\n\
+                      while (m != nil) { print m + 1; m = nil; }\n\
+                      print m + 1;\n\
+                      print m != nil and m > 0 ? m + 1 : 0;\n\
+                      var g = fun (): str { print y; };\n\
+                      var h = (n: num): str => n * 2;\n";
+        assert_eq!(
+            vec![
+                "3:9: error: Cannot apply '+' to num? and num",
+                "6:1: error: Cannot return str from a function returning num",
+                "11:9: error: Cannot apply '-' to num | str and num",
+                "14:9: error: Cannot apply '+' to num? and num",
+                "15:22: error: Cannot apply '>' to num? and num",
+                "16:9: error: Function returning str can end without returning",
+                "17:26: error: Cannot return num from a function returning str",
+            ],
+            errors(source)
+        );
+        assert!(errors("var k: num? = nil; k = 2; print k + 1;").is_empty());
+        assert!(errors("var f = fun (x: num?): num? { return; };").is_empty());
+    }
+}
@@ -0,0 +1,295 @@
+use crate::environment::Environment;
+use crate::expr::Expr;
+use crate::interpreter::Hook;
+use crate::parser::Parser;
+use crate::scanner::Span;
+use crate::statement::Statement;
+use crate::{LoxErr, Scanner};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+
+/// A condition whose outcome decides which way execution goes: the
+/// condition of an `if`, `while` or `?:`, the left side of a `??`, whether a
+/// `for-in` loop has another value, an arm of a `match`, or one operand of an
+/// `and`/`or`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+struct Condition {
+    span: Span,
+    operand: usize,
+}
+
+/// Records which lines ran and which way every condition went, and writes
+/// them out as an LCOV tracefile. The script is parsed up front so that
+/// lines and branches that never run are reported as well.
+pub struct Coverage {
+    path: String,
+    lines: BTreeMap<usize, u64>,
+    /// How often each condition was false and true, in that order.
+    conditions: BTreeMap<Condition, [u64; 2]>,
+}
+
+impl Coverage {
+    pub fn new(path: &str, source: &str) -> Self {
+        let mut coverage = Self {
+            path: path.to_string(),
+            lines: BTreeMap::new(),
+            conditions: BTreeMap::new(),
+        };
+        if let Ok(tokens) = Scanner::new(source).scan_tokens() {
+            for stmt in &Parser::new(tokens).parse() {
+                coverage.statement(stmt);
+            }
+        }
+        coverage
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        self.lines.entry(stmt.span().line).or_default();
+        match stmt {
+            Statement::Expression { expression, .. }
+            | Statement::Print { expression, .. }
+            | Statement::Var { expression, .. } => self.expression(expression),
+            Statement::Assert { expression_a, .. } => self.expression(expression_a),
+            Statement::Throw { expression, .. } => self.expression(expression),
+            Statement::Return { value, .. } | Statement::Yield { value, .. } => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Statement::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.statement(body);
+                if let Some(catch) = catch {
+                    self.statement(&catch.body);
+                }
+                if let Some(finally) = finally {
+                    self.statement(finally);
+                }
+            }
+            Statement::Match { subject, arms, .. } => {
+                self.expression(subject);
+                for arm in arms {
+                    self.condition(arm.span, 0);
+                    if let Some(guard) = &arm.guard {
+                        self.expression(guard);
+                    }
+                    self.statement(&arm.body);
+                }
+            }
+            Statement::Import { .. } | Statement::Break { .. } | Statement::Enum { .. } => {}
+            Statement::Block { statements, .. } => {
+                for stmt in statements {
+                    self.statement(stmt);
+                }
+            }
+            Statement::If {
+                conditional,
+                happy_path,
+                sad_path,
+                span,
+            } => {
+                self.condition(*span, 0);
+                self.expression(conditional);
+                self.statement(happy_path);
+                if let Some(sad_path) = sad_path {
+                    self.statement(sad_path);
+                }
+            }
+            Statement::While {
+                predicate,
+                happy_path,
+                span,
+            } => {
+                self.condition(*span, 0);
+                self.expression(predicate);
+                self.statement(happy_path);
+            }
+            Statement::ForIn {
+                iterable,
+                body,
+                span,
+                ..
+            } => {
+                self.condition(*span, 0);
+                self.expression(iterable);
+                self.statement(body);
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Logical { left, op, right } => {
+                self.condition(op.span(), 0);
+                self.condition(op.span(), 1);
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Binary { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Grouping { expression }
+            | Expr::Get {
+                object: expression, ..
+            } => self.expression(expression),
+            Expr::Unary { right, .. } => self.expression(right),
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+                span,
+            } => {
+                self.condition(*span, 0);
+                self.expression(condition);
+                self.expression(then_branch);
+                self.expression(else_branch);
+            }
+            Expr::Coalesce { left, right, span } => {
+                self.condition(*span, 0);
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Interpolation { parts } => {
+                for part in parts {
+                    self.expression(part);
+                }
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Expr::Range {
+                start, end, step, ..
+            } => {
+                self.expression(start);
+                self.expression(end);
+                if let Some(step) = step {
+                    self.expression(step);
+                }
+            }
+            Expr::Index { object, index } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            Expr::Function { body, .. } => {
+                for stmt in body {
+                    self.statement(stmt);
+                }
+            }
+            Expr::Assignment { value, .. } | Expr::Update { value, .. } => self.expression(value),
+            Expr::LiteralExpr { .. } | Expr::Var { .. } => {}
+        }
+    }
+
+    fn condition(&mut self, span: Span, operand: usize) {
+        self.conditions
+            .entry(Condition { span, operand })
+            .or_default();
+    }
+
+    /// The tracefile. Each condition is a block with a branch for true and
+    /// one for false; a condition that was never evaluated is shown as `-`.
+    pub fn lcov(&self) -> String {
+        let mut lcov = format!("TN:\nSF:{}\n", self.path);
+        let mut blocks: HashMap<usize, usize> = HashMap::new();
+        let mut branches_hit = 0;
+        for (condition, [falses, trues]) in &self.conditions {
+            let line = condition.span.line;
+            let block = blocks.entry(line).or_default();
+            for (branch, count) in [trues, falses].into_iter().enumerate() {
+                let taken = match falses + trues {
+                    0 => "-".to_string(),
+                    _ => count.to_string(),
+                };
+                if *count > 0 {
+                    branches_hit += 1;
+                }
+                lcov.push_str(&format!("BRDA:{},{},{},{}\n", line, block, branch, taken));
+            }
+            *block += 1;
+        }
+        lcov.push_str(&format!(
+            "BRF:{}\nBRH:{}\n",
+            self.conditions.len() * 2,
+            branches_hit
+        ));
+        for (line, hits) in &self.lines {
+            lcov.push_str(&format!("DA:{},{}\n", line, hits));
+        }
+        lcov.push_str(&format!(
+            "LF:{}\nLH:{}\nend_of_record\n",
+            self.lines.len(),
+            self.lines.values().filter(|hits| **hits > 0).count()
+        ));
+        lcov
+    }
+}
+
+impl Hook for Coverage {
+    fn before_statement(
+        &mut self,
+        stmt: &Statement,
+        _env: &Rc<RefCell<Environment>>,
+        _depth: usize,
+    ) -> Result<(), LoxErr> {
+        *self.lines.entry(stmt.span().line).or_default() += 1;
+        Ok(())
+    }
+
+    fn branch(&mut self, span: Span, operand: usize, outcome: bool) -> Result<(), LoxErr> {
+        let counts = self
+            .conditions
+            .entry(Condition { span, operand })
+            .or_default();
+        counts[outcome as usize] += 1;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Coverage;
+    use crate::parser::Parser;
+    use crate::{Interpreter, Scanner};
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_lines_and_branches() {
+        let source = "var i = 0;\n\
+                      while (i < 2) {\n\
+                      \x20 if (i == 0 or false) print i;\n\
+                      \x20 else print \"later\";\n\
+                      \x20 i = i + 1;\n\
+                      }\n";
+        let coverage = Rc::new(RefCell::new(Coverage::new("test.lox", source)));
+        let mut interpreter = Interpreter::new();
+        interpreter.add_hook(Box::new(coverage.clone()));
+        interpreter.set_output(Box::new(io::sink()));
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        interpreter.interpret(Parser::new(tokens).parse()).unwrap();
+
+        assert_eq!(
+            "TN:\nSF:test.lox\n\
+             BRDA:2,0,0,2\nBRDA:2,0,1,1\n\
+             BRDA:3,0,0,1\nBRDA:3,0,1,1\n\
+             BRDA:3,1,0,1\nBRDA:3,1,1,1\n\
+             BRDA:3,2,0,0\nBRDA:3,2,1,2\n\
+             BRF:8\nBRH:7\n\
+             DA:1,1\nDA:2,3\nDA:3,3\nDA:4,1\nDA:5,2\n\
+             LF:5\nLH:5\nend_of_record\n",
+            coverage.borrow().lcov()
+        );
+    }
+}
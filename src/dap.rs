@@ -0,0 +1,518 @@
+use crate::debugger::{describe, evaluate, Mode, Quit, StopReason, Stops};
+use crate::environment::Environment;
+use crate::function::Function;
+use crate::interpreter::Hook;
+use crate::json::{read_message, write_message, Json, Malformed};
+use crate::statement::Statement;
+use crate::{run, Interpreter, LoxErr};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::rc::Rc;
+
+const THREAD_ID: f64 = 1.0;
+
+/// Serves the Debug Adapter Protocol over stdin/stdout. Scripts only have
+/// one thread, with a stack frame for the script and one for each function
+/// being called.
+pub fn run_stdio() -> Result<(), LoxErr> {
+    let channel = Channel {
+        reader: Box::new(io::stdin().lock()),
+        writer: Box::new(io::stdout()),
+        seq: 0,
+    };
+    DebugAdapter::new(channel).run()
+}
+
+struct Channel {
+    reader: Box<dyn BufRead>,
+    writer: Box<dyn Write>,
+    seq: u64,
+}
+
+impl Channel {
+    /// The next request, answering any malformed messages before it with a
+    /// failed response.
+    fn read(&mut self) -> Result<Option<Json>, LoxErr> {
+        loop {
+            match read_message(&mut self.reader) {
+                Err(error) if error.is::<Malformed>() => {
+                    self.fail(&Json::Null, &error.to_string())?
+                }
+                result => return result,
+            }
+        }
+    }
+
+    fn send(&mut self, kind: &str, mut fields: Vec<(&str, Json)>) -> Result<(), LoxErr> {
+        self.seq += 1;
+        fields.insert(0, ("seq", Json::Number(self.seq as f64)));
+        fields.insert(1, ("type", Json::string(kind)));
+        write_message(&mut self.writer, &Json::object(fields))
+    }
+
+    fn respond(&mut self, request: &Json, body: Json) -> Result<(), LoxErr> {
+        self.send(
+            "response",
+            vec![
+                (
+                    "request_seq",
+                    request.get("seq").cloned().unwrap_or(Json::Null),
+                ),
+                ("success", Json::Bool(true)),
+                ("command", Json::string(command(request))),
+                ("body", body),
+            ],
+        )
+    }
+
+    fn fail(&mut self, request: &Json, message: &str) -> Result<(), LoxErr> {
+        self.send(
+            "response",
+            vec![
+                (
+                    "request_seq",
+                    request.get("seq").cloned().unwrap_or(Json::Null),
+                ),
+                ("success", Json::Bool(false)),
+                ("command", Json::string(command(request))),
+                ("message", Json::string(message)),
+            ],
+        )
+    }
+
+    fn event(&mut self, event: &str, body: Json) -> Result<(), LoxErr> {
+        self.send(
+            "event",
+            vec![("event", Json::string(event)), ("body", body)],
+        )
+    }
+}
+
+fn command(request: &Json) -> &str {
+    request
+        .get("command")
+        .and_then(|c| c.as_str())
+        .unwrap_or("")
+}
+
+fn arguments(request: &Json) -> &Json {
+    request.get("arguments").unwrap_or(&Json::Null)
+}
+
+fn breakpoint_lines(request: &Json) -> BTreeSet<usize> {
+    arguments(request)
+        .get("breakpoints")
+        .and_then(|b| b.as_array())
+        .map(|breakpoints| {
+            breakpoints
+                .iter()
+                .filter_map(|b| b.get("line").and_then(|l| l.as_f64()))
+                .map(|line| line as usize)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn set_breakpoints_body(lines: &BTreeSet<usize>) -> Json {
+    Json::object(vec![(
+        "breakpoints",
+        Json::Array(
+            lines
+                .iter()
+                .map(|line| {
+                    Json::object(vec![
+                        ("verified", Json::Bool(true)),
+                        ("line", Json::Number(*line as f64)),
+                    ])
+                })
+                .collect(),
+        ),
+    )])
+}
+
+fn threads_body() -> Json {
+    Json::object(vec![(
+        "threads",
+        Json::Array(vec![Json::object(vec![
+            ("id", Json::Number(THREAD_ID)),
+            ("name", Json::string("main")),
+        ])]),
+    )])
+}
+
+/// Sends everything the script prints to the client as `output` events,
+/// one event per line.
+struct EventOutput {
+    channel: Rc<RefCell<Channel>>,
+    pending: String,
+}
+
+impl EventOutput {
+    fn emit(&mut self, text: &str) -> io::Result<()> {
+        let body = Json::object(vec![
+            ("category", Json::string("stdout")),
+            ("output", Json::string(text)),
+        ]);
+        self.channel
+            .borrow_mut()
+            .event("output", body)
+            .map_err(|e| io::Error::other(e.to_string()))
+    }
+}
+
+impl Write for EventOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.pending.push_str(&String::from_utf8_lossy(buf));
+        while let Some(end) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=end).collect();
+            self.emit(&line)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if !self.pending.is_empty() {
+            let text = std::mem::take(&mut self.pending);
+            self.emit(&text)?;
+        }
+        Ok(())
+    }
+}
+
+struct DebugAdapter {
+    channel: Rc<RefCell<Channel>>,
+    program: Option<String>,
+    stop_on_entry: bool,
+    breakpoints: BTreeSet<usize>,
+}
+
+impl DebugAdapter {
+    fn new(channel: Channel) -> Self {
+        Self {
+            channel: Rc::new(RefCell::new(channel)),
+            program: None,
+            stop_on_entry: false,
+            breakpoints: BTreeSet::new(),
+        }
+    }
+
+    fn run(&mut self) -> Result<(), LoxErr> {
+        loop {
+            let request = match self.channel.borrow_mut().read()? {
+                Some(request) => request,
+                None => return Ok(()),
+            };
+            let mut channel = self.channel.borrow_mut();
+            match command(&request) {
+                "initialize" => {
+                    let capabilities = Json::object(vec![
+                        ("supportsConfigurationDoneRequest", Json::Bool(true)),
+                        ("supportsEvaluateForHovers", Json::Bool(true)),
+                    ]);
+                    channel.respond(&request, capabilities)?;
+                    channel.event("initialized", Json::object(vec![]))?;
+                }
+                "launch" => {
+                    let args = arguments(&request);
+                    self.program = args
+                        .get("program")
+                        .and_then(|p| p.as_str())
+                        .map(|p| p.to_string());
+                    self.stop_on_entry = args.get("stopOnEntry") == Some(&Json::Bool(true));
+                    match self.program {
+                        Some(_) => channel.respond(&request, Json::Null)?,
+                        None => channel.fail(&request, "launch needs a 'program' to run")?,
+                    }
+                }
+                "setBreakpoints" => {
+                    self.breakpoints = breakpoint_lines(&request);
+                    channel.respond(&request, set_breakpoints_body(&self.breakpoints))?;
+                }
+                "configurationDone" => {
+                    channel.respond(&request, Json::Null)?;
+                    drop(channel);
+                    if self.launch()? {
+                        return Ok(());
+                    }
+                }
+                "threads" => channel.respond(&request, threads_body())?,
+                "disconnect" => {
+                    channel.respond(&request, Json::Null)?;
+                    return Ok(());
+                }
+                _ => channel.fail(&request, "Not supported while the program is not paused")?,
+            }
+        }
+    }
+
+    /// Runs the launched program to completion. Returns true when the
+    /// client disconnected part way through.
+    fn launch(&mut self) -> Result<bool, LoxErr> {
+        let path = match &self.program {
+            Some(path) => path.clone(),
+            None => return Ok(false),
+        };
+        let mode = match self.stop_on_entry {
+            true => Mode::StepIn,
+            false => Mode::Continue,
+        };
+        let mut stops = Stops::new(mode);
+        stops.breakpoints = self.breakpoints.clone();
+        let hook = AdapterHook {
+            channel: self.channel.clone(),
+            stops,
+            frames: vec![Frame {
+                name: "main".to_string(),
+                path: path.clone(),
+                line: 1,
+            }],
+            entry: self.stop_on_entry,
+            scopes: vec![],
+        };
+
+        let mut interpreter = Interpreter::new();
+        interpreter.add_hook(Box::new(hook));
+        interpreter.set_output(Box::new(EventOutput {
+            channel: self.channel.clone(),
+            pending: String::new(),
+        }));
+        let result = interpreter
+            .set_script(Path::new(&path))
+            .and_then(|()| fs::read_to_string(&path).map_err(LoxErr::from))
+            .and_then(|contents| run(&mut interpreter, &contents));
+
+        let mut channel = self.channel.borrow_mut();
+        let exit_code = match result {
+            Err(e) if e.is::<Quit>() => return Ok(true),
+            Err(e) => {
+                let body = Json::object(vec![
+                    ("category", Json::string("stderr")),
+                    ("output", Json::String(format!("{}\n", e))),
+                ]);
+                channel.event("output", body)?;
+                1.0
+            }
+            Ok(()) => 0.0,
+        };
+        channel.event(
+            "exited",
+            Json::object(vec![("exitCode", Json::Number(exit_code))]),
+        )?;
+        channel.event("terminated", Json::object(vec![]))?;
+        Ok(false)
+    }
+}
+
+/// The script, or a function being called, as shown in the stack trace.
+struct Frame {
+    name: String,
+    path: String,
+    /// The line being run, or for an outer frame the line of its call.
+    line: usize,
+}
+
+impl Frame {
+    fn to_json(&self, id: usize) -> Json {
+        let name = Path::new(&self.path)
+            .file_name()
+            .map_or(self.path.clone(), |name| name.to_string_lossy().to_string());
+        Json::object(vec![
+            ("id", Json::Number(id as f64)),
+            ("name", Json::string(&self.name)),
+            (
+                "source",
+                Json::object(vec![
+                    ("name", Json::String(name)),
+                    ("path", Json::string(&self.path)),
+                ]),
+            ),
+            ("line", Json::Number(self.line as f64)),
+            ("column", Json::Number(1.0)),
+        ])
+    }
+}
+
+struct AdapterHook {
+    channel: Rc<RefCell<Channel>>,
+    stops: Stops,
+    /// The script's frame and then each function being called, innermost
+    /// last.
+    frames: Vec<Frame>,
+    entry: bool,
+    /// The environment chain at the current pause, innermost first.
+    /// `variablesReference` n refers to `scopes[n - 1]`.
+    scopes: Vec<Rc<RefCell<Environment>>>,
+}
+
+impl AdapterHook {
+    fn pause(&mut self, reason: &str, depth: usize) -> Result<(), LoxErr> {
+        let mut channel = self.channel.borrow_mut();
+        channel.event(
+            "stopped",
+            Json::object(vec![
+                ("reason", Json::string(reason)),
+                ("threadId", Json::Number(THREAD_ID)),
+                ("allThreadsStopped", Json::Bool(true)),
+            ]),
+        )?;
+        loop {
+            let request = match channel.read()? {
+                Some(request) => request,
+                None => return Err(Quit.into()),
+            };
+            let args = arguments(&request);
+            match command(&request) {
+                "threads" => channel.respond(&request, threads_body())?,
+                "stackTrace" => {
+                    // Frame ids count out from the innermost, which is 1.
+                    let frames = self
+                        .frames
+                        .iter()
+                        .rev()
+                        .enumerate()
+                        .map(|(index, frame)| frame.to_json(index + 1))
+                        .collect();
+                    let body = Json::object(vec![
+                        ("stackFrames", Json::Array(frames)),
+                        ("totalFrames", Json::Number(self.frames.len() as f64)),
+                    ]);
+                    channel.respond(&request, body)?;
+                }
+                "scopes" => {
+                    let last = self.scopes.len();
+                    let scopes = (1..=last)
+                        .map(|reference| {
+                            let name = match reference {
+                                r if r == last => "Globals".to_string(),
+                                1 => "Locals".to_string(),
+                                r => format!("Block {}", r - 1),
+                            };
+                            Json::object(vec![
+                                ("name", Json::String(name)),
+                                ("variablesReference", Json::Number(reference as f64)),
+                                ("expensive", Json::Bool(false)),
+                            ])
+                        })
+                        .collect();
+                    let body = Json::object(vec![("scopes", Json::Array(scopes))]);
+                    channel.respond(&request, body)?;
+                }
+                "variables" => {
+                    let reference = args
+                        .get("variablesReference")
+                        .and_then(|r| r.as_f64())
+                        .unwrap_or(0.0) as usize;
+                    let variables = match self.scopes.get(reference.wrapping_sub(1)) {
+                        Some(env) => {
+                            let env = env.borrow();
+                            let mut values: Vec<_> = env.values().collect();
+                            values.sort_by_key(|(name, _)| name.as_str());
+                            values
+                                .into_iter()
+                                .map(|(name, value)| {
+                                    Json::object(vec![
+                                        ("name", Json::string(name)),
+                                        ("value", Json::String(describe(value))),
+                                        ("variablesReference", Json::Number(0.0)),
+                                    ])
+                                })
+                                .collect()
+                        }
+                        None => vec![],
+                    };
+                    let body = Json::object(vec![("variables", Json::Array(variables))]);
+                    channel.respond(&request, body)?;
+                }
+                "evaluate" => {
+                    let expression = args
+                        .get("expression")
+                        .and_then(|e| e.as_str())
+                        .unwrap_or("");
+                    match evaluate(expression, &self.scopes[0]) {
+                        Ok(value) => {
+                            let body = Json::object(vec![
+                                ("result", Json::String(describe(&value))),
+                                ("variablesReference", Json::Number(0.0)),
+                            ]);
+                            channel.respond(&request, body)?;
+                        }
+                        Err(e) => channel.fail(&request, &e.to_string())?,
+                    }
+                }
+                "setBreakpoints" => {
+                    self.stops.breakpoints = breakpoint_lines(&request);
+                    channel.respond(&request, set_breakpoints_body(&self.stops.breakpoints))?;
+                }
+                "next" => {
+                    self.stops.mode = Mode::StepOver(depth);
+                    return channel.respond(&request, Json::Null);
+                }
+                "stepIn" => {
+                    self.stops.mode = Mode::StepIn;
+                    return channel.respond(&request, Json::Null);
+                }
+                "continue" => {
+                    self.stops.mode = Mode::Continue;
+                    let body = Json::object(vec![("allThreadsContinued", Json::Bool(true))]);
+                    return channel.respond(&request, body);
+                }
+                "disconnect" => {
+                    channel.respond(&request, Json::Null)?;
+                    return Err(Quit.into());
+                }
+                _ => channel.fail(&request, "Not supported")?,
+            }
+        }
+    }
+}
+
+impl Hook for AdapterHook {
+    fn before_statement(
+        &mut self,
+        stmt: &Statement,
+        env: &Rc<RefCell<Environment>>,
+        depth: usize,
+    ) -> Result<(), LoxErr> {
+        let line = stmt.span().line;
+        if let Some(frame) = self.frames.last_mut() {
+            frame.line = line;
+        }
+        let reason = match self.stops.check(line, depth) {
+            Some(_) if self.entry => "entry",
+            Some(StopReason::Step) => "step",
+            Some(StopReason::Breakpoint) => "breakpoint",
+            None => return Ok(()),
+        };
+        self.entry = false;
+        self.scopes.clear();
+        let mut scope = Some(env.clone());
+        while let Some(current) = scope {
+            scope = current.borrow().enclosing.clone();
+            self.scopes.push(current);
+        }
+        self.pause(reason, depth)
+    }
+
+    fn enter_function(&mut self, function: &Function) -> Result<(), LoxErr> {
+        let path = match &function.module {
+            Some(module) => module.display().to_string(),
+            None => self.frames[0].path.clone(),
+        };
+        self.frames.push(Frame {
+            name: format!("fn:{}", function.span.line),
+            path,
+            line: function.span.line,
+        });
+        Ok(())
+    }
+
+    fn leave_function(&mut self) -> Result<(), LoxErr> {
+        if self.frames.len() > 1 {
+            self.frames.pop();
+        }
+        Ok(())
+    }
+}
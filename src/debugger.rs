@@ -0,0 +1,281 @@
+use crate::environment::Environment;
+use crate::interpreter::{Hook, Interpreter};
+use crate::parser::Parser;
+use crate::statement::Statement;
+use crate::{LiteralValue, LoxErr, Scanner};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fmt::Display;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+const HELP: &str = "\
+commands:
+  step, s           run the next statement, entering blocks
+  next, n           run the next statement without stopping inside it
+  continue, c       run until the next breakpoint
+  break, b LINE     stop whenever LINE is reached
+  delete, d LINE    remove the breakpoint on LINE
+  print, p EXPR     evaluate EXPR in the current scope
+  env, e            show every scope from the innermost out
+  list, l           show the source around the current line
+  quit, q           stop the program";
+
+/// Returned from the hook when the user quits, so the interpreter unwinds
+/// without reporting a runtime error.
+#[derive(Debug)]
+pub struct Quit;
+
+impl Display for Quit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Stopped by the debugger")
+    }
+}
+
+impl Error for Quit {}
+
+pub enum Mode {
+    StepIn,
+    StepOver(usize),
+    Continue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    Step,
+    Breakpoint,
+}
+
+/// Decides where a debugger stops. Shared by the terminal debugger and the
+/// debug adapter, which only differ in how they talk to the user.
+pub struct Stops {
+    pub breakpoints: BTreeSet<usize>,
+    pub mode: Mode,
+    paused_line: Option<usize>,
+}
+
+impl Stops {
+    pub fn new(mode: Mode) -> Self {
+        Self {
+            breakpoints: BTreeSet::new(),
+            mode,
+            paused_line: None,
+        }
+    }
+
+    /// Called before each statement with its line and depth. Returns why
+    /// execution should pause there, if it should.
+    pub fn check(&mut self, line: usize, depth: usize) -> Option<StopReason> {
+        if self.paused_line != Some(line) {
+            self.paused_line = None;
+        }
+        let stepped = match self.mode {
+            Mode::StepIn => true,
+            Mode::StepOver(paused_depth) => depth <= paused_depth,
+            Mode::Continue => false,
+        };
+        // A breakpoint fires once per visit to its line, not once for every
+        // statement written on that line.
+        let hit = self.breakpoints.contains(&line) && self.paused_line.is_none();
+        if !stepped && !hit {
+            return None;
+        }
+        self.paused_line = Some(line);
+        match stepped {
+            true => Some(StopReason::Step),
+            false => Some(StopReason::Breakpoint),
+        }
+    }
+}
+
+/// A terminal debugger. It stops before the first statement, then whenever
+/// a breakpoint line is reached or a step finishes, and reads commands from
+/// `input` until told to carry on.
+pub struct Debugger<R: BufRead, W: Write> {
+    source: Vec<String>,
+    stops: Stops,
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> Debugger<R, W> {
+    pub fn new(source: &str, input: R, output: W) -> Self {
+        Self {
+            source: source.lines().map(|l| l.to_string()).collect(),
+            stops: Stops::new(Mode::StepIn),
+            input,
+            output,
+        }
+    }
+
+    fn source_line(&self, line: usize) -> &str {
+        self.source
+            .get(line.wrapping_sub(1))
+            .map(|l| l.trim())
+            .unwrap_or("")
+    }
+
+    fn pause(
+        &mut self,
+        line: usize,
+        env: &Rc<RefCell<Environment>>,
+        depth: usize,
+    ) -> Result<(), LoxErr> {
+        let text = self.source_line(line).to_string();
+        writeln!(self.output, "stopped at line {}: {}", line, text)?;
+        loop {
+            write!(self.output, "(debug) ")?;
+            self.output.flush()?;
+            let mut command = String::new();
+            if self.input.read_line(&mut command)? == 0 {
+                return Err(Quit.into());
+            }
+            let command = command.trim();
+            let (name, argument) = command.split_once(' ').unwrap_or((command, ""));
+            let argument = argument.trim();
+            match name {
+                "s" | "step" => {
+                    self.stops.mode = Mode::StepIn;
+                    return Ok(());
+                }
+                "n" | "next" => {
+                    self.stops.mode = Mode::StepOver(depth);
+                    return Ok(());
+                }
+                "c" | "continue" => {
+                    self.stops.mode = Mode::Continue;
+                    return Ok(());
+                }
+                "q" | "quit" => return Err(Quit.into()),
+                "b" | "break" => match argument.parse::<usize>() {
+                    Ok(line) => {
+                        self.stops.breakpoints.insert(line);
+                        writeln!(self.output, "breakpoint set at line {}", line)?;
+                    }
+                    Err(_) => writeln!(self.output, "usage: break LINE")?,
+                },
+                "d" | "delete" => match argument.parse::<usize>() {
+                    Ok(line) if self.stops.breakpoints.remove(&line) => {
+                        writeln!(self.output, "breakpoint removed from line {}", line)?
+                    }
+                    Ok(line) => writeln!(self.output, "no breakpoint at line {}", line)?,
+                    Err(_) => writeln!(self.output, "usage: delete LINE")?,
+                },
+                "p" | "print" => match evaluate(argument, env) {
+                    Ok(value) => writeln!(self.output, "{}", value.to_string())?,
+                    Err(e) => writeln!(self.output, "error: {}", e)?,
+                },
+                "e" | "env" => self.dump_env(env)?,
+                "l" | "list" => {
+                    let first = line.saturating_sub(3).max(1);
+                    for number in first..=(line + 3).min(self.source.len()) {
+                        let marker = if number == line { "->" } else { "  " };
+                        let text = &self.source[number - 1];
+                        writeln!(self.output, "{} {:>4} {}", marker, number, text)?;
+                    }
+                }
+                "h" | "help" => writeln!(self.output, "{}", HELP)?,
+                "" => {}
+                _ => writeln!(self.output, "unknown command '{}', try 'help'", name)?,
+            }
+        }
+    }
+
+    fn dump_env(&mut self, env: &Rc<RefCell<Environment>>) -> Result<(), LoxErr> {
+        let mut scope = Some(env.clone());
+        let mut level = 0;
+        while let Some(current) = scope {
+            let current = current.borrow();
+            match current.enclosing {
+                Some(_) => writeln!(self.output, "scope {}:", level)?,
+                None => writeln!(self.output, "globals:")?,
+            }
+            let mut values: Vec<(&String, &LiteralValue)> = current.values().collect();
+            values.sort_by_key(|(name, _)| name.as_str());
+            for (name, value) in values {
+                writeln!(self.output, "  {} = {}", name, describe(value))?;
+            }
+            scope = current.enclosing.clone();
+            level += 1;
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead, W: Write> Hook for Debugger<R, W> {
+    fn before_statement(
+        &mut self,
+        stmt: &Statement,
+        env: &Rc<RefCell<Environment>>,
+        depth: usize,
+    ) -> Result<(), LoxErr> {
+        let line = stmt.span().line;
+        if self.stops.check(line, depth).is_some() {
+            self.pause(line, env, depth)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parses and evaluates `source` as an expression in `env`.
+pub fn evaluate(source: &str, env: &Rc<RefCell<Environment>>) -> Result<LiteralValue, LoxErr> {
+    let tokens = Scanner::new(source).scan_tokens()?;
+    let mut expr = Parser::new(tokens).parse_expression()?;
+    expr.evaluate(&mut Interpreter::with_env(env.clone()))
+}
+
+/// Formats a value for display, quoting strings so they stand out.
+pub fn describe(value: &LiteralValue) -> String {
+    match value {
+        LiteralValue::StringValue(s) => format!("{:?}", s),
+        value => value.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Debugger;
+    use crate::parser::Parser;
+    use crate::testing::Output;
+    use crate::{Interpreter, Scanner};
+    use std::io::Cursor;
+
+    fn session(source: &str, commands: &str) -> String {
+        let output = Output::default();
+        let debugger = Debugger::new(source, Cursor::new(commands.to_string()), output.clone());
+        let mut interpreter = Interpreter::new();
+        interpreter.add_hook(Box::new(debugger));
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse();
+        interpreter.interpret(statements).unwrap();
+        output.text()
+    }
+
+    #[test]
+    fn test_breakpoint_print_and_env() {
+        let source = "var a = 1;\n{\n  var b = a + 1;\n  b = b * 10;\n}\nvar c = 3;\n";
+        let output = session(source, "break 4\ncontinue\np b + a\nenv\ncontinue\n");
+        assert_eq!(
+            "stopped at line 1: var a = 1;\n\
+             (debug) breakpoint set at line 4\n\
+             (debug) stopped at line 4: b = b * 10;\n\
+             (debug) 3\n\
+             (debug) scope 0:\n  b = 2\nglobals:\n  a = 1\n\
+             (debug) ",
+            output
+        );
+    }
+
+    #[test]
+    fn test_step_over_and_in() {
+        let source = "var a = 1;\n{\n  a = 2;\n}\nvar c = 3;\n";
+        let output = session(source, "next\nnext\nnext\ncontinue\n");
+        assert!(output.contains("stopped at line 2: {\n"));
+        assert!(output.contains("stopped at line 5: var c = 3;\n"));
+        assert!(!output.contains("line 3"));
+
+        let output = session(source, "step\nstep\ncontinue\n");
+        assert!(output.contains("stopped at line 3: a = 2;\n"));
+    }
+}
@@ -0,0 +1,58 @@
+use crate::LiteralValue;
+use std::rc::Rc;
+
+/// One variant of an enum, shared by the enum and every value made from it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tag {
+    pub enum_name: String,
+    pub variant: String,
+    /// The names of the payload fields, empty for a plain variant.
+    pub fields: Vec<String>,
+}
+
+impl Tag {
+    /// `Name.Variant`, as values of the variant are written.
+    pub fn path(&self) -> String {
+        format!("{}.{}", self.enum_name, self.variant)
+    }
+}
+
+/// An `enum` declaration as a value. `Name.Variant` reads a plain variant's
+/// value, or the constructor of one with a payload.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnumType {
+    pub name: String,
+    pub tags: Vec<Rc<Tag>>,
+}
+
+impl EnumType {
+    pub fn variant(&self, name: &str) -> Option<LiteralValue> {
+        let tag = self.tags.iter().find(|tag| tag.variant == name)?;
+        Some(match tag.fields.is_empty() {
+            true => LiteralValue::Variant {
+                tag: tag.clone(),
+                payload: vec![],
+            },
+            false => LiteralValue::Constructor(tag.clone()),
+        })
+    }
+}
+
+/// Whether `value` is `of`: a variant of the enum `of`, or of the same
+/// variant as the constructor or plain variant `of`. `None` if `of` is not
+/// an enum or variant.
+pub fn is(value: &LiteralValue, of: &LiteralValue) -> Option<bool> {
+    let tag = match value {
+        LiteralValue::Variant { tag, .. } => Some(tag),
+        _ => None,
+    };
+    match of {
+        LiteralValue::Enum(enum_type) => {
+            Some(tag.is_some_and(|tag| tag.enum_name == enum_type.name))
+        }
+        LiteralValue::Constructor(of) | LiteralValue::Variant { tag: of, .. } => {
+            Some(tag.is_some_and(|tag| tag == of))
+        }
+        _ => None,
+    }
+}
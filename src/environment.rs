@@ -1,4 +1,5 @@
-use crate::{LiteralValue, LoxErr};
+use crate::scanner::Span;
+use crate::{LiteralValue, LoxErr, SyntaxError};
 use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
@@ -6,6 +7,8 @@ use std::rc::Rc;
 #[derive(Debug, Clone)]
 pub struct Environment {
     values: HashMap<String, LiteralValue>,
+    /// Where each `const` binding in this scope was declared.
+    constants: HashMap<String, Span>,
     pub enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
@@ -13,14 +16,31 @@ impl Environment {
     pub fn new() -> Self {
         Self {
             values: HashMap::new(),
+            constants: HashMap::new(),
             enclosing: None,
         }
     }
 
     pub fn define(&mut self, name: String, literal: LiteralValue) {
+        self.constants.remove(&name);
         self.values.insert(name, literal);
     }
 
+    pub fn define_constant(&mut self, name: String, literal: LiteralValue, span: Span) {
+        self.constants.insert(name.clone(), span);
+        self.values.insert(name, literal);
+    }
+
+    /// Where `name` was declared if it is a constant of this scope, rather
+    /// than of an enclosing one.
+    pub fn constant(&self, name: &str) -> Option<Span> {
+        self.constants.get(name).copied()
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = (&String, &LiteralValue)> {
+        self.values.iter()
+    }
+
     pub fn get(&self, key: String) -> Result<LiteralValue, LoxErr> {
         let fetched_val = self.values.get(&key);
         match (fetched_val, &self.enclosing) {
@@ -30,15 +50,39 @@ impl Environment {
         }
     }
 
-    pub fn assign(&mut self, name: &str, value: LiteralValue) -> bool {
+    /// Returns whether the variable was found, or where it was declared if
+    /// it is a constant.
+    pub fn assign(&mut self, name: &str, value: LiteralValue) -> Result<bool, Span> {
+        if let Some(declared) = self.constants.get(name) {
+            return Err(*declared);
+        }
         let old_value = self.values.get(name);
         match (old_value, &mut self.enclosing) {
             (Some(_), _) => {
                 self.values.insert(name.to_string(), value);
-                true
+                Ok(true)
             }
             (None, Some(env)) => (*env).borrow_mut().assign(name, value),
-            (None, None) => false,
+            (None, None) => Ok(false),
         }
     }
 }
+
+/// The error for assigning to the constant `name` at `span`.
+pub fn constant_error(name: &str, declared: Span, span: Span) -> LoxErr {
+    let message = format!(
+        "Cannot assign to constant '{}' declared at [line {}]",
+        name, declared
+    );
+    SyntaxError::new(message, span).into()
+}
+
+/// The error for declaring `name` at `span` in a scope that already has a
+/// constant of that name.
+pub fn redeclare_error(name: &str, declared: Span, span: Span) -> LoxErr {
+    let message = format!(
+        "Cannot redeclare constant '{}' declared at [line {}]",
+        name, declared
+    );
+    SyntaxError::new(message, span).into()
+}
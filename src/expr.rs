@@ -1,8 +1,15 @@
-use crate::{environment::Environment, scanner::*, LoxErr};
-use std::cell::RefCell;
+use crate::enums;
+use crate::environment::constant_error;
+use crate::function::Function;
+use crate::integer::{Integer, MAX_BITS};
+use crate::range::{self, Range};
+use crate::statement::Statement;
+use crate::types::Type;
+use crate::{interpreter::Interpreter, scanner::*, LoxErr};
 use std::rc::Rc;
 
 #[derive(Debug, Clone)]
+#[allow(clippy::enum_variant_names)]
 pub enum Expr {
     Binary {
         left: Box<Expr>,
@@ -21,49 +28,192 @@ pub enum Expr {
     },
     Var {
         identifier: String,
+        span: Span,
     },
     Assignment {
         name: String,
         value: Box<Expr>,
+        span: Span,
     },
     Logical {
         left: Box<Expr>,
         op: Token,
         right: Box<Expr>,
     },
+    /// `name += value` and the other compound assignments, where `op` is
+    /// the plain operator, and `++`/`--`, which add or subtract 1. A postfix
+    /// update evaluates to the value from before it.
+    Update {
+        name: String,
+        op: Token,
+        value: Box<Expr>,
+        postfix: bool,
+        span: Span,
+    },
+    /// `condition ? then_branch : else_branch`, evaluating only the branch
+    /// the condition picks. The span is the `?`.
+    Conditional {
+        condition: Box<Expr>,
+        then_branch: Box<Expr>,
+        else_branch: Box<Expr>,
+        span: Span,
+    },
+    /// `left ?? right`, evaluating `right` only when `left` is nil. The span
+    /// is the `??`.
+    Coalesce {
+        left: Box<Expr>,
+        right: Box<Expr>,
+        span: Span,
+    },
+    /// `callee(arguments)`. The span is the `(`.
+    Call {
+        callee: Box<Expr>,
+        arguments: Vec<Expr>,
+        span: Span,
+    },
+    /// `object.name`, reading an export of a module, a variant of an enum or
+    /// a field of an enum value. The span is the name's.
+    Get {
+        object: Box<Expr>,
+        name: String,
+        span: Span,
+    },
+    /// `start..end`, or `start..=end` when `inclusive`, optionally followed
+    /// by `by step`.
+    Range {
+        start: Box<Expr>,
+        end: Box<Expr>,
+        step: Option<Box<Expr>>,
+        inclusive: bool,
+    },
+    /// `object[index]`, a character of a string or, for a range index, the
+    /// characters at each of its values.
+    Index {
+        object: Box<Expr>,
+        index: Box<Expr>,
+    },
+    /// `fun (params) { body }`, or `(params) => value` whose body returns
+    /// `value`. A body with a `yield` makes it a generator. The span is the
+    /// `fun` or the `(`.
+    Function {
+        params: Vec<Token>,
+        body: Vec<Statement>,
+        generator: bool,
+        span: Span,
+        /// The `: type` after each parameter, which only `muslox check`
+        /// looks at.
+        annotations: Vec<Option<Type>>,
+        /// The `: type` after the parameters, for what the function returns.
+        returns: Option<Type>,
+    },
+    /// A string literal with embedded expressions, as the literal text and
+    /// expressions in the order they appear.
+    Interpolation {
+        parts: Vec<Expr>,
+    },
 }
 
 impl Expr {
+    #[allow(clippy::inherent_to_string)]
     pub fn to_string(&self) -> String {
         match self {
             Expr::Logical { left, op, right } => {
-                return format!("{} {} {}", op.lexeme, left.to_string(), right.to_string())
+                format!("{} {} {}", op.lexeme, left.to_string(), right.to_string())
             }
             Expr::Binary { left, op, right } => {
-                return format!("({} {} {})", op.lexeme, left.to_string(), right.to_string())
+                format!("({} {} {})", op.lexeme, left.to_string(), right.to_string())
             }
-            Expr::Grouping { expression } => return format!("(group {})", expression.to_string()),
-            Expr::LiteralExpr { literal } => return format!("{}", literal.to_string()),
+            Expr::Grouping { expression } => format!("(group {})", expression.to_string()),
+            Expr::LiteralExpr { literal } => literal.to_string(),
             Expr::Unary { operator, right } => {
-                return format!("({} {})", operator.lexeme, right.to_string())
+                format!("({} {})", operator.lexeme, right.to_string())
+            }
+            Expr::Var { identifier, .. } => {
+                format!("var {} ", identifier)
+            }
+            Expr::Assignment { name, value, .. } => {
+                format!("var {} = {}", name, value.to_string())
+            }
+            Expr::Update {
+                name,
+                op,
+                value,
+                postfix,
+                ..
+            } => {
+                let update = format!("var {} {}= {}", name, op.lexeme, value.to_string());
+                match postfix {
+                    true => format!("(postfix {})", update),
+                    false => update,
+                }
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                format!(
+                    "(? {} {} {})",
+                    condition.to_string(),
+                    then_branch.to_string(),
+                    else_branch.to_string()
+                )
+            }
+            Expr::Coalesce { left, right, .. } => {
+                format!("(?? {} {})", left.to_string(), right.to_string())
             }
-            Expr::Var { identifier } => {
-                return format!("var {} ", identifier);
+            Expr::Get { object, name, .. } => {
+                format!("(. {} {})", object.to_string(), name)
             }
-            Expr::Assignment { name, value } => {
-                return format!("var {} = {}", name, value.to_string())
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                let arguments: Vec<String> = arguments.iter().map(|a| a.to_string()).collect();
+                format!("(call {} {})", callee.to_string(), arguments.join(" "))
+            }
+            Expr::Range {
+                start,
+                end,
+                step,
+                inclusive,
+            } => {
+                let op = if *inclusive { "..=" } else { ".." };
+                let range = format!("({} {} {})", op, start.to_string(), end.to_string());
+                match step {
+                    Some(step) => format!("(by {} {})", range, step.to_string()),
+                    None => range,
+                }
+            }
+            Expr::Index { object, index } => {
+                format!("([] {} {})", object.to_string(), index.to_string())
+            }
+            Expr::Function { params, .. } => {
+                let params: Vec<&str> = params.iter().map(|p| p.lexeme.as_str()).collect();
+                format!("(fun {})", params.join(" "))
+            }
+            Expr::Interpolation { parts } => {
+                let parts: Vec<String> = parts.iter().map(|p| p.to_string()).collect();
+                format!("(interpolate {})", parts.join(" "))
             }
         }
     }
 
-    pub fn evaluate(
-        &mut self,
-        environment: Rc<RefCell<Environment>>,
-    ) -> Result<LiteralValue, LoxErr> {
+    pub fn evaluate(&mut self, interpreter: &mut Interpreter) -> Result<LiteralValue, LoxErr> {
+        interpreter.step()?;
+        interpreter.enter()?;
+        let result = self.evaluate_nested(interpreter);
+        interpreter.leave();
+        result
+    }
+
+    fn evaluate_nested(&mut self, interpreter: &mut Interpreter) -> Result<LiteralValue, LoxErr> {
         match self {
             Expr::Logical { left, op, right } => {
-                let left = left.evaluate(environment.clone())?;
-                let right = right.evaluate(environment)?;
+                let left = boolean(op, left.evaluate(interpreter)?)?;
+                interpreter.branch(op.span(), 0, left.to_boolean())?;
+                let right = boolean(op, right.evaluate(interpreter)?)?;
+                interpreter.branch(op.span(), 1, right.to_boolean())?;
 
                 match op.token_type {
                     TokenType::AND => {
@@ -83,183 +233,387 @@ impl Expr {
                     _ => Err("Invalid token type for op".into()),
                 }
             }
-            Expr::Assignment { name, value } => {
-                let value = value.evaluate(environment.clone())?;
-                let assign_success = (*environment).borrow_mut().assign(name, value.clone());
+            Expr::Assignment { name, value, span } => {
+                let value = value.evaluate(interpreter)?;
+                let assign_success = (*interpreter.env())
+                    .borrow_mut()
+                    .assign(name, value.clone());
                 match assign_success {
-                    true => return Ok(value),
-                    false => return Err(format!("Variable {} has not been declared", name).into()),
+                    Ok(true) => Ok(value),
+                    Ok(false) => Err(format!("Variable {} has not been declared", name).into()),
+                    Err(declared) => Err(constant_error(name, declared, *span)),
                 }
             }
-            Expr::Var { identifier } => match (*environment).borrow().get(identifier.to_string()) {
-                Ok(ident) => Ok(ident.clone()),
-                Err(_) => {
-                    let error = format!("Undefined Var {}", identifier);
-                    Err(error.into())
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+                span,
+            } => match condition.evaluate(interpreter)? {
+                LiteralValue::True => {
+                    interpreter.branch(*span, 0, true)?;
+                    then_branch.evaluate(interpreter)
                 }
+                LiteralValue::False => {
+                    interpreter.branch(*span, 0, false)?;
+                    else_branch.evaluate(interpreter)
+                }
+                _ => Err("Condition of '?' must be true or false".into()),
             },
+            Expr::Coalesce { left, right, span } => {
+                let left = left.evaluate(interpreter)?;
+                // The branch is taken when the right side is used.
+                interpreter.branch(*span, 0, left == LiteralValue::Nil)?;
+                match left {
+                    LiteralValue::Nil => right.evaluate(interpreter),
+                    left => Ok(left),
+                }
+            }
+            Expr::Get { object, name, .. } => property(object.evaluate(interpreter)?, name),
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                let callee = callee.evaluate(interpreter)?;
+                let mut values = vec![];
+                for argument in arguments {
+                    values.push(argument.evaluate(interpreter)?);
+                }
+                call(callee, values, interpreter)
+            }
+            Expr::Range {
+                start,
+                end,
+                step,
+                inclusive,
+            } => {
+                let start = start.evaluate(interpreter)?;
+                let end = end.evaluate(interpreter)?;
+                let step = match step {
+                    Some(step) => Some(step.evaluate(interpreter)?),
+                    None => None,
+                };
+                let range = Range::new(start, end, step, *inclusive)?;
+                Ok(LiteralValue::Range(Rc::new(range)))
+            }
+            Expr::Index { object, index } => {
+                let object = object.evaluate(interpreter)?;
+                let index = index.evaluate(interpreter)?;
+                match object {
+                    LiteralValue::StringValue(s) => string_index(&s, &index),
+                    value => Err(
+                        format!("Only strings can be indexed, not {}", value.type_name()).into(),
+                    ),
+                }
+            }
+            Expr::Function {
+                params,
+                body,
+                generator,
+                span,
+                ..
+            } => Ok(LiteralValue::Function(Rc::new(Function {
+                params: params.iter().map(|p| p.lexeme.clone()).collect(),
+                body: body.clone(),
+                generator: *generator,
+                closure: interpreter.env(),
+                module: interpreter.module(),
+                span: *span,
+            }))),
+            Expr::Update {
+                name,
+                op,
+                value,
+                postfix,
+                span,
+            } => {
+                let env = interpreter.env();
+                let old = match env.borrow().get(name.to_string()) {
+                    Ok(old) => old,
+                    Err(_) => return Err(format!("Variable {} has not been declared", name).into()),
+                };
+                let value = value.evaluate(interpreter)?;
+                let new = Expr::binary(op, old.clone(), value, interpreter)?;
+                match env.borrow_mut().assign(name, new.clone()) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        return Err(format!("Variable {} has not been declared", name).into())
+                    }
+                    Err(declared) => return Err(constant_error(name, declared, *span)),
+                }
+                match postfix {
+                    true => Ok(old),
+                    false => Ok(new),
+                }
+            }
+            Expr::Var { identifier, .. } => {
+                match (*interpreter.env()).borrow().get(identifier.to_string()) {
+                    Ok(ident) => Ok(ident.clone()),
+                    Err(_) => {
+                        let error = format!("Undefined Var {}", identifier);
+                        Err(error.into())
+                    }
+                }
+            }
             Expr::LiteralExpr { literal } => Ok(literal.clone()),
-            Expr::Grouping { expression } => expression.evaluate(environment),
+            Expr::Interpolation { parts } => {
+                let mut text = String::new();
+                for part in parts {
+                    let value = part.evaluate(interpreter)?.to_string();
+                    interpreter.check_string(text.len() + value.len())?;
+                    text.push_str(&value);
+                }
+                Ok(LiteralValue::StringValue(text))
+            }
+            Expr::Grouping { expression } => expression.evaluate(interpreter),
             Expr::Unary { operator, right } => {
-                let right = right.evaluate(environment)?;
+                let right = right.evaluate(interpreter)?;
                 match (right.clone(), operator.token_type.clone()) {
-                    (LiteralValue::FValue(x), TokenType::MINUS) => {
-                        return Ok(LiteralValue::FValue(-1.0 * x));
+                    (LiteralValue::FValue(x), TokenType::MINUS) => Ok(LiteralValue::FValue(-x)),
+                    (LiteralValue::IValue(x), TokenType::MINUS) => {
+                        Ok(LiteralValue::IValue(x.neg()))
+                    }
+                    (LiteralValue::IValue(x), TokenType::TILDE) => {
+                        Ok(LiteralValue::IValue(x.not()))
                     }
+                    (_, TokenType::TILDE) => Err("Operand of '~' must be an integer".into()),
                     (_, TokenType::MINUS) => Err("Unable to negate this expression".into()),
                     (any, TokenType::BANG) => Ok(self.is_falsy(any)),
                     _ => panic!("Should not get to this point"),
                 }
             }
             Expr::Binary { left, op, right } => {
-                let left = left.evaluate(environment.clone())?;
-                let right = right.evaluate(environment)?;
+                let left = left.evaluate(interpreter)?;
+                let right = right.evaluate(interpreter)?;
 
-                match (left, right, op.token_type.clone()) {
-                    (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::PLUS) => {
-                        return Ok(LiteralValue::FValue(x + y));
-                    }
-                    (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::MINUS) => {
-                        return Ok(LiteralValue::FValue(x - y));
-                    }
-                    (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::SLASH) => {
-                        return Ok(LiteralValue::FValue(x / y));
-                    }
-                    (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::STAR) => {
-                        return Ok(LiteralValue::FValue(x * y));
-                    }
-                    (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::GREATER) => {
-                        let bool = x > y;
-                        match bool {
-                            true => Ok(LiteralValue::True),
-                            false => Ok(LiteralValue::False),
-                        }
-                    }
-                    (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::GREATEREQUAL) => {
-                        let bool = x >= y;
-                        match bool {
-                            true => Ok(LiteralValue::True),
-                            false => Ok(LiteralValue::False),
-                        }
-                    }
-                    (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::LESS) => {
-                        let bool = x < y;
-                        match bool {
-                            true => Ok(LiteralValue::True),
-                            false => Ok(LiteralValue::False),
-                        }
-                    }
-                    (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::LESSEQUAL) => {
-                        let bool = x <= y;
-                        match bool {
-                            true => Ok(LiteralValue::True),
-                            false => Ok(LiteralValue::False),
-                        }
-                    }
-                    (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::EQUALEQUAL) => {
-                        let boolean_res = x == y;
-                        if boolean_res {
-                            return Ok(LiteralValue::True);
-                        }
-                        Ok(LiteralValue::False)
-                    }
-                    (
-                        LiteralValue::StringValue(x),
-                        LiteralValue::StringValue(y),
-                        TokenType::EQUALEQUAL,
-                    ) => {
-                        let boolean_res = x == y;
-                        if boolean_res {
-                            return Ok(LiteralValue::True);
-                        }
-                        Ok(LiteralValue::False)
-                    }
-                    (
-                        LiteralValue::StringValue(x),
-                        LiteralValue::StringValue(y),
-                        TokenType::BANGEQUAL,
-                    ) => {
-                        let boolean_res = x != y;
-                        if boolean_res {
-                            return Ok(LiteralValue::True);
-                        }
-                        Ok(LiteralValue::False)
-                    }
-                    (LiteralValue::StringValue(x), LiteralValue::FValue(y), TokenType::STAR) => {
-                        let mut concat = String::new();
+                Expr::binary(op, left, right, interpreter)
+            }
+        }
+    }
 
-                        for _ in 0..y as usize {
-                            concat.push_str(&x);
-                        }
+    /// Applies a binary operator to values that have already been
+    /// evaluated.
+    fn binary(
+        op: &Token,
+        left: LiteralValue,
+        right: LiteralValue,
+        interpreter: &mut Interpreter,
+    ) -> Result<LiteralValue, LoxErr> {
+        if op.token_type == TokenType::IS {
+            return match enums::is(&left, &right) {
+                Some(true) => Ok(LiteralValue::True),
+                Some(false) => Ok(LiteralValue::False),
+                None => Err(format!(
+                    "Right side of 'is' must be an enum or variant, not {}",
+                    right.type_name()
+                )
+                .into()),
+            };
+        }
+        if op.token_type == TokenType::IN {
+            return match range::contains(&left, &right)? {
+                true => Ok(LiteralValue::True),
+                false => Ok(LiteralValue::False),
+            };
+        }
+        // An integer meeting a float becomes a float, so `1 + 0.5`
+        // is `1.5` and `1 == 1.0` holds.
+        let (left, right) = match (left, right) {
+            (LiteralValue::IValue(x), LiteralValue::FValue(y)) => {
+                (LiteralValue::FValue(x.to_f64()), LiteralValue::FValue(y))
+            }
+            (LiteralValue::FValue(x), LiteralValue::IValue(y)) => {
+                (LiteralValue::FValue(x), LiteralValue::FValue(y.to_f64()))
+            }
+            operands => operands,
+        };
 
-                        Ok(LiteralValue::StringValue(concat))
-                    }
-                    (
-                        LiteralValue::StringValue(x),
-                        LiteralValue::StringValue(y),
-                        TokenType::LESS,
-                    ) => {
-                        let bool = x < y;
-                        match bool {
-                            true => Ok(LiteralValue::True),
-                            false => Ok(LiteralValue::False),
-                        }
-                    }
-                    (
-                        LiteralValue::StringValue(x),
-                        LiteralValue::StringValue(y),
-                        TokenType::GREATER,
-                    ) => {
-                        let bool = x > y;
-                        match bool {
-                            true => Ok(LiteralValue::True),
-                            false => Ok(LiteralValue::False),
-                        }
-                    }
-                    (
-                        LiteralValue::StringValue(x),
-                        LiteralValue::StringValue(y),
-                        TokenType::GREATEREQUAL,
-                    ) => {
-                        let bool = x >= y;
-                        match bool {
-                            true => Ok(LiteralValue::True),
-                            false => Ok(LiteralValue::False),
-                        }
-                    }
-                    (
-                        LiteralValue::StringValue(x),
-                        LiteralValue::StringValue(y),
-                        TokenType::LESSEQUAL,
-                    ) => {
-                        let bool = x <= y;
-                        match bool {
-                            true => Ok(LiteralValue::True),
-                            false => Ok(LiteralValue::False),
-                        }
-                    }
-                    (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::BANGEQUAL) => {
-                        let bool = x != y;
-                        match bool {
-                            true => Ok(LiteralValue::True),
-                            false => Ok(LiteralValue::False),
-                        }
-                    }
-                    (
-                        LiteralValue::StringValue(x),
-                        LiteralValue::StringValue(y),
-                        TokenType::PLUS,
-                    ) => {
-                        let mut concat = String::new();
+        match (left, right, op.token_type.clone()) {
+            (LiteralValue::IValue(x), LiteralValue::IValue(y), TokenType::PLUS) => {
+                Ok(LiteralValue::IValue(x.add(&y)))
+            }
+            (LiteralValue::IValue(x), LiteralValue::IValue(y), TokenType::MINUS) => {
+                Ok(LiteralValue::IValue(x.sub(&y)))
+            }
+            (LiteralValue::IValue(x), LiteralValue::IValue(y), TokenType::STAR) => {
+                check_bits(op, (x.bits() + y.bits()) as f64)?;
+                Ok(LiteralValue::IValue(x.mul(&y)))
+            }
+            // Dividing always gives a float, so `10 / 4` is `2.5`. `div` is
+            // the integer division.
+            (LiteralValue::IValue(x), LiteralValue::IValue(y), TokenType::SLASH) => {
+                Ok(LiteralValue::FValue(x.to_f64() / y.to_f64()))
+            }
+            (
+                LiteralValue::IValue(x),
+                LiteralValue::IValue(y),
+                op @ (TokenType::GREATER
+                | TokenType::GREATEREQUAL
+                | TokenType::LESS
+                | TokenType::LESSEQUAL
+                | TokenType::EQUALEQUAL
+                | TokenType::BANGEQUAL),
+            ) => {
+                let ordering = x.cmp(&y);
+                let bool = match op {
+                    TokenType::GREATER => ordering.is_gt(),
+                    TokenType::GREATEREQUAL => ordering.is_ge(),
+                    TokenType::LESS => ordering.is_lt(),
+                    TokenType::LESSEQUAL => ordering.is_le(),
+                    TokenType::EQUALEQUAL => ordering.is_eq(),
+                    _ => ordering.is_ne(),
+                };
+                match bool {
+                    true => Ok(LiteralValue::True),
+                    false => Ok(LiteralValue::False),
+                }
+            }
+            (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::PLUS) => {
+                Ok(LiteralValue::FValue(x + y))
+            }
+            (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::MINUS) => {
+                Ok(LiteralValue::FValue(x - y))
+            }
+            (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::SLASH) => {
+                Ok(LiteralValue::FValue(x / y))
+            }
+            (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::STAR) => {
+                Ok(LiteralValue::FValue(x * y))
+            }
+            (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::GREATER) => {
+                let bool = x > y;
+                match bool {
+                    true => Ok(LiteralValue::True),
+                    false => Ok(LiteralValue::False),
+                }
+            }
+            (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::GREATEREQUAL) => {
+                let bool = x >= y;
+                match bool {
+                    true => Ok(LiteralValue::True),
+                    false => Ok(LiteralValue::False),
+                }
+            }
+            (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::LESS) => {
+                let bool = x < y;
+                match bool {
+                    true => Ok(LiteralValue::True),
+                    false => Ok(LiteralValue::False),
+                }
+            }
+            (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::LESSEQUAL) => {
+                let bool = x <= y;
+                match bool {
+                    true => Ok(LiteralValue::True),
+                    false => Ok(LiteralValue::False),
+                }
+            }
+            (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::EQUALEQUAL) => {
+                let boolean_res = x == y;
+                if boolean_res {
+                    return Ok(LiteralValue::True);
+                }
+                Ok(LiteralValue::False)
+            }
+            (LiteralValue::StringValue(x), LiteralValue::StringValue(y), TokenType::EQUALEQUAL) => {
+                let boolean_res = x == y;
+                if boolean_res {
+                    return Ok(LiteralValue::True);
+                }
+                Ok(LiteralValue::False)
+            }
+            (LiteralValue::StringValue(x), LiteralValue::StringValue(y), TokenType::BANGEQUAL) => {
+                let boolean_res = x != y;
+                if boolean_res {
+                    return Ok(LiteralValue::True);
+                }
+                Ok(LiteralValue::False)
+            }
+            (LiteralValue::StringValue(x), LiteralValue::FValue(y), TokenType::STAR) => {
+                repeat(&x, y as usize, &LiteralValue::FValue(y), interpreter)
+            }
+            (LiteralValue::StringValue(x), LiteralValue::IValue(y), TokenType::STAR) => {
+                let count = match y.to_i64() {
+                    Some(count) => count.max(0) as usize,
+                    None if y.is_negative() => 0,
+                    None => usize::MAX,
+                };
+                repeat(&x, count, &LiteralValue::IValue(y), interpreter)
+            }
+            (LiteralValue::StringValue(x), LiteralValue::StringValue(y), TokenType::LESS) => {
+                let bool = x < y;
+                match bool {
+                    true => Ok(LiteralValue::True),
+                    false => Ok(LiteralValue::False),
+                }
+            }
+            (LiteralValue::StringValue(x), LiteralValue::StringValue(y), TokenType::GREATER) => {
+                let bool = x > y;
+                match bool {
+                    true => Ok(LiteralValue::True),
+                    false => Ok(LiteralValue::False),
+                }
+            }
+            (
+                LiteralValue::StringValue(x),
+                LiteralValue::StringValue(y),
+                TokenType::GREATEREQUAL,
+            ) => {
+                let bool = x >= y;
+                match bool {
+                    true => Ok(LiteralValue::True),
+                    false => Ok(LiteralValue::False),
+                }
+            }
+            (LiteralValue::StringValue(x), LiteralValue::StringValue(y), TokenType::LESSEQUAL) => {
+                let bool = x <= y;
+                match bool {
+                    true => Ok(LiteralValue::True),
+                    false => Ok(LiteralValue::False),
+                }
+            }
+            (LiteralValue::FValue(x), LiteralValue::FValue(y), TokenType::BANGEQUAL) => {
+                let bool = x != y;
+                match bool {
+                    true => Ok(LiteralValue::True),
+                    false => Ok(LiteralValue::False),
+                }
+            }
+            (LiteralValue::StringValue(x), LiteralValue::StringValue(y), TokenType::PLUS) => {
+                interpreter.check_string(x.len() + y.len())?;
+                let mut concat = String::new();
 
-                        concat.push_str(x.as_str());
-                        concat.push_str(y.as_str());
+                concat.push_str(x.as_str());
+                concat.push_str(y.as_str());
 
-                        Ok(LiteralValue::StringValue(concat))
-                    }
-                    _ => panic!("Should not get to this point"),
-                }
+                Ok(LiteralValue::StringValue(concat))
             }
+            (
+                left,
+                right,
+                TokenType::PERCENT
+                | TokenType::DIV
+                | TokenType::STARSTAR
+                | TokenType::AMPERSAND
+                | TokenType::PIPE
+                | TokenType::CARET
+                | TokenType::LESSLESS
+                | TokenType::GREATERGREATER,
+            ) => arithmetic(op, left, right, interpreter),
+            (left, right, TokenType::EQUALEQUAL) => match left == right {
+                true => Ok(LiteralValue::True),
+                false => Ok(LiteralValue::False),
+            },
+            (left, right, TokenType::BANGEQUAL) => match left != right {
+                true => Ok(LiteralValue::True),
+                false => Ok(LiteralValue::False),
+            },
+            (left, right, _) => Err(format!(
+                "Cannot apply '{}' to {} and {}",
+                op.lexeme,
+                left.type_name(),
+                right.type_name()
+            )
+            .into()),
         }
     }
 
@@ -271,8 +625,14 @@ impl Expr {
                 }
                 LiteralValue::True
             }
+            LiteralValue::IValue(x) => {
+                if x.is_negative() {
+                    return LiteralValue::False;
+                }
+                LiteralValue::True
+            }
             LiteralValue::StringValue(s) => {
-                if s.len() == 0 {
+                if s.is_empty() {
                     return LiteralValue::True;
                 }
                 LiteralValue::False
@@ -280,7 +640,7 @@ impl Expr {
             LiteralValue::True => LiteralValue::False,
             LiteralValue::False => LiteralValue::True,
             LiteralValue::Nil => LiteralValue::True,
-            _ => panic!("Should not reach this point"),
+            _ => LiteralValue::False,
         }
     }
 
@@ -289,6 +649,203 @@ impl Expr {
     }
 }
 
+/// Reads the property `name` of `object`, as `object.name` does.
+pub fn property(object: LiteralValue, name: &str) -> Result<LiteralValue, LoxErr> {
+    match object {
+        LiteralValue::Module(module) => match module.env.borrow().get(name.to_string()) {
+            Ok(value) => Ok(value),
+            Err(_) => Err(format!("Module {} has no export '{}'", module.name(), name).into()),
+        },
+        LiteralValue::Error { message, line } => match name {
+            "message" => Ok(LiteralValue::StringValue(message)),
+            "line" => Ok(LiteralValue::IValue(Integer::from(line as i64))),
+            _ => Err(format!("Errors have no property '{}'", name).into()),
+        },
+        LiteralValue::Enum(enum_type) => match enum_type.variant(name) {
+            Some(variant) => Ok(variant),
+            None => Err(format!("Enum {} has no variant '{}'", enum_type.name, name).into()),
+        },
+        LiteralValue::Variant { tag, payload } => {
+            match tag.fields.iter().position(|field| field == name) {
+                Some(index) => Ok(payload[index].clone()),
+                None => Err(format!("{} has no field '{}'", tag.path(), name).into()),
+            }
+        }
+        LiteralValue::Generator(generator) => match name {
+            "next" => Ok(LiteralValue::Next(generator)),
+            "done" => match generator.done() {
+                true => Ok(LiteralValue::True),
+                false => Ok(LiteralValue::False),
+            },
+            _ => Err(format!("Generators have no property '{}'", name).into()),
+        },
+        value => Err(format!(
+            "Only modules, enums, errors and generators have properties, cannot read '{}' of {}",
+            name,
+            value.type_name()
+        )
+        .into()),
+    }
+}
+
+/// Calls `callee` with `arguments`, as `callee(arguments)` does.
+pub fn call(
+    callee: LiteralValue,
+    arguments: Vec<LiteralValue>,
+    interpreter: &mut Interpreter,
+) -> Result<LiteralValue, LoxErr> {
+    match callee {
+        LiteralValue::Constructor(tag) if tag.fields.len() == arguments.len() => {
+            Ok(LiteralValue::Variant {
+                tag,
+                payload: arguments,
+            })
+        }
+        LiteralValue::Constructor(tag) => Err(format!(
+            "{} takes {} arguments but got {}",
+            tag.path(),
+            tag.fields.len(),
+            arguments.len()
+        )
+        .into()),
+        LiteralValue::Function(function) => interpreter.call(&function, arguments),
+        LiteralValue::Next(generator) if arguments.is_empty() => {
+            Ok(generator.resume(interpreter)?.unwrap_or(LiteralValue::Nil))
+        }
+        LiteralValue::Next(_) => {
+            Err(format!("Expected 0 arguments but got {}", arguments.len()).into())
+        }
+        value => Err(format!("Cannot call a {}", value.type_name()).into()),
+    }
+}
+
+/// An operand of `and`/`or`, which must be a boolean.
+fn boolean(op: &Token, value: LiteralValue) -> Result<LiteralValue, LoxErr> {
+    match value {
+        LiteralValue::True | LiteralValue::False => Ok(value),
+        _ => Err(format!("Operands of '{}' must be true or false", op.lexeme).into()),
+    }
+}
+
+/// `s[index]`: the character at an integer index, or the characters at
+/// each value of a range, so `s[1..3]` is a slice.
+fn string_index(s: &str, index: &LiteralValue) -> Result<LiteralValue, LoxErr> {
+    let chars: Vec<char> = s.chars().collect();
+    let char_at = |index: &LiteralValue| match index {
+        LiteralValue::IValue(i) => match i.to_i64() {
+            Some(i) if i >= 0 && (i as usize) < chars.len() => Ok(chars[i as usize]),
+            _ => Err(format!(
+                "Index {} is out of bounds for a string of length {}",
+                i,
+                chars.len()
+            )),
+        },
+        index => Err(format!(
+            "String index must be an integer, not {}",
+            index.type_name()
+        )),
+    };
+    match index {
+        LiteralValue::Range(range) => {
+            let mut slice = String::new();
+            for i in 0.. {
+                match range.get(i) {
+                    Some(index) => slice.push(char_at(&index)?),
+                    None => break,
+                }
+            }
+            Ok(LiteralValue::StringValue(slice))
+        }
+        index => Ok(LiteralValue::StringValue(char_at(index)?.to_string())),
+    }
+}
+
+/// `s * times` for a `times` of `count`, failing rather than panicking if
+/// the result is longer than a string can be.
+fn repeat(
+    s: &str,
+    count: usize,
+    times: &LiteralValue,
+    interpreter: &Interpreter,
+) -> Result<LiteralValue, LoxErr> {
+    let len = s
+        .len()
+        .checked_mul(count)
+        .filter(|len| *len <= isize::MAX as usize)
+        .ok_or_else(|| format!("Cannot repeat a string {} times", times.to_string()))?;
+    interpreter.check_string(len)?;
+    Ok(LiteralValue::StringValue(s.repeat(count)))
+}
+
+/// Fails if an integer result of about `bits` bits would be over
+/// `integer::MAX_BITS`, before it is worked out.
+fn check_bits(op: &Token, bits: f64) -> Result<(), LoxErr> {
+    match bits > MAX_BITS as f64 {
+        true => Err(format!(
+            "Result of '{}' would have more than {} bits",
+            op.lexeme, MAX_BITS
+        )
+        .into()),
+        false => Ok(()),
+    }
+}
+
+/// The operators added after the others: `%`, `div`, `**` and the bitwise
+/// ones. Mixed integer and float operands have already been made floats.
+fn arithmetic(
+    op: &Token,
+    left: LiteralValue,
+    right: LiteralValue,
+    interpreter: &mut Interpreter,
+) -> Result<LiteralValue, LoxErr> {
+    use LiteralValue::{FValue, IValue};
+    match (left, right, &op.token_type) {
+        (IValue(x), IValue(y), TokenType::PERCENT) => match x.floor_div_rem(&y) {
+            Some((_, remainder)) => Ok(IValue(remainder)),
+            None => Err("Division by zero".into()),
+        },
+        (IValue(x), IValue(y), TokenType::DIV) => match x.floor_div_rem(&y) {
+            Some((quotient, _)) => Ok(IValue(quotient)),
+            None => Err("Division by zero".into()),
+        },
+        (FValue(x), FValue(y), TokenType::PERCENT) => Ok(FValue(x - y * (x / y).floor())),
+        (FValue(x), FValue(y), TokenType::DIV) => Ok(FValue((x / y).floor())),
+        (IValue(x), IValue(y), TokenType::STARSTAR) => match y.to_i64() {
+            Some(exponent) if exponent >= 0 => {
+                check_bits(op, x.pow_bits(exponent as u64))?;
+                Ok(IValue(x.pow(exponent as u64, || interpreter.check_halt())?))
+            }
+            _ if y.is_negative() => Ok(FValue(x.to_f64().powf(y.to_f64()))),
+            _ => Err(format!("Exponent {} is too large", y).into()),
+        },
+        (FValue(x), FValue(y), TokenType::STARSTAR) => Ok(FValue(x.powf(y))),
+        (IValue(x), IValue(y), TokenType::LESSLESS | TokenType::GREATERGREATER) => {
+            let bits = match y.to_i64() {
+                Some(bits) if bits >= 0 => bits as u64,
+                _ => return Err(format!("Cannot shift by {} bits", y).into()),
+            };
+            match op.token_type {
+                TokenType::LESSLESS if x.bits() > 0 => {
+                    check_bits(op, x.bits() as f64 + bits as f64)?;
+                    Ok(IValue(x.shl(bits)))
+                }
+                TokenType::LESSLESS => Ok(IValue(x)),
+                _ => Ok(IValue(x.shr(bits))),
+            }
+        }
+        (IValue(x), IValue(y), _) => match (x.to_i64(), y.to_i64(), &op.token_type) {
+            (Some(x), Some(y), TokenType::AMPERSAND) => Ok(IValue(Integer::from(x & y))),
+            (Some(x), Some(y), TokenType::PIPE) => Ok(IValue(Integer::from(x | y))),
+            (Some(x), Some(y), _) => Ok(IValue(Integer::from(x ^ y))),
+            _ => Err(format!("Operands of '{}' must fit in 64 bits", op.lexeme).into()),
+        },
+        (_, _, TokenType::PERCENT | TokenType::DIV | TokenType::STARSTAR) => {
+            Err(format!("Operands of '{}' must be numbers", op.lexeme).into())
+        }
+        _ => Err(format!("Operands of '{}' must be integers", op.lexeme).into()),
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::{Expr, LiteralValue};
@@ -301,6 +858,7 @@ mod tests {
                 lexeme: "-".to_string(),
                 literal: None,
                 line_number: 0,
+                column: 0,
             },
             right: Box::new(Expr::LiteralExpr {
                 literal: LiteralValue::FValue(2.0),
@@ -313,8 +871,209 @@ mod tests {
                 lexeme: "*".to_string(),
                 literal: None,
                 line_number: 0,
+                column: 0,
             },
             right: Box::new(minus_expr),
         };
     }
+
+    fn evaluate(source: &str) -> Result<String, String> {
+        let tokens = crate::Scanner::new(source).scan_tokens().unwrap();
+        let mut expr = crate::parser::Parser::new(tokens)
+            .parse_expression()
+            .unwrap();
+        let mut interpreter = crate::Interpreter::new();
+        match expr.evaluate(&mut interpreter) {
+            Ok(value) => Ok(value.to_string()),
+            Err(e) => Err(e.to_string()),
+        }
+    }
+
+    fn parse_error(source: &str) -> String {
+        let tokens = crate::Scanner::new(source).scan_tokens().unwrap();
+        match crate::parser::Parser::new(tokens).parse_expression() {
+            Ok(_) => panic!("{} parsed", source),
+            Err(e) => e.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_arithmetic_operators() {
+        assert_eq!(Ok("-4".to_string()), evaluate("-2 ** 2"));
+        assert_eq!(Ok("512".to_string()), evaluate("2 ** 3 ** 2"));
+        assert_eq!(Ok("0.5".to_string()), evaluate("2 ** -1"));
+        assert_eq!(
+            Ok("1267650600228229401496703205376".to_string()),
+            evaluate("2 ** 100")
+        );
+        assert_eq!(Ok("2".to_string()), evaluate("-7 % 3"));
+        assert_eq!(Ok("-4".to_string()), evaluate("-7 div 2"));
+        assert_eq!(Ok("-3.5".to_string()), evaluate("-7 / 2"));
+        assert_eq!(Ok("5".to_string()), evaluate("10 / 2"));
+        assert_eq!(Ok("inf".to_string()), evaluate("1 / 0"));
+        assert_eq!(Ok("1.5".to_string()), evaluate("7.5 % 2"));
+        assert_eq!(Ok("3".to_string()), evaluate("7.5 div 2"));
+        assert_eq!(Ok("7".to_string()), evaluate("1 | 2 ^ 8 & 12 << 1 + 1 | 4"));
+        assert_eq!(Ok("-6".to_string()), evaluate("~5"));
+        assert_eq!(Ok("-1".to_string()), evaluate("-5 >> 10"));
+        assert_eq!(Ok("36893488147419103232".to_string()), evaluate("1 << 65"));
+        assert_eq!(Err("Division by zero".to_string()), evaluate("1 % 0"));
+        assert_eq!(
+            Err("Operands of '&' must be integers".to_string()),
+            evaluate("1.5 & 1")
+        );
+        assert_eq!(
+            Err("Operands of '%' must be numbers".to_string()),
+            evaluate("\"a\" % 2")
+        );
+        assert_eq!(
+            Err("Result of '<<' would have more than 32768 bits".to_string()),
+            evaluate("1 << 40000000000")
+        );
+        assert_eq!(
+            Err("Result of '**' would have more than 32768 bits".to_string()),
+            evaluate("3 ** 4000000000")
+        );
+        assert_eq!(Ok("1".to_string()), evaluate("(-1) ** 4000000000"));
+        assert_eq!(Ok("0".to_string()), evaluate("0 << 40000000000"));
+    }
+
+    #[test]
+    fn test_conditionals_are_lazy() {
+        assert_eq!(Ok("1".to_string()), evaluate("true ? 1 : 1 / 0"));
+        assert_eq!(Ok("2".to_string()), evaluate("false ? 1 / 0 : 2"));
+        assert_eq!(Ok("2".to_string()), evaluate("false ? 1 : true ? 2 : 3"));
+        assert_eq!(Ok("3".to_string()), evaluate("3 ?? 1 / 0"));
+        assert_eq!(Ok("4".to_string()), evaluate("nil ?? nil ?? 4"));
+        assert_eq!(Ok("false".to_string()), evaluate("false ?? 1"));
+        assert_eq!(Ok("true".to_string()), evaluate("nil ?? false or true"));
+        assert_eq!(Ok("5".to_string()), evaluate("nil ?? 1 == 1 ? 5 : 6"));
+        assert_eq!(
+            Err("Condition of '?' must be true or false".to_string()),
+            evaluate("1 ? 2 : 3")
+        );
+        assert_eq!(
+            Err("Condition of '?' must be true or false".to_string()),
+            evaluate("nil ? 2 : 3")
+        );
+        assert_eq!(
+            "[line 1:9] Error: Expect ':' in conditional expression at end",
+            parse_error("true ? 1")
+        );
+        assert_eq!(
+            "[line 1:1] Error: Expected expression at '??'",
+            parse_error("?? 1")
+        );
+        assert_eq!(
+            "[line 1:5] Error: Expected expression at end",
+            parse_error("1 ??")
+        );
+    }
+
+    #[test]
+    fn test_ranges() {
+        assert_eq!(Ok("0..10 by 3".to_string()), evaluate("0..10 by 3"));
+        assert_eq!(Ok("0..=4".to_string()), evaluate("0..=2 + 2"));
+        assert_eq!(Ok("true".to_string()), evaluate("9 in 0..10 by 3"));
+        assert_eq!(Ok("false".to_string()), evaluate("10 in 0..10"));
+        assert_eq!(Ok("true".to_string()), evaluate("1 in 3..=1 by -1"));
+        assert_eq!(Ok("true".to_string()), evaluate("0.5 in 0..1 by 0.25"));
+        assert_eq!(Ok("true".to_string()), evaluate("0..3 == 0..3"));
+        assert_eq!(Ok("true".to_string()), evaluate("\"ell\" in \"hello\""));
+        assert_eq!(Ok("ell".to_string()), evaluate("\"hello\"[1..4]"));
+        assert_eq!(Ok("olleh".to_string()), evaluate("\"hello\"[4..=0 by -1]"));
+        assert_eq!(Ok("o".to_string()), evaluate("\"hello\"[4]"));
+        assert_eq!(
+            Err("Index 5 is out of bounds for a string of length 5".to_string()),
+            evaluate("\"hello\"[0..=5]")
+        );
+        assert_eq!(
+            Err("Step of a range can't be 0".to_string()),
+            evaluate("0..1 by 0")
+        );
+        assert_eq!(
+            Err("Right side of 'in' must be a range or string, not num".to_string()),
+            evaluate("1 in 1")
+        );
+        assert_eq!(
+            Err("Cannot apply 'in' to num and str".to_string()),
+            evaluate("3 in \"abc\"")
+        );
+        assert_eq!(
+            Err("Cannot apply 'in' to str and range".to_string()),
+            evaluate("\"a\" in 0..3")
+        );
+        assert_eq!(
+            Err("Range bounds must be numbers, not str".to_string()),
+            evaluate("\"a\"..\"b\"")
+        );
+        assert_eq!(
+            Err("Range bounds must be numbers, not nil".to_string()),
+            evaluate("0..1 by nil")
+        );
+        assert_eq!(
+            Err("String index must be an integer, not num".to_string()),
+            evaluate("\"hi\"[0.5]")
+        );
+        assert_eq!(
+            Err("Only strings can be indexed, not range".to_string()),
+            evaluate("(0..3)[1]")
+        );
+        assert_eq!(
+            "[line 1:4] Error: Expected expression at end",
+            parse_error("0..")
+        );
+    }
+
+    #[test]
+    fn test_updates() {
+        let mut interpreter = crate::Interpreter::new();
+        let env = interpreter.env();
+        env.borrow_mut().define(
+            "x".to_string(),
+            LiteralValue::IValue(crate::integer::Integer::from(5)),
+        );
+        let mut run = |source: &str| {
+            let tokens = crate::Scanner::new(source).scan_tokens().unwrap();
+            let mut expr = crate::parser::Parser::new(tokens).parse_expression()?;
+            let value = expr.evaluate(&mut interpreter)?.to_string();
+            let x = env.borrow().get("x".to_string())?.to_string();
+            Ok::<(String, String), crate::LoxErr>((value, x))
+        };
+        let steps = [
+            ("x++", "5", "6"),
+            ("++x", "7", "7"),
+            ("x -= 2", "5", "5"),
+            ("x *= 3", "15", "15"),
+            ("x /= 2", "7.5", "7.5"),
+            ("x %= 4", "3.5", "3.5"),
+            ("x--", "3.5", "2.5"),
+            ("--x + 10", "11.5", "1.5"),
+        ];
+        for (source, value, x) in steps {
+            assert_eq!(
+                (value.to_string(), x.to_string()),
+                run(source).unwrap(),
+                "{}",
+                source
+            );
+        }
+        assert_eq!(
+            "[line 1:2] Error: Invalid assignment target at '++'",
+            run("1++").unwrap_err().to_string()
+        );
+        assert_eq!(
+            "Variable y has not been declared",
+            run("y += 1").unwrap_err().to_string()
+        );
+        assert_eq!(
+            "Cannot apply '+' to num and str",
+            run("x += \"a\"").unwrap_err().to_string()
+        );
+        assert_eq!(
+            "[line 1:2] Error: Invalid assignment target at '--'",
+            run("5--3").unwrap_err().to_string()
+        );
+        assert_eq!(("8".to_string(), "1.5".to_string()), run("5 - -3").unwrap());
+    }
 }
@@ -0,0 +1,37 @@
+use crate::environment::Environment;
+use crate::scanner::Span;
+use crate::statement::Statement;
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// A function value: its parameters and body, and the scope it was created
+/// in, which its body runs inside of.
+pub struct Function {
+    pub params: Vec<String>,
+    pub body: Vec<Statement>,
+    pub closure: Rc<RefCell<Environment>>,
+    /// Calling it makes a `Generator` instead of running the body.
+    pub generator: bool,
+    /// The module the function was written in, unless it is the script.
+    pub module: Option<PathBuf>,
+    /// Where the function was written.
+    pub span: Span,
+}
+
+/// Leaves out the closure, which may hold the function itself.
+impl Debug for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Function")
+            .field("params", &self.params)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A function is only equal to itself.
+impl PartialEq for Function {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
@@ -0,0 +1,302 @@
+use crate::environment::Environment;
+use crate::expr::Expr;
+use crate::function::Function;
+use crate::interpreter::{Interpreter, Unwind};
+use crate::iteration::Iteration;
+use crate::scanner::Span;
+use crate::statement::Statement;
+use crate::{LiteralValue, LoxErr};
+use std::cell::RefCell;
+use std::fmt::Debug;
+use std::rc::Rc;
+
+/// A call of a generator function, paused at a `yield` or not started yet.
+///
+/// The interpreter can't stop part way through `Interpreter::interpret`, so
+/// the generator runs the statements that have a `yield` in them itself,
+/// keeping a frame for each one it is inside. Statements without a `yield`
+/// are handed to the interpreter as usual.
+pub struct Generator {
+    function: Rc<Function>,
+    /// Empty once the function has finished.
+    frames: RefCell<Vec<Frame>>,
+}
+
+enum Frame {
+    /// Statements run one after another in `env`.
+    Block {
+        statements: Vec<Statement>,
+        next: usize,
+        env: Rc<RefCell<Environment>>,
+    },
+    While {
+        predicate: Expr,
+        body: Statement,
+        span: Span,
+        env: Rc<RefCell<Environment>>,
+    },
+    ForIn {
+        variable: String,
+        iteration: Iteration,
+        body: Statement,
+        span: Span,
+        env: Rc<RefCell<Environment>>,
+    },
+}
+
+/// What running a frame a little further led to.
+enum Step {
+    Continue,
+    Yield(LiteralValue),
+    Push(Box<Frame>),
+    /// The frame on top has finished.
+    Pop,
+}
+
+impl Generator {
+    /// A generator that will run `function` in `env`, which holds its
+    /// arguments.
+    pub fn new(function: Rc<Function>, env: Rc<RefCell<Environment>>) -> Self {
+        let frames = vec![Frame::Block {
+            statements: function.body.clone(),
+            next: 0,
+            env,
+        }];
+        Generator {
+            function,
+            frames: RefCell::new(frames),
+        }
+    }
+
+    pub fn done(&self) -> bool {
+        self.frames
+            .try_borrow()
+            .is_ok_and(|frames| frames.is_empty())
+    }
+
+    /// Runs the function up to its next `yield` and returns the value, or
+    /// `None` once it has finished. An error finishes it too.
+    pub fn resume(&self, interpreter: &mut Interpreter) -> Result<Option<LiteralValue>, LoxErr> {
+        let mut frames = match self.frames.try_borrow_mut() {
+            Ok(frames) => frames,
+            Err(_) => return Err("Generator is already running".into()),
+        };
+        let result =
+            interpreter.in_function(&self.function, |interpreter| run(&mut frames, interpreter));
+        if !matches!(result, Ok(Some(_))) {
+            frames.clear();
+        }
+        result
+    }
+}
+
+/// Leaves out the frames, whose scopes may hold the generator itself.
+impl Debug for Generator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Generator")
+            .field("function", &self.function)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A generator is only equal to itself.
+impl PartialEq for Generator {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::eq(self, other)
+    }
+}
+
+fn run(
+    frames: &mut Vec<Frame>,
+    interpreter: &mut Interpreter,
+) -> Result<Option<LiteralValue>, LoxErr> {
+    loop {
+        let step = match frames.last_mut() {
+            Some(frame) => frame.step(interpreter),
+            None => return Ok(None),
+        };
+        match step {
+            Ok(Step::Continue) => {}
+            Ok(Step::Yield(value)) => return Ok(Some(value)),
+            Ok(Step::Push(frame)) => frames.push(*frame),
+            Ok(Step::Pop) => {
+                frames.pop();
+            }
+            Err(e) => match e.downcast_ref::<Unwind>() {
+                Some(Unwind::Break) => {
+                    while let Some(frame) = frames.pop() {
+                        if matches!(frame, Frame::While { .. } | Frame::ForIn { .. }) {
+                            break;
+                        }
+                    }
+                }
+                Some(Unwind::Return(_)) => return Ok(None),
+                None => return Err(e),
+            },
+        }
+    }
+}
+
+impl Frame {
+    /// A frame that runs `stmt` in `env` without a new scope.
+    fn of(stmt: Statement, env: Rc<RefCell<Environment>>) -> Self {
+        Frame::Block {
+            statements: vec![stmt],
+            next: 0,
+            env,
+        }
+    }
+
+    fn step(&mut self, interpreter: &mut Interpreter) -> Result<Step, LoxErr> {
+        match self {
+            Frame::Block {
+                statements,
+                next,
+                env,
+            } => match statements.get(*next) {
+                Some(stmt) => {
+                    *next += 1;
+                    start(stmt.clone(), env.clone(), interpreter)
+                }
+                None => Ok(Step::Pop),
+            },
+            Frame::While {
+                predicate,
+                body,
+                span,
+                env,
+            } => {
+                let flag = match interpreter.in_env(env.clone(), |i| predicate.evaluate(i))? {
+                    LiteralValue::True => true,
+                    LiteralValue::False => false,
+                    _ => return Err("Condition of 'while' must be true or false".into()),
+                };
+                interpreter.branch(*span, 0, flag)?;
+                match flag {
+                    true => Ok(Step::Push(Box::new(Frame::of(body.clone(), env.clone())))),
+                    false => Ok(Step::Pop),
+                }
+            }
+            Frame::ForIn {
+                variable,
+                iteration,
+                body,
+                span,
+                env,
+            } => {
+                let next = interpreter.in_env(env.clone(), |i| iteration.next(i))?;
+                interpreter.branch(*span, 0, next.is_some())?;
+                let value = match next {
+                    Some(value) => value,
+                    None => return Ok(Step::Pop),
+                };
+                let mut scope = Environment::new();
+                scope.enclosing = Some(env.clone());
+                scope.define(variable.clone(), value);
+                let scope = Rc::new(RefCell::new(scope));
+                Ok(Step::Push(Box::new(Frame::of(body.clone(), scope))))
+            }
+        }
+    }
+}
+
+/// Starts running `stmt` in `env`: all of it if it has no `yield`, or else
+/// the part before the first frame it needs.
+fn start(
+    stmt: Statement,
+    env: Rc<RefCell<Environment>>,
+    interpreter: &mut Interpreter,
+) -> Result<Step, LoxErr> {
+    if !yields(&stmt) {
+        interpreter.in_env(env, |i| i.interpret(vec![stmt]))?;
+        return Ok(Step::Continue);
+    }
+    interpreter.visit(&stmt)?;
+    match stmt {
+        Statement::Yield { value, .. } => {
+            let value = match value {
+                Some(mut value) => interpreter.in_env(env, |i| value.evaluate(i))?,
+                None => LiteralValue::Nil,
+            };
+            Ok(Step::Yield(value))
+        }
+        Statement::Block { statements, .. } => {
+            let mut scope = Environment::new();
+            scope.enclosing = Some(env);
+            Ok(Step::Push(Box::new(Frame::Block {
+                statements,
+                next: 0,
+                env: Rc::new(RefCell::new(scope)),
+            })))
+        }
+        Statement::If {
+            mut conditional,
+            happy_path,
+            sad_path,
+            span,
+        } => {
+            let flag = match interpreter.in_env(env.clone(), |i| conditional.evaluate(i))? {
+                LiteralValue::True => true,
+                LiteralValue::False => false,
+                _ => return Err("Condition of 'if' must be true or false".into()),
+            };
+            interpreter.branch(span, 0, flag)?;
+            match (flag, sad_path) {
+                (true, _) => Ok(Step::Push(Box::new(Frame::of(*happy_path, env)))),
+                (false, Some(sad_path)) => Ok(Step::Push(Box::new(Frame::of(*sad_path, env)))),
+                (false, None) => Ok(Step::Continue),
+            }
+        }
+        Statement::While {
+            predicate,
+            happy_path,
+            span,
+        } => Ok(Step::Push(Box::new(Frame::While {
+            predicate,
+            body: *happy_path,
+            span,
+            env,
+        }))),
+        Statement::ForIn {
+            variable,
+            mut iterable,
+            body,
+            span,
+        } => {
+            let iterable = interpreter.in_env(env.clone(), |i| iterable.evaluate(i))?;
+            Ok(Step::Push(Box::new(Frame::ForIn {
+                variable: variable.lexeme,
+                iteration: Iteration::new(iterable)?,
+                body: *body,
+                span,
+                env,
+            })))
+        }
+        Statement::Match {
+            mut subject, arms, ..
+        } => {
+            let value = interpreter.in_env(env.clone(), |i| subject.evaluate(i))?;
+            let (body, scope) = interpreter.in_env(env, |i| i.choose_arm(value, arms))?;
+            Ok(Step::Push(Box::new(Frame::of(body, scope))))
+        }
+        _ => unreachable!("only statements that can hold a yield get here"),
+    }
+}
+
+/// Whether `stmt` has a `yield` in it that belongs to the function it is in.
+/// The parser doesn't allow one inside a `try`.
+fn yields(stmt: &Statement) -> bool {
+    match stmt {
+        Statement::Yield { .. } => true,
+        Statement::Block { statements, .. } => statements.iter().any(yields),
+        Statement::If {
+            happy_path,
+            sad_path,
+            ..
+        } => yields(happy_path) || sad_path.as_deref().is_some_and(yields),
+        Statement::While { happy_path, .. } => yields(happy_path),
+        Statement::ForIn { body, .. } => yields(body),
+        Statement::Match { arms, .. } => arms.iter().any(|arm| yields(&arm.body)),
+        _ => false,
+    }
+}
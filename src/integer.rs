@@ -0,0 +1,499 @@
+use std::cmp::Ordering;
+use std::fmt::Display;
+
+/// An integer that never overflows. Values that fit in an `i64` are kept as
+/// one, and arithmetic that would overflow carries on with a `BigInt`
+/// instead. Results always go back to `Small` when they fit, so equal
+/// values have equal representations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Integer {
+    Small(i64),
+    Big(BigInt),
+}
+
+/// Sign and magnitude, with the magnitude in base 2^32 limbs, least
+/// significant first and without trailing zero limbs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BigInt {
+    negative: bool,
+    magnitude: Vec<u32>,
+}
+
+/// The most bits a result of `*`, `**` or `<<` may have. Working with
+/// bigger numbers takes long enough to get past the interpreter's limits,
+/// since one operation can't be stopped part way through.
+pub const MAX_BITS: u64 = 1 << 15;
+
+impl Integer {
+    /// Parses digits in `radix`, ignoring `_` separators. The caller has
+    /// already checked that every other character is a valid digit.
+    pub fn parse(digits: &str, radix: u32) -> Integer {
+        let mut magnitude = vec![];
+        for digit in digits.chars().filter_map(|c| c.to_digit(radix)) {
+            magnitude = mul_small(&magnitude, radix, digit);
+        }
+        BigInt::new(false, magnitude).normalize()
+    }
+
+    pub fn to_f64(&self) -> f64 {
+        match self {
+            Integer::Small(x) => *x as f64,
+            Integer::Big(x) => {
+                let magnitude = x
+                    .magnitude
+                    .iter()
+                    .rev()
+                    .fold(0.0, |value, limb| value * 4294967296.0 + *limb as f64);
+                if x.negative {
+                    -magnitude
+                } else {
+                    magnitude
+                }
+            }
+        }
+    }
+
+    pub fn to_i64(&self) -> Option<i64> {
+        match self {
+            Integer::Small(x) => Some(*x),
+            Integer::Big(_) => None,
+        }
+    }
+
+    /// The number of bits in the magnitude, 0 for zero.
+    pub fn bits(&self) -> u64 {
+        match self {
+            Integer::Small(x) => 64 - x.unsigned_abs().leading_zeros() as u64,
+            Integer::Big(x) => match x.magnitude.last() {
+                Some(top) => x.magnitude.len() as u64 * 32 - top.leading_zeros() as u64,
+                None => 0,
+            },
+        }
+    }
+
+    pub fn is_negative(&self) -> bool {
+        match self {
+            Integer::Small(x) => *x < 0,
+            Integer::Big(x) => x.negative,
+        }
+    }
+
+    pub fn add(&self, other: &Integer) -> Integer {
+        match (self, other) {
+            (Integer::Small(x), Integer::Small(y)) => match x.checked_add(*y) {
+                Some(sum) => Integer::Small(sum),
+                None => BigInt::from_i128(*x as i128 + *y as i128).normalize(),
+            },
+            _ => self.big().add(&other.big()).normalize(),
+        }
+    }
+
+    pub fn sub(&self, other: &Integer) -> Integer {
+        self.add(&other.neg())
+    }
+
+    pub fn mul(&self, other: &Integer) -> Integer {
+        match (self, other) {
+            (Integer::Small(x), Integer::Small(y)) => match x.checked_mul(*y) {
+                Some(product) => Integer::Small(product),
+                None => BigInt::from_i128(*x as i128 * *y as i128).normalize(),
+            },
+            _ => {
+                let (x, y) = (self.big(), other.big());
+                let magnitude = mul_magnitudes(&x.magnitude, &y.magnitude);
+                BigInt::new(x.negative != y.negative, magnitude).normalize()
+            }
+        }
+    }
+
+    /// Division rounding towards zero, or `None` when dividing by zero.
+    pub fn div(&self, other: &Integer) -> Option<Integer> {
+        self.div_rem(other).map(|(quotient, _)| quotient)
+    }
+
+    /// Quotient rounded towards zero and the remainder, which takes the
+    /// sign of `self`.
+    pub fn div_rem(&self, other: &Integer) -> Option<(Integer, Integer)> {
+        match (self, other) {
+            (_, Integer::Small(0)) => None,
+            (Integer::Small(x), Integer::Small(y)) if !(*x == i64::MIN && *y == -1) => {
+                Some((Integer::Small(x / y), Integer::Small(x % y)))
+            }
+            _ => {
+                let (x, y) = (self.big(), other.big());
+                let (quotient, remainder) = div_rem_magnitudes(&x.magnitude, &y.magnitude);
+                Some((
+                    BigInt::new(x.negative != y.negative, quotient).normalize(),
+                    BigInt::new(x.negative, remainder).normalize(),
+                ))
+            }
+        }
+    }
+
+    /// Quotient rounded down and the remainder, which takes the sign of
+    /// `other`, so that `quotient * other + remainder == self`.
+    pub fn floor_div_rem(&self, other: &Integer) -> Option<(Integer, Integer)> {
+        let (quotient, remainder) = self.div_rem(other)?;
+        if remainder != Integer::Small(0) && remainder.is_negative() != other.is_negative() {
+            return Some((quotient.sub(&Integer::Small(1)), remainder.add(other)));
+        }
+        Some((quotient, remainder))
+    }
+
+    /// Roughly how many bits `self` raised to `exponent` has, without
+    /// working it out.
+    pub fn pow_bits(&self, exponent: u64) -> f64 {
+        let magnitude = self.to_f64().abs();
+        let bits = match magnitude.log2() {
+            bits if bits.is_finite() => bits,
+            _ if magnitude == 0.0 => 0.0,
+            _ => self.bits() as f64,
+        };
+        bits * exponent as f64
+    }
+
+    /// `self` raised to `exponent`, by repeated squaring. `check` runs
+    /// before each squaring and stops the work when it fails.
+    pub fn pow<E>(
+        &self,
+        mut exponent: u64,
+        mut check: impl FnMut() -> Result<(), E>,
+    ) -> Result<Integer, E> {
+        let mut base = self.clone();
+        let mut result = Integer::Small(1);
+        while exponent > 0 {
+            check()?;
+            if exponent & 1 == 1 {
+                result = result.mul(&base);
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = base.mul(&base);
+            }
+        }
+        Ok(result)
+    }
+
+    /// Shifts left by moving whole limbs and then the bits within them.
+    pub fn shl(&self, bits: u64) -> Integer {
+        let x = self.big();
+        if x.magnitude.is_empty() {
+            return Integer::Small(0);
+        }
+        let mut magnitude = vec![0u32; (bits / 32) as usize];
+        magnitude.extend(mul_small(&x.magnitude, 1 << (bits % 32), 0));
+        BigInt::new(x.negative, magnitude).normalize()
+    }
+
+    /// Shifts right, rounding down like an arithmetic shift does.
+    pub fn shr(&self, bits: u64) -> Integer {
+        match (self, bits) {
+            (Integer::Small(x), 0..=63) => Integer::Small(x >> bits),
+            _ if bits >= self.bits() => Integer::Small(if self.is_negative() { -1 } else { 0 }),
+            _ => {
+                let divisor = Integer::Small(1).shl(bits);
+                self.floor_div_rem(&divisor).unwrap().0
+            }
+        }
+    }
+
+    /// The bitwise complement, `-self - 1` in two's complement.
+    pub fn not(&self) -> Integer {
+        self.neg().sub(&Integer::Small(1))
+    }
+
+    pub fn neg(&self) -> Integer {
+        match self {
+            Integer::Small(x) => match x.checked_neg() {
+                Some(negated) => Integer::Small(negated),
+                None => BigInt::from_i128(-(*x as i128)).normalize(),
+            },
+            Integer::Big(x) => BigInt::new(!x.negative, x.magnitude.clone()).normalize(),
+        }
+    }
+
+    fn big(&self) -> BigInt {
+        match self {
+            Integer::Small(x) => BigInt::from_i128(*x as i128),
+            Integer::Big(x) => x.clone(),
+        }
+    }
+}
+
+impl From<i64> for Integer {
+    fn from(value: i64) -> Self {
+        Integer::Small(value)
+    }
+}
+
+impl Ord for Integer {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Integer::Small(x), Integer::Small(y)) => x.cmp(y),
+            _ => {
+                let (x, y) = (self.big(), other.big());
+                match (x.negative, y.negative) {
+                    (false, true) => Ordering::Greater,
+                    (true, false) => Ordering::Less,
+                    (false, false) => compare_magnitudes(&x.magnitude, &y.magnitude),
+                    (true, true) => compare_magnitudes(&y.magnitude, &x.magnitude),
+                }
+            }
+        }
+    }
+}
+
+impl PartialOrd for Integer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Display for Integer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let x = match self {
+            Integer::Small(x) => return write!(f, "{}", x),
+            Integer::Big(x) => x,
+        };
+        let mut chunks = vec![];
+        let mut magnitude = x.magnitude.clone();
+        while !magnitude.is_empty() {
+            let (quotient, chunk) = div_small(&magnitude, 1_000_000_000);
+            chunks.push(chunk);
+            magnitude = quotient;
+        }
+        if x.negative {
+            write!(f, "-")?;
+        }
+        let mut chunks = chunks.iter().rev();
+        write!(f, "{}", chunks.next().unwrap_or(&0))?;
+        for chunk in chunks {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl BigInt {
+    fn new(negative: bool, mut magnitude: Vec<u32>) -> BigInt {
+        while magnitude.last() == Some(&0) {
+            magnitude.pop();
+        }
+        BigInt {
+            negative: negative && !magnitude.is_empty(),
+            magnitude,
+        }
+    }
+
+    fn from_i128(value: i128) -> BigInt {
+        let mut rest = value.unsigned_abs();
+        let mut magnitude = vec![];
+        while rest > 0 {
+            magnitude.push(rest as u32);
+            rest >>= 32;
+        }
+        BigInt::new(value < 0, magnitude)
+    }
+
+    fn normalize(self) -> Integer {
+        if self.magnitude.len() <= 2 {
+            let value = self
+                .magnitude
+                .iter()
+                .rev()
+                .fold(0i128, |value, limb| value << 32 | *limb as i128);
+            let value = if self.negative { -value } else { value };
+            if let Ok(small) = i64::try_from(value) {
+                return Integer::Small(small);
+            }
+        }
+        Integer::Big(self)
+    }
+
+    fn add(&self, other: &BigInt) -> BigInt {
+        if self.negative == other.negative {
+            return BigInt::new(
+                self.negative,
+                add_magnitudes(&self.magnitude, &other.magnitude),
+            );
+        }
+        match compare_magnitudes(&self.magnitude, &other.magnitude) {
+            Ordering::Less => BigInt::new(
+                other.negative,
+                sub_magnitudes(&other.magnitude, &self.magnitude),
+            ),
+            _ => BigInt::new(
+                self.negative,
+                sub_magnitudes(&self.magnitude, &other.magnitude),
+            ),
+        }
+    }
+}
+
+fn compare_magnitudes(a: &[u32], b: &[u32]) -> Ordering {
+    a.len()
+        .cmp(&b.len())
+        .then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut sum = Vec::with_capacity(a.len().max(b.len()) + 1);
+    let mut carry = 0u64;
+    for i in 0..a.len().max(b.len()) {
+        let total = *a.get(i).unwrap_or(&0) as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        sum.push(total as u32);
+        carry = total >> 32;
+    }
+    if carry > 0 {
+        sum.push(carry as u32);
+    }
+    sum
+}
+
+/// `a - b`, where `a` is at least `b`.
+fn sub_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut difference = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, limb) in a.iter().enumerate() {
+        let mut value = *limb as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if value < 0 {
+            value += 1 << 32;
+            borrow = 1;
+        }
+        difference.push(value as u32);
+    }
+    difference
+}
+
+fn mul_magnitudes(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut product = vec![0u32; a.len() + b.len()];
+    for (i, x) in a.iter().enumerate() {
+        let mut carry = 0u64;
+        for (j, y) in b.iter().enumerate() {
+            let total = product[i + j] as u64 + *x as u64 * *y as u64 + carry;
+            product[i + j] = total as u32;
+            carry = total >> 32;
+        }
+        product[i + b.len()] = carry as u32;
+    }
+    product
+}
+
+/// `a * factor + addend`.
+fn mul_small(a: &[u32], factor: u32, addend: u32) -> Vec<u32> {
+    let mut result = Vec::with_capacity(a.len() + 1);
+    let mut carry = addend as u64;
+    for limb in a {
+        let total = *limb as u64 * factor as u64 + carry;
+        result.push(total as u32);
+        carry = total >> 32;
+    }
+    if carry > 0 {
+        result.push(carry as u32);
+    }
+    result
+}
+
+fn div_small(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder = 0u64;
+    for i in (0..a.len()).rev() {
+        let current = remainder << 32 | a[i] as u64;
+        quotient[i] = (current / divisor as u64) as u32;
+        remainder = current % divisor as u64;
+    }
+    while quotient.last() == Some(&0) {
+        quotient.pop();
+    }
+    (quotient, remainder as u32)
+}
+
+/// Long division one bit at a time. Slow next to Knuth's algorithm, but
+/// the numbers scripts work with are small enough for it not to matter.
+fn div_rem_magnitudes(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if b.len() == 1 {
+        let (quotient, remainder) = div_small(a, b[0]);
+        return (quotient, vec![remainder]);
+    }
+    let mut quotient = vec![0u32; a.len()];
+    let mut remainder: Vec<u32> = vec![];
+    for bit in (0..a.len() * 32).rev() {
+        remainder = mul_small(&remainder, 2, (a[bit / 32] >> (bit % 32)) & 1);
+        if compare_magnitudes(&remainder, b) != Ordering::Less {
+            remainder = sub_magnitudes(&remainder, b);
+            while remainder.last() == Some(&0) {
+                remainder.pop();
+            }
+            quotient[bit / 32] |= 1 << (bit % 32);
+        }
+    }
+    (quotient, remainder)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Integer;
+
+    fn int(digits: &str) -> Integer {
+        match digits.strip_prefix('-') {
+            Some(digits) => Integer::parse(digits, 10).neg(),
+            None => Integer::parse(digits, 10),
+        }
+    }
+
+    #[test]
+    fn test_promotion_and_back() {
+        let max = Integer::from(i64::MAX);
+        let big = max.add(&Integer::from(1));
+        assert_eq!("9223372036854775808", big.to_string());
+        assert!(matches!(big, Integer::Big(_)));
+        assert_eq!(max, big.sub(&Integer::from(1)));
+        assert!(matches!(big.sub(&Integer::from(1)), Integer::Small(_)));
+        assert_eq!(Integer::from(i64::MIN), Integer::from(i64::MIN).neg().neg());
+        assert_eq!("-9223372036854775808", Integer::from(i64::MIN).to_string());
+    }
+
+    #[test]
+    fn test_big_arithmetic() {
+        let a = int("123456789012345678901234567890");
+        let b = int("-987654321098765432109876543210");
+        assert_eq!("-864197532086419753208641975320", a.add(&b).to_string());
+        assert_eq!(
+            "-121932631137021795226185032733622923332237463801111263526900",
+            a.mul(&b).to_string()
+        );
+        let (quotient, remainder) = b.div_rem(&a).unwrap();
+        assert_eq!(
+            ("-8".to_string(), "-9000000000900000000090".to_string()),
+            (quotient.to_string(), remainder.to_string())
+        );
+        assert_eq!(None, a.div(&Integer::from(0)));
+        assert!(b < a && a > Integer::from(i64::MAX));
+        assert_eq!(255, Integer::parse("ff", 16).to_i64().unwrap());
+        assert_eq!(1.2345678901234568e29, a.to_f64());
+
+        let mut factorial = Integer::from(1);
+        for n in 1..=25 {
+            factorial = factorial.mul(&Integer::from(n));
+        }
+        assert_eq!("15511210043330985984000000", factorial.to_string());
+    }
+
+    #[test]
+    fn test_pow_and_shifts() {
+        let never = || Ok::<(), ()>(());
+        assert_eq!(int("-1"), int("-1").pow(1_000_001, never).unwrap());
+        assert_eq!(
+            "1267650600228229401496703205376",
+            int("2").pow(100, never).unwrap().to_string()
+        );
+        assert_eq!(int("2").pow(100, never).unwrap(), int("1").shl(100));
+        assert_eq!(int("-40"), int("-5").shl(3));
+        assert_eq!(int("-1"), int("-5").shr(1_000));
+        assert_eq!(int("4"), int("1").shl(100).add(&int("7")).shr(98));
+        assert_eq!(101, int("1").shl(100).bits());
+        assert_eq!(Err(()), int("3").pow(10, || Err(())));
+        assert_eq!(0.0, int("1").pow_bits(u64::MAX));
+    }
+}
@@ -1,95 +1,1292 @@
-use crate::{environment::Environment, statement::Statement, LiteralValue, LoxErr};
+use crate::debugger::Quit;
+use crate::enums::{EnumType, Tag};
+use crate::environment::{redeclare_error, Environment};
+use crate::function::Function;
+use crate::generator::Generator;
+use crate::iteration::Iteration;
+use crate::module::Module;
+use crate::parser::Parser;
+use crate::statement::{Arm, Catch};
+use crate::{scanner::Span, statement::Statement, LiteralValue, LoxErr};
+use crate::{Scanner, SyntaxError};
 use std::cell::RefCell;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Runs around every statement the interpreter executes. `depth` is how
+/// many statement lists deep the statement is, starting at 1 for the top
+/// level. Returning an error stops the program with that error.
+pub trait Hook {
+    fn before_statement(
+        &mut self,
+        stmt: &Statement,
+        env: &Rc<RefCell<Environment>>,
+        depth: usize,
+    ) -> Result<(), LoxErr>;
+
+    /// Runs once the statement passed to the matching `before_statement`
+    /// has finished, whether or not it failed.
+    fn after_statement(&mut self, _depth: usize) -> Result<(), LoxErr> {
+        Ok(())
+    }
+
+    /// Runs whenever a condition decides which way execution goes: the
+    /// condition of an `if`, `while` or `?:` at `span`, the left side of the
+    /// `??` at `span` being nil, the `for-in` loop at `span` having another
+    /// value, the `match` arm whose pattern is at `span` being taken, or
+    /// operand `operand` of the `and`/`or` whose operator is at `span`.
+    fn branch(&mut self, _span: Span, _operand: usize, _outcome: bool) -> Result<(), LoxErr> {
+        Ok(())
+    }
+
+    /// Runs when `function` is called or a generator made from it resumes,
+    /// before its body runs.
+    fn enter_function(&mut self, _function: &Function) -> Result<(), LoxErr> {
+        Ok(())
+    }
+
+    /// Runs once the call passed to the matching `enter_function` has
+    /// finished, whether or not it failed.
+    fn leave_function(&mut self) -> Result<(), LoxErr> {
+        Ok(())
+    }
+}
+
+/// Lets the caller keep a handle on a hook, to read its results once the
+/// program has finished.
+impl<H: Hook> Hook for Rc<RefCell<H>> {
+    fn before_statement(
+        &mut self,
+        stmt: &Statement,
+        env: &Rc<RefCell<Environment>>,
+        depth: usize,
+    ) -> Result<(), LoxErr> {
+        self.borrow_mut().before_statement(stmt, env, depth)
+    }
+
+    fn after_statement(&mut self, depth: usize) -> Result<(), LoxErr> {
+        self.borrow_mut().after_statement(depth)
+    }
+
+    fn branch(&mut self, span: Span, operand: usize, outcome: bool) -> Result<(), LoxErr> {
+        self.borrow_mut().branch(span, operand, outcome)
+    }
+
+    fn enter_function(&mut self, function: &Function) -> Result<(), LoxErr> {
+        self.borrow_mut().enter_function(function)
+    }
+
+    fn leave_function(&mut self) -> Result<(), LoxErr> {
+        self.borrow_mut().leave_function()
+    }
+}
+
+/// Bounds on what a script may use, for running code that isn't trusted.
+/// `None` means no limit. The step budget and deadline apply to each call
+/// of `Interpreter::interpret` from outside.
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Statements and expressions evaluated.
+    pub max_steps: Option<u64>,
+    /// How deeply statements and expressions may nest while running.
+    pub max_depth: Option<usize>,
+    /// Length in bytes of any string the script builds.
+    pub max_string: Option<usize>,
+    pub timeout: Option<Duration>,
+    /// How deeply functions may call each other before the script fails
+    /// with "Stack overflow". This one always applies, since every call
+    /// takes native stack; `stack_size` says how much to give the thread.
+    pub max_calls: usize,
+}
+
+/// Native stack one function call can take, for the frames of the
+/// statements and expressions it runs through. A debug build uses several
+/// times more.
+const CALL_STACK: usize = if cfg!(debug_assertions) { 96 } else { 24 } * 1024;
+
+/// Native stack for parsing the most deeply nested script the parser
+/// accepts, and for the interpreter's own frames below the first call.
+const BASE_STACK: usize = if cfg!(debug_assertions) { 16 } else { 4 } * 1024 * 1024;
+
+impl Limits {
+    /// The native stack a thread needs to run a script to `max_calls`
+    /// deep without overflowing.
+    pub fn stack_size(&self) -> usize {
+        BASE_STACK.saturating_add(self.max_calls.saturating_mul(CALL_STACK))
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_steps: None,
+            max_depth: None,
+            max_string: None,
+            timeout: None,
+            max_calls: 1000,
+        }
+    }
+}
+
+/// Stops a running script from another thread. The script fails with a
+/// runtime error at its next step.
+#[derive(Debug, Clone, Default)]
+pub struct InterruptHandle(Arc<AtomicBool>);
+
+impl InterruptHandle {
+    pub fn interrupt(&self) {
+        self.0.store(true, Ordering::SeqCst);
+    }
+}
+
+/// A value thrown with `throw` on its way to a `catch`.
+#[derive(Debug)]
+pub struct Thrown {
+    pub value: LiteralValue,
+    pub span: Span,
+}
+
+impl Display for Thrown {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "[line {}] Error: Uncaught {}",
+            self.span,
+            self.value.to_string()
+        )
+    }
+}
+
+impl Error for Thrown {}
+
+/// An error from importing or running a module, which names the module.
+#[derive(Debug)]
+pub struct ModuleError {
+    pub path: String,
+    pub error: LoxErr,
+}
+
+impl Display for ModuleError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match has_location(self.error.as_ref()) {
+            true => write!(f, "{}:{}", self.path, self.error),
+            false => write!(f, "{}: Error: {}", self.path, self.error),
+        }
+    }
+}
+
+impl Error for ModuleError {}
+
+/// How an uncaught error is reported: `[line 3:5] Error: ...` when the
+/// error knows where it happened, otherwise `Error: ...`.
+pub fn describe(error: &(dyn Error + 'static)) -> String {
+    match has_location(error) || error.is::<ModuleError>() {
+        true => error.to_string(),
+        false => format!("Error: {}", error),
+    }
+}
+
+fn has_location(error: &(dyn Error + 'static)) -> bool {
+    error.is::<SyntaxError>() || error.is::<Thrown>()
+}
+
+/// A limit being hit or the script being interrupted. Unlike other runtime
+/// errors it can't be caught, so that it always stops the script.
+#[derive(Debug)]
+pub struct Halt(String);
+
+impl Display for Halt {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for Halt {}
+
+/// Statements being left early. It is carried up like an error so that
+/// `finally` runs for it too, and is stopped by the statement it leaves.
+#[derive(Debug)]
+pub enum Unwind {
+    Break,
+    Return(LiteralValue),
+}
+
+impl Display for Unwind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Unwind::Break => write!(f, "'break' outside of a loop"),
+            Unwind::Return(_) => write!(f, "'return' outside of a function"),
+        }
+    }
+}
+
+impl Error for Unwind {}
 
 pub struct Interpreter {
     env: Rc<RefCell<Environment>>,
+    hooks: Vec<Box<dyn Hook>>,
+    depth: usize,
+    output: Box<dyn Write>,
+    limits: Limits,
+    /// Expressions being evaluated, counted towards `max_depth`.
+    nesting: usize,
+    steps: u64,
+    deadline: Option<Instant>,
+    interrupt: InterruptHandle,
+    /// The script being run followed by the modules it is importing, in
+    /// the order they were imported. Imports resolve against the last one.
+    files: Vec<PathBuf>,
+    /// Every module that finished running, by canonical path.
+    modules: HashMap<PathBuf, Module>,
+    /// The line of the innermost statement that failed with an error a
+    /// `catch` can handle, for the error value it receives. `break` and
+    /// `return` don't set it, since they are stopped without a `catch`.
+    error_line: Option<usize>,
+    /// Functions being called, kept under `max_calls`.
+    calls: usize,
 }
 
 impl Interpreter {
     pub fn new() -> Self {
         Self {
             env: Rc::new(RefCell::new(Environment::new())),
+            hooks: vec![],
+            depth: 0,
+            output: Box::new(io::stdout()),
+            limits: Limits::default(),
+            nesting: 0,
+            steps: 0,
+            deadline: None,
+            interrupt: InterruptHandle::default(),
+            files: vec![],
+            modules: HashMap::new(),
+            error_line: None,
+            calls: 0,
+        }
+    }
+
+    /// An interpreter that runs in `env`, for evaluating code in a scope
+    /// that belongs to a paused program.
+    pub fn with_env(env: Rc<RefCell<Environment>>) -> Self {
+        Self { env, ..Self::new() }
+    }
+
+    /// The innermost scope of the code currently running.
+    pub fn env(&self) -> Rc<RefCell<Environment>> {
+        self.env.clone()
+    }
+
+    /// The module running now, if it isn't the script.
+    pub fn module(&self) -> Option<PathBuf> {
+        match self.files.len() {
+            0 | 1 => None,
+            _ => self.files.last().cloned(),
+        }
+    }
+
+    /// Sends `print` output somewhere other than stdout.
+    pub fn set_output(&mut self, output: Box<dyn Write>) {
+        self.output = output;
+    }
+
+    /// The file the script being run came from, so that its imports can be
+    /// found. Without one they resolve against the working directory.
+    pub fn set_script(&mut self, path: &Path) -> Result<(), LoxErr> {
+        self.files = vec![fs::canonicalize(path)?];
+        Ok(())
+    }
+
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    pub fn interrupt_handle(&self) -> InterruptHandle {
+        self.interrupt.clone()
+    }
+
+    /// Counts one statement or expression against the limits, failing if
+    /// the script has used up its budget, run out of time or been
+    /// interrupted.
+    pub fn step(&mut self) -> Result<(), LoxErr> {
+        self.steps += 1;
+        if let Some(max) = self.limits.max_steps {
+            if self.steps > max {
+                return Err(Halt(format!("Step limit of {} exceeded", max)).into());
+            }
         }
+        self.check_halt()
+    }
+
+    /// Fails if the script has run out of time or been interrupted, for
+    /// long operations to check part way through.
+    pub fn check_halt(&mut self) -> Result<(), LoxErr> {
+        if self.interrupt.0.swap(false, Ordering::SeqCst) {
+            return Err(Halt("Interrupted".to_string()).into());
+        }
+        if let Some(deadline) = self.deadline {
+            if Instant::now() > deadline {
+                let timeout = self.limits.timeout.unwrap_or_default();
+                return Err(Halt(format!("Timed out after {:?}", timeout)).into());
+            }
+        }
+        Ok(())
+    }
+
+    /// Enters a nested expression, failing if that goes past `max_depth`.
+    /// Every successful call must be paired with `leave`.
+    pub fn enter(&mut self) -> Result<(), LoxErr> {
+        self.check_depth(self.depth + self.nesting + 1)?;
+        self.nesting += 1;
+        Ok(())
+    }
+
+    pub fn leave(&mut self) {
+        self.nesting -= 1;
+    }
+
+    fn check_depth(&self, depth: usize) -> Result<(), LoxErr> {
+        match self.limits.max_depth {
+            Some(max) if depth > max => {
+                Err(Halt(format!("Nesting limit of {} exceeded", max)).into())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Fails if a string of `len` bytes would be over `max_string`. Call it
+    /// before building the string.
+    pub fn check_string(&self, len: usize) -> Result<(), LoxErr> {
+        match self.limits.max_string {
+            Some(max) if len > max => Err(Halt(format!(
+                "String of {} bytes exceeds the limit of {}",
+                len, max
+            ))
+            .into()),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn add_hook(&mut self, hook: Box<dyn Hook>) {
+        self.hooks.push(hook);
     }
 
     pub fn interpret(&mut self, statements: Vec<Statement>) -> Result<(), LoxErr> {
+        if self.depth == 0 && self.nesting == 0 {
+            self.steps = 0;
+            self.error_line = None;
+            self.deadline = self.limits.timeout.map(|timeout| Instant::now() + timeout);
+        }
+        self.check_depth(self.depth + self.nesting + 1)?;
+        self.depth += 1;
+        let result = self.execute(statements);
+        self.depth -= 1;
+        result
+    }
+
+    /// Tells the hooks which way a condition went.
+    pub fn branch(&mut self, span: Span, operand: usize, outcome: bool) -> Result<(), LoxErr> {
+        for hook in self.hooks.iter_mut() {
+            hook.branch(span, operand, outcome)?;
+        }
+        Ok(())
+    }
+
+    fn execute(&mut self, statements: Vec<Statement>) -> Result<(), LoxErr> {
         for stmt in statements {
-            match stmt {
-                Statement::While {
-                    mut predicate,
-                    happy_path,
-                } => {
-                    let mut flag = predicate.evaluate(self.env.clone())?;
-                    while flag.to_boolean() == true {
-                        self.interpret(vec![*happy_path.clone()])?;
-                        flag = predicate.evaluate(self.env.clone())?;
-                    }
-                }
-                Statement::If {
-                    mut conditional,
-                    happy_path,
-                    sad_path,
-                } => {
-                    let res = conditional.evaluate(self.env.clone())?;
-                    match res {
-                        LiteralValue::True => self.interpret(vec![*happy_path]),
-                        LiteralValue::False => {
-                            if sad_path.is_some() {
-                                self.interpret(vec![*sad_path.unwrap()])?;
-                            }
-                            return Ok(());
-                        }
-                        _ => return Err("Error should not ever get to this point".into()),
-                    }?;
+            self.step()?;
+            for hook in self.hooks.iter_mut() {
+                hook.before_statement(&stmt, &self.env, self.depth)?;
+            }
+            let line = stmt.span().line;
+            let result = self.execute_statement(stmt);
+            for hook in self.hooks.iter_mut() {
+                hook.after_statement(self.depth)?;
+            }
+            match &result {
+                Err(e) if self.error_line.is_none() && catchable(e.as_ref()) => {
+                    self.error_line = Some(line);
                 }
-                Statement::Block { statements } => {
-                    let mut new_env = Environment::new();
-                    new_env.enclosing = Some(self.env.clone());
-                    let old_env = self.env.clone();
-                    self.env = Rc::new(RefCell::new(new_env));
-                    let block_result = self.interpret(statements);
-                    self.env = old_env;
-                    block_result?
+                _ => {}
+            }
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Runs `function` with `arguments` bound to its parameters, in a new
+    /// scope inside the one it was created in. A generator function
+    /// doesn't run yet, and gives a generator for running it instead.
+    pub fn call(
+        &mut self,
+        function: &Rc<Function>,
+        arguments: Vec<LiteralValue>,
+    ) -> Result<LiteralValue, LoxErr> {
+        if arguments.len() != function.params.len() {
+            return Err(format!(
+                "Expected {} arguments but got {}",
+                function.params.len(),
+                arguments.len()
+            )
+            .into());
+        }
+        let mut env = Environment::new();
+        env.enclosing = Some(function.closure.clone());
+        for (param, argument) in function.params.iter().zip(arguments) {
+            env.define(param.clone(), argument);
+        }
+        let env = Rc::new(RefCell::new(env));
+        if function.generator {
+            let generator = Generator::new(function.clone(), env);
+            return Ok(LiteralValue::Generator(Rc::new(generator)));
+        }
+        let body = function.body.clone();
+        let result = self.in_function(function, |i| i.in_env(env, |i| i.interpret(body)));
+        match result {
+            Ok(()) => Ok(LiteralValue::Nil),
+            Err(e) => match e.downcast::<Unwind>() {
+                Ok(unwind) => match *unwind {
+                    Unwind::Return(value) => Ok(value),
+                    unwind => Err(unwind.into()),
+                },
+                Err(e) => Err(e),
+            },
+        }
+    }
+
+    /// Runs `f` as a call of `function`, which counts towards `max_calls`
+    /// and is shown to the hooks. Like the rest of a module, functions from
+    /// one are hidden from the hooks.
+    pub fn in_function<T>(
+        &mut self,
+        function: &Function,
+        f: impl FnOnce(&mut Self) -> Result<T, LoxErr>,
+    ) -> Result<T, LoxErr> {
+        if self.calls >= self.limits.max_calls {
+            return Err("Stack overflow".into());
+        }
+        let hooks = match &function.module {
+            Some(module) => {
+                self.files.push(module.clone());
+                std::mem::take(&mut self.hooks)
+            }
+            None => vec![],
+        };
+        self.calls += 1;
+        let entered = self
+            .hooks
+            .iter_mut()
+            .try_for_each(|hook| hook.enter_function(function));
+        let result = entered.and_then(|()| f(self));
+        let left = self
+            .hooks
+            .iter_mut()
+            .try_for_each(|hook| hook.leave_function());
+        let result = left.and(result);
+        self.calls -= 1;
+        if function.module.is_some() {
+            self.files.pop();
+            self.hooks = hooks;
+        }
+        result
+    }
+
+    /// Runs `f` with `env` as the innermost scope.
+    pub fn in_env<T>(
+        &mut self,
+        env: Rc<RefCell<Environment>>,
+        f: impl FnOnce(&mut Self) -> T,
+    ) -> T {
+        let old_env = std::mem::replace(&mut self.env, env);
+        let result = f(self);
+        self.env = old_env;
+        result
+    }
+
+    /// Counts `stmt` as a step and shows it to the hooks, for statements
+    /// that are run some other way than by `interpret`.
+    pub fn visit(&mut self, stmt: &Statement) -> Result<(), LoxErr> {
+        self.step()?;
+        for hook in self.hooks.iter_mut() {
+            hook.before_statement(stmt, &self.env, self.depth + 1)?;
+        }
+        for hook in self.hooks.iter_mut() {
+            hook.after_statement(self.depth + 1)?;
+        }
+        Ok(())
+    }
+
+    /// Runs the module at `path` in its own global scope, or returns the
+    /// one already run. Hooks don't see the module's statements, since
+    /// their spans belong to a different file.
+    fn import(&mut self, path: &str, span: Span) -> Result<Module, LoxErr> {
+        let base = match self.files.last().and_then(|file| file.parent()) {
+            Some(dir) => dir.to_path_buf(),
+            None => PathBuf::from("."),
+        };
+        let resolved = match fs::canonicalize(base.join(path)) {
+            Ok(resolved) => resolved,
+            Err(e) => {
+                let message = format!("Cannot import \"{}\": {}", path, e);
+                return Err(SyntaxError::new(message, span).into());
+            }
+        };
+        if let Some(start) = self.files.iter().position(|file| *file == resolved) {
+            let chain: Vec<String> = self.files[start..]
+                .iter()
+                .chain([&resolved])
+                .map(|file| self.relative(file))
+                .collect();
+            let message = format!("Import cycle: {}", chain.join(" -> "));
+            return Err(SyntaxError::new(message, span).into());
+        }
+        if let Some(module) = self.modules.get(&resolved) {
+            return Ok(module.clone());
+        }
+
+        let source = fs::read_to_string(&resolved)?;
+        let relative = self.relative(&resolved);
+        let in_module = |error: LoxErr| -> LoxErr {
+            match catchable(error.as_ref()) && !error.is::<ModuleError>() {
+                true => ModuleError {
+                    path: relative.clone(),
+                    error,
                 }
-                Statement::Var {
-                    indentifier,
-                    mut expression,
-                } => {
-                    let result = expression.evaluate(self.env.clone())?;
-                    (*self.env).borrow_mut().define(indentifier, result);
+                .into(),
+                false => error,
+            }
+        };
+        let tokens = Scanner::new(&source).scan_tokens().map_err(in_module)?;
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse();
+        if let Some(error) = parser.take_errors().into_iter().next() {
+            return Err(in_module(error));
+        }
+
+        let module = Module {
+            path: resolved.clone(),
+            env: Rc::new(RefCell::new(Environment::new())),
+        };
+        let env = std::mem::replace(&mut self.env, module.env.clone());
+        let hooks = std::mem::take(&mut self.hooks);
+        self.files.push(resolved.clone());
+        let result = self.interpret(statements);
+        self.files.pop();
+        self.hooks = hooks;
+        self.env = env;
+        result.map_err(in_module)?;
+        self.modules.insert(resolved, module.clone());
+        Ok(module)
+    }
+
+    /// Runs a `try` statement. `finally` runs however the rest finishes,
+    /// and an error it raises replaces the one it interrupted.
+    fn try_statement(
+        &mut self,
+        body: Statement,
+        catch: Option<Catch>,
+        finally: Option<Box<Statement>>,
+    ) -> Result<(), LoxErr> {
+        let mut result = self.interpret(vec![body]);
+        if let Some(catch) = catch {
+            result = match result {
+                Err(error) if catchable(error.as_ref()) => {
+                    let value = self.error_value(error);
+                    self.catch(catch, value)
                 }
-                Statement::Expression { mut expression } => {
-                    expression.evaluate(self.env.clone())?;
+                result => result,
+            };
+        }
+        if let Some(finally) = finally {
+            // An error in `finally` replaces the one being passed on, so it
+            // has to record its own line.
+            let line = self.error_line.take();
+            self.interpret(vec![*finally])?;
+            self.error_line = line;
+        }
+        result
+    }
+
+    /// Runs a `catch` body in a scope where its name holds the error.
+    fn catch(&mut self, catch: Catch, value: LiteralValue) -> Result<(), LoxErr> {
+        let mut env = Environment::new();
+        env.enclosing = Some(self.env.clone());
+        env.define(catch.name, value);
+        let old_env = std::mem::replace(&mut self.env, Rc::new(RefCell::new(env)));
+        let result = self.interpret(vec![*catch.body]);
+        self.env = old_env;
+        result
+    }
+
+    /// Runs `body` for each value of `iterable`, in a new scope holding
+    /// that value as `variable`.
+    fn for_in(
+        &mut self,
+        variable: &str,
+        iterable: LiteralValue,
+        body: Statement,
+        span: Span,
+    ) -> Result<(), LoxErr> {
+        let mut iteration = Iteration::new(iterable)?;
+        loop {
+            let next = iteration.next(self)?;
+            self.branch(span, 0, next.is_some())?;
+            let value = match next {
+                Some(value) => value,
+                None => return Ok(()),
+            };
+            let mut env = Environment::new();
+            env.enclosing = Some(self.env.clone());
+            env.define(variable.to_string(), value);
+            let env = Rc::new(RefCell::new(env));
+            match self.in_env(env, |i| i.interpret(vec![body.clone()])) {
+                Err(e) if matches!(e.downcast_ref(), Some(Unwind::Break)) => return Ok(()),
+                result => result?,
+            }
+        }
+    }
+
+    /// Runs the first arm of a `match` whose pattern and guard fit `value`.
+    /// Each arm is tried in a fresh scope holding its bindings.
+    fn match_statement(&mut self, value: LiteralValue, arms: Vec<Arm>) -> Result<(), LoxErr> {
+        let (body, env) = self.choose_arm(value, arms)?;
+        self.in_env(env, |i| i.interpret(vec![body]))
+    }
+
+    /// The body of the first arm whose pattern matches `value` and whose
+    /// guard holds, with the scope holding what its pattern bound.
+    pub fn choose_arm(
+        &mut self,
+        value: LiteralValue,
+        arms: Vec<Arm>,
+    ) -> Result<(Statement, Rc<RefCell<Environment>>), LoxErr> {
+        for mut arm in arms {
+            let mut bindings = vec![];
+            let matched = arm.pattern.bind(&value, &mut bindings);
+            let mut env = Environment::new();
+            env.enclosing = Some(self.env.clone());
+            for (name, value) in bindings {
+                env.define(name, value);
+            }
+            let env = Rc::new(RefCell::new(env));
+            if self.in_env(env.clone(), |i| i.guard(&mut arm, matched))? {
+                return Ok((*arm.body, env));
+            }
+        }
+        Err(format!("No arm of 'match' matches {}", value.to_string()).into())
+    }
+
+    /// Whether `arm` is taken: its pattern `matched` and its guard holds.
+    fn guard(&mut self, arm: &mut Arm, matched: bool) -> Result<bool, LoxErr> {
+        let mut matched = matched;
+        if let (true, Some(guard)) = (matched, &mut arm.guard) {
+            matched = match guard.evaluate(self)? {
+                LiteralValue::True => true,
+                LiteralValue::False => false,
+                _ => return Err("Guard of a 'match' arm must be true or false".into()),
+            };
+        }
+        self.branch(arm.span, 0, matched)?;
+        Ok(matched)
+    }
+
+    /// Rejects declaring `name` where a constant of the same name was
+    /// declared in the same scope, such as on an earlier REPL line.
+    fn check_redeclare(&self, name: &str, span: Span) -> Result<(), LoxErr> {
+        match self.env.borrow().constant(name) {
+            Some(declared) => Err(redeclare_error(name, declared, span)),
+            None => Ok(()),
+        }
+    }
+
+    /// The value a `catch` receives: what was thrown, or an error value
+    /// for a runtime error.
+    fn error_value(&mut self, error: LoxErr) -> LiteralValue {
+        let error = match error.downcast::<ModuleError>() {
+            Ok(error) => error.error,
+            Err(error) => error,
+        };
+        let line = self.error_line.take().unwrap_or_default();
+        let error = match error.downcast::<Thrown>() {
+            Ok(thrown) => return thrown.value,
+            Err(error) => error,
+        };
+        match error.downcast_ref::<SyntaxError>() {
+            Some(error) => LiteralValue::Error {
+                message: error.message.clone(),
+                line: error.span.line,
+            },
+            None => LiteralValue::Error {
+                message: error.to_string(),
+                line,
+            },
+        }
+    }
+
+    /// `file` relative to the directory of the script, for messages.
+    fn relative(&self, file: &Path) -> String {
+        let root = match self.files.first().and_then(|root| root.parent()) {
+            Some(root) => root.to_path_buf(),
+            None => std::env::current_dir().unwrap_or_default(),
+        };
+        file.strip_prefix(root)
+            .unwrap_or(file)
+            .display()
+            .to_string()
+    }
+
+    fn execute_statement(&mut self, stmt: Statement) -> Result<(), LoxErr> {
+        match stmt {
+            Statement::While {
+                mut predicate,
+                happy_path,
+                span,
+            } => loop {
+                let flag = match predicate.evaluate(self)? {
+                    LiteralValue::True => true,
+                    LiteralValue::False => false,
+                    _ => return Err("Condition of 'while' must be true or false".into()),
+                };
+                self.branch(span, 0, flag)?;
+                if !flag {
+                    break;
                 }
-                Statement::Print { mut expression } => {
-                    let val = expression.evaluate(self.env.clone())?;
-                    let val = match val {
-                        LiteralValue::FValue(x) => format!("{}", x),
-                        LiteralValue::False => format!("false"),
-                        LiteralValue::True => format!("true"),
-                        LiteralValue::StringValue(y) => format!("{}", y),
-                        LiteralValue::Nil => format!("nil"),
-                        _ => todo!(),
-                    };
-                    println!("{}", val);
+                match self.interpret(vec![*happy_path.clone()]) {
+                    Err(e) if matches!(e.downcast_ref(), Some(Unwind::Break)) => break,
+                    result => result?,
                 }
-                Statement::Assert { mut expression_a } => {
-                    match expression_a.evaluate(self.env.clone()) {
-                        Ok(res) => match res {
-                            LiteralValue::True => {}
-                            LiteralValue::False => {
-                                panic!("Assertion Failed")
-                            }
-                            _ => panic!("Should not get to this point"),
-                        },
-                        Err(e) => {
-                            println!("{}", e)
+            },
+            Statement::ForIn {
+                variable,
+                mut iterable,
+                body,
+                span,
+            } => {
+                let iterable = iterable.evaluate(self)?;
+                self.for_in(&variable.lexeme, iterable, *body, span)?
+            }
+            Statement::If {
+                mut conditional,
+                happy_path,
+                sad_path,
+                span,
+            } => {
+                let res = conditional.evaluate(self)?;
+                match res {
+                    LiteralValue::True => {
+                        self.branch(span, 0, true)?;
+                        self.interpret(vec![*happy_path])
+                    }
+                    LiteralValue::False => {
+                        self.branch(span, 0, false)?;
+                        if let Some(sad_path) = sad_path {
+                            self.interpret(vec![*sad_path])?;
                         }
+                        Ok(())
                     }
+                    _ => return Err("Condition of 'if' must be true or false".into()),
+                }?;
+            }
+            Statement::Block { statements, .. } => {
+                let mut new_env = Environment::new();
+                new_env.enclosing = Some(self.env.clone());
+                let old_env = self.env.clone();
+                self.env = Rc::new(RefCell::new(new_env));
+                let block_result = self.interpret(statements);
+                self.env = old_env;
+                block_result?
+            }
+            Statement::Var {
+                indentifier,
+                mut expression,
+                span,
+                constant,
+                ..
+            } => {
+                let result = expression.evaluate(self)?;
+                self.check_redeclare(&indentifier, span)?;
+                match constant {
+                    true => (*self.env)
+                        .borrow_mut()
+                        .define_constant(indentifier, result, span),
+                    false => (*self.env).borrow_mut().define(indentifier, result),
                 }
             }
+            Statement::Import { path, name, span } => {
+                let module = self.import(&path, span)?;
+                self.check_redeclare(&name, span)?;
+                (*self.env)
+                    .borrow_mut()
+                    .define(name, LiteralValue::Module(module));
+            }
+            Statement::Throw {
+                mut expression,
+                span,
+            } => {
+                let value = expression.evaluate(self)?;
+                return Err(Thrown { value, span }.into());
+            }
+            Statement::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => self.try_statement(*body, catch, finally)?,
+            Statement::Break { .. } => return Err(Unwind::Break.into()),
+            Statement::Yield { .. } => return Err("Can't yield outside of a generator".into()),
+            Statement::Return { value, .. } => {
+                let value = match value {
+                    Some(mut value) => value.evaluate(self)?,
+                    None => LiteralValue::Nil,
+                };
+                return Err(Unwind::Return(value).into());
+            }
+            Statement::Enum {
+                name,
+                variants,
+                span,
+            } => {
+                let tags = variants
+                    .into_iter()
+                    .map(|variant| {
+                        Rc::new(Tag {
+                            enum_name: name.clone(),
+                            variant: variant.name,
+                            fields: variant.fields,
+                        })
+                    })
+                    .collect();
+                let enum_type = EnumType {
+                    name: name.clone(),
+                    tags,
+                };
+                self.check_redeclare(&name, span)?;
+                (*self.env).borrow_mut().define_constant(
+                    name,
+                    LiteralValue::Enum(Rc::new(enum_type)),
+                    span,
+                );
+            }
+            Statement::Match {
+                mut subject, arms, ..
+            } => {
+                let value = subject.evaluate(self)?;
+                self.match_statement(value, arms)?
+            }
+            Statement::Expression { mut expression, .. } => {
+                expression.evaluate(self)?;
+            }
+            Statement::Print { mut expression, .. } => {
+                let val = expression.evaluate(self)?;
+                writeln!(self.output, "{}", val.to_string())?;
+            }
+            Statement::Assert {
+                mut expression_a, ..
+            } => match expression_a.evaluate(self)? {
+                LiteralValue::True => {}
+                LiteralValue::False => return Err("Assertion failed".into()),
+                _ => return Err("Assertion must be true or false".into()),
+            },
         }
         Ok(())
     }
 }
+
+/// Whether a `catch` may handle `error`. Limits, control flow and the
+/// debugger stopping the script pass through.
+fn catchable(error: &(dyn Error + 'static)) -> bool {
+    !(error.is::<Halt>() || error.is::<Unwind>() || error.is::<Quit>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Interpreter, Limits};
+    use crate::parser::Parser;
+    use crate::testing::{run_capturing, run_with};
+    use crate::Scanner;
+    use std::time::Duration;
+    use std::{fs, thread};
+
+    fn run(source: &str, limits: Limits) -> Result<(), String> {
+        let mut interpreter = Interpreter::new();
+        interpreter.set_limits(limits);
+        run_with(interpreter, source).1
+    }
+
+    #[test]
+    fn test_limits() {
+        let forever = "while (true) {}";
+        let steps = Limits {
+            max_steps: Some(1000),
+            ..Limits::default()
+        };
+        assert_eq!(
+            Err("Step limit of 1000 exceeded".to_string()),
+            run(forever, steps)
+        );
+
+        let timeout = Limits {
+            timeout: Some(Duration::from_millis(20)),
+            ..Limits::default()
+        };
+        assert_eq!(
+            Err("Timed out after 20ms".to_string()),
+            run(forever, timeout)
+        );
+
+        let string = Limits {
+            max_string: Some(1 << 20),
+            ..Limits::default()
+        };
+        assert_eq!(
+            Err("String of 1000000000000 bytes exceeds the limit of 1048576".to_string()),
+            run("print \"a\" * 1000000000000;", string.clone())
+        );
+        assert_eq!(Ok(()), run("print \"ab\" * 1000;", string));
+        assert_eq!(
+            Err("Cannot repeat a string 18446744073709551616 times".to_string()),
+            run("print \"x\" * (2**64);", Limits::default())
+        );
+        assert_eq!(
+            Err("Cannot repeat a string 9223372036854775807 times".to_string()),
+            run("print \"ab\" * (2**63 - 1);", Limits::default())
+        );
+
+        let depth = Limits {
+            max_depth: Some(10),
+            ..Limits::default()
+        };
+        assert_eq!(Ok(()), run("{{{ print 1; }}}", depth.clone()));
+        assert_eq!(
+            Err("Nesting limit of 10 exceeded".to_string()),
+            run("print ((((((((((1))))))))));", depth)
+        );
+
+        let calls = Limits {
+            max_calls: 3,
+            ..Limits::default()
+        };
+        let count = "var count = fun (n) { return n == 0 ? 0 : 1 + count(n - 1); };\n";
+        assert_eq!(
+            Ok(()),
+            run(&format!("{}print count(2);", count), calls.clone())
+        );
+        assert_eq!(
+            Err("Stack overflow".to_string()),
+            run(&format!("{}print count(3);", count), calls.clone())
+        );
+        assert!(calls.stack_size() < Limits::default().stack_size());
+    }
+
+    #[test]
+    fn test_interrupt() {
+        let mut interpreter = Interpreter::new();
+        let handle = interpreter.interrupt_handle();
+        let interrupter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            handle.interrupt();
+        });
+        let tokens = Scanner::new("while (true) {}").scan_tokens().unwrap();
+        let result = interpreter.interpret(Parser::new(tokens).parse());
+        interrupter.join().unwrap();
+        assert_eq!("Interrupted", result.unwrap_err().to_string());
+
+        let steps = Limits {
+            max_steps: Some(1000),
+            ..Limits::default()
+        };
+        let source = "var f = fun () { while (true) {} };\nassert f();\nprint \"after\";";
+        assert_eq!(
+            Err("Step limit of 1000 exceeded".to_string()),
+            run(source, steps)
+        );
+        assert_eq!(
+            (
+                "".to_string(),
+                Err("Cannot apply '-' to str and num".to_string())
+            ),
+            run_capturing("assert \"a\" - 1 == 0;\nprint \"after\";")
+        );
+    }
+
+    #[test]
+    fn test_imports() {
+        let dir = std::env::temp_dir().join(format!("muslox-imports-{}", std::process::id()));
+        fs::create_dir_all(dir.join("util")).unwrap();
+        let files = [
+            (
+                "main.lox",
+                "import \"util/math.lox\" as m;\n\
+                 import \"util/math.lox\" as again;\n\
+                 print m.pi + again.two;\n\
+                 print m.nope;\n",
+            ),
+            (
+                "util/math.lox",
+                "print \"loaded\";\nvar pi = 3;\nconst two = 2;\n",
+            ),
+            ("a.lox", "import \"util/b.lox\" as b;\n"),
+            ("util/b.lox", "var x = 1;\nimport \"../a.lox\" as a;\n"),
+            ("c.lox", "import \"util/bad.lox\" as bad;\n"),
+            ("util/bad.lox", "var x = 1;\nprint x + nil;\n"),
+            (
+                "d.lox",
+                "try { import \"util/bad.lox\" as bad; } catch (e) { print e.message; print e.line; }\n",
+            ),
+        ];
+        for (name, source) in files {
+            fs::write(dir.join(name), source).unwrap();
+        }
+        let run = |name: &str| {
+            let mut interpreter = Interpreter::new();
+            interpreter.set_script(&dir.join(name)).unwrap();
+            let source = fs::read_to_string(dir.join(name)).unwrap();
+            let (output, result) = run_with(interpreter, &source);
+            (output, result.unwrap_err())
+        };
+
+        assert_eq!(
+            (
+                "loaded\n5\n".to_string(),
+                "Module math.lox has no export 'nope'".to_string()
+            ),
+            run("main.lox")
+        );
+        let separator = std::path::MAIN_SEPARATOR;
+        assert_eq!(
+            format!(
+                "util{}b.lox:[line 2:22] Error: Import cycle: a.lox -> util{}b.lox -> a.lox",
+                separator, separator
+            ),
+            run("a.lox").1
+        );
+        assert_eq!(
+            format!(
+                "util{}bad.lox: Error: Cannot apply '+' to num and nil",
+                separator
+            ),
+            run("c.lox").1
+        );
+        let mut interpreter = Interpreter::new();
+        interpreter.set_script(&dir.join("d.lox")).unwrap();
+        let source = fs::read_to_string(dir.join("d.lox")).unwrap();
+        assert_eq!(
+            ("Cannot apply '+' to num and nil\n2\n".to_string(), Ok(())),
+            run_with(interpreter, &source)
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_try_catch_finally() {
+        let source = "try { print 1 + \"a\"; } catch (e) { print e.message; print e.line; }\n\
+                      try { throw \"boom\"; } catch (e) { print e; } finally { print \"done\"; }\n\
+                      var i = 0;\n\
+                      while (true) { try { i++; if (i == 2) break; } finally { print i; } }\n\
+                      try { try { throw nil; } finally { print \"inner\"; } } catch (e) { print e; }\n\
+                      throw i * 10;\n";
+        let (output, result) = run_capturing(source);
+        assert_eq!(
+            "Cannot apply '+' to num and str\n1\nboom\ndone\n1\n2\ninner\nnil\n",
+            output
+        );
+        assert_eq!(Err("[line 6:1] Error: Uncaught 20".to_string()), result);
+
+        let source = "var f = fun () { try { return 1; } finally {} }; f();\n\
+                      while (true) { break; }\n\
+                      try { 1 + nil; } catch (e) { print e.line; print e; }\n";
+        assert_eq!(
+            (
+                "3\n[line 3] Error: Cannot apply '+' to num and nil\n".to_string(),
+                Ok(())
+            ),
+            run_capturing(source)
+        );
+
+        // An error in `finally` replaces the body's error, line and all.
+        let source = "try {\n\
+                      \x20 try {\n\
+                      \x20   1 + nil;\n\
+                      \x20 } finally {\n\
+                      \x20   \"x\" - 1;\n\
+                      \x20 }\n\
+                      } catch (e) { print e.line; print e.message; }\n\
+                      try { try { 1 + nil; } finally { print 0; } } catch (e) { print e.line; }\n";
+        assert_eq!(
+            (
+                "5\nCannot apply '-' to str and num\n0\n8\n".to_string(),
+                Ok(())
+            ),
+            run_capturing(source)
+        );
+
+        let steps = Limits {
+            max_steps: Some(1000),
+            ..Limits::default()
+        };
+        assert_eq!(
+            Err("Step limit of 1000 exceeded".to_string()),
+            run("try { while (true) {} } catch (e) {}", steps)
+        );
+    }
+
+    #[test]
+    fn test_match() {
+        let source = "var i = -1;\n\
+                      while (i < 13) {\n\
+                      \x20 match (i) {\n\
+                      \x20   -1 => print \"minus one\",\n\
+                      \x20   0..2 => print \"small\",\n\
+                      \x20   2..=3.5 => { var x = i * 10; print x; }\n\
+                      \x20   n if n > 10 => print \"big ${n}\",\n\
+                      \x20   _ => {}\n\
+                      \x20 }\n\
+                      \x20 i += 1;\n\
+                      }\n\
+                      match (\"s\") { \"s\" => print \"string\" }\n\
+                      match (nil) { true => print \"no\", false => print \"no\" }\n";
+        let (output, result) = run_capturing(source);
+        assert_eq!(
+            "minus one\nsmall\nsmall\n20\n30\nbig 11\nbig 12\nstring\n",
+            output
+        );
+        assert_eq!(Err("No arm of 'match' matches nil".to_string()), result);
+    }
+
+    #[test]
+    fn test_enums() {
+        let source = "enum Shape { Point, Circle(r), Rect(w, h) }\n\
+                      var shapes = Shape.Rect(2, \"x\");\n\
+                      print Shape.Point;\n\
+                      print shapes;\n\
+                      print shapes == Shape.Rect(2, \"x\");\n\
+                      print shapes == Shape.Rect(2, \"y\");\n\
+                      print shapes is Shape;\n\
+                      print shapes is Shape.Circle;\n\
+                      print shapes.h;\n\
+                      match (Shape.Circle(1.5)) {\n\
+                      \x20 Shape.Rect(w, h) => print w,\n\
+                      \x20 Shape.Circle(r) if r > 1 => print \"circle ${r}\",\n\
+                      \x20 Shape.Point => print \"point\",\n\
+                      }\n\
+                      Shape.Circle();\n";
+        let (output, result) = run_capturing(source);
+        assert_eq!(
+            "Shape.Point\nShape.Rect(2, \"x\")\ntrue\nfalse\ntrue\nfalse\nx\ncircle 1.5\n",
+            output
+        );
+        assert_eq!(
+            Err("Shape.Circle takes 1 arguments but got 0".to_string()),
+            result
+        );
+    }
+
+    #[test]
+    fn test_functions() {
+        let source = "var counter = fun () {\n\
+                      \x20 var count = 0;\n\
+                      \x20 return () => { count += 1; return count; };\n\
+                      };\n\
+                      var next = counter();\n\
+                      next();\n\
+                      print next();\n\
+                      var apply = fun (f, x) { return f(x); };\n\
+                      print apply((n) => n * 2, 21);\n\
+                      var fact = fun (n) { if (n <= 1) return 1; return n * fact(n - 1); };\n\
+                      print fact(5);\n\
+                      print fun () { while (true) { try { return 1; } finally { print \"finally\"; } } }();\n\
+                      print (() => {})();\n\
+                      print apply;\n\
+                      apply(1);\n";
+        let (output, result) = run_capturing(source);
+        assert_eq!("2\n42\n120\nfinally\n1\nnil\n<fn (f, x)>\n", output);
+        assert_eq!(Err("Expected 2 arguments but got 1".to_string()), result);
+
+        // Deep recursion needs more native stack than a test thread has.
+        let recursion = thread::Builder::new()
+            .stack_size(Limits::default().stack_size())
+            .spawn(|| {
+                let source = "var f = fun (n) { return f(n + 1); };\n\
+                              try { f(0); } catch (e) { print e.message; throw e; }";
+                run(source, Limits::default())
+            })
+            .unwrap();
+        assert_eq!(
+            Err("[line 2:44] Error: Uncaught [line 1] Error: Stack overflow".to_string()),
+            recursion.join().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_for_in() {
+        let source = "for (c in \"ab\") print c;\n\
+                      for (i in 3..=1 by -1) print i;\n\
+                      enum Iter { Of(next, done) }\n\
+                      var countdown = fun (n) { return Iter.Of(() => n--, () => n == 0); };\n\
+                      for (i in countdown(5)) { if (i == 2) break; print i; }\n\
+                      var values = fun () { var i = 0; return Iter.Of(() => i++ == 0 ? nil : 1, () => i == 2); };\n\
+                      for (v in values()) print v;\n\
+                      var first = nil;\n\
+                      for (c in \"xy\") { if (first == nil) first = () => c; }\n\
+                      print first();\n\
+                      for (x in Iter.Of(() => 1, 0)) {}\n";
+        let (output, result) = run_capturing(source);
+        assert_eq!("a\nb\n3\n2\n1\n5\n4\n3\nnil\n1\nx\n", output);
+        assert_eq!(
+            Err("'done' of an iterator must be true or false".to_string()),
+            result
+        );
+        for (source, error) in [
+            ("for (x in 1) {}", "Cannot iterate over a num"),
+            ("for (x in () => 1) {}", "Cannot iterate over a function"),
+            (
+                "enum E { Of(next) } for (x in E.Of(1)) {}",
+                "Cannot iterate over a variant",
+            ),
+        ] {
+            assert_eq!(Err(error.to_string()), run_capturing(source).1);
+        }
+    }
+
+    #[test]
+    fn test_generators() {
+        let source = "var count = fun (n) {\n\
+                      \x20 for (i in 0..n) { if (i == 2) { print \"two\"; } else { yield i; } }\n\
+                      };\n\
+                      var c = count(4);\n\
+                      print c.next();\n\
+                      print c.next();\n\
+                      print c.done;\n\
+                      print c.next();\n\
+                      print c.next();\n\
+                      print c.done;\n\
+                      var fib = fun () { var a = 0; var b = 1; while (true) { yield a; b = a + b; a = b - a; } };\n\
+                      for (x in fib()) { if (x > 10) break; print x; }\n\
+                      var again = fun () { yield again.next(); };\n\
+                      again = again();\n\
+                      again.next();\n";
+        let (output, result) = run_capturing(source);
+        assert_eq!(
+            "0\n1\nfalse\ntwo\n3\nnil\ntrue\n0\n1\n1\n2\n3\n5\n8\n",
+            output
+        );
+        assert_eq!(Err("Generator is already running".to_string()), result);
+
+        let tokens = Scanner::new("fun () { try { yield 1; } finally {} };")
+            .scan_tokens()
+            .unwrap();
+        let mut parser = Parser::new(tokens);
+        parser.parse();
+        assert_eq!(
+            "[line 1:16] Error: Can't yield inside 'try' at 'yield'",
+            parser.errors()[0].to_string()
+        );
+    }
+}
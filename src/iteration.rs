@@ -0,0 +1,76 @@
+use crate::expr::{call, property};
+use crate::generator::Generator;
+use crate::interpreter::Interpreter;
+use crate::range::Range;
+use crate::{LiteralValue, LoxErr};
+use std::rc::Rc;
+
+/// How a `for-in` loop goes through the value it was given.
+///
+/// A string gives its characters, a range its values and a generator what
+/// it yields. Any other value with `next` and `done` properties, such as an
+/// enum variant with those fields, is an iterator the way a generator is:
+/// before each step the loop reads `done`, calling it if it is a function,
+/// and stops once it is true, and otherwise `next()` gives the value.
+pub enum Iteration {
+    Chars { chars: Vec<char>, next: usize },
+    Range { range: Rc<Range>, next: u64 },
+    Generator(Rc<Generator>),
+    Iterator(LiteralValue),
+}
+
+impl Iteration {
+    pub fn new(iterable: LiteralValue) -> Result<Self, LoxErr> {
+        match iterable {
+            LiteralValue::StringValue(s) => Ok(Iteration::Chars {
+                chars: s.chars().collect(),
+                next: 0,
+            }),
+            LiteralValue::Range(range) => Ok(Iteration::Range { range, next: 0 }),
+            LiteralValue::Generator(generator) => Ok(Iteration::Generator(generator)),
+            value if is_iterator(&value) => Ok(Iteration::Iterator(value)),
+            value => Err(format!("Cannot iterate over a {}", value.type_name()).into()),
+        }
+    }
+
+    /// The next value, or `None` once there are no more.
+    pub fn next(&mut self, interpreter: &mut Interpreter) -> Result<Option<LiteralValue>, LoxErr> {
+        match self {
+            Iteration::Chars { chars, next } => {
+                let char = chars
+                    .get(*next)
+                    .map(|c| LiteralValue::StringValue(c.to_string()));
+                *next += 1;
+                Ok(char)
+            }
+            Iteration::Range { range, next } => {
+                let value = range.get(*next);
+                *next += 1;
+                Ok(value)
+            }
+            Iteration::Generator(generator) => generator.resume(interpreter),
+            Iteration::Iterator(iterator) => {
+                let done = match property(iterator.clone(), "done")? {
+                    done @ (LiteralValue::Function(_) | LiteralValue::Next(_)) => {
+                        call(done, vec![], interpreter)?
+                    }
+                    done => done,
+                };
+                match done {
+                    LiteralValue::True => Ok(None),
+                    LiteralValue::False => {
+                        let next = property(iterator.clone(), "next")?;
+                        Ok(Some(call(next, vec![], interpreter)?))
+                    }
+                    _ => Err("'done' of an iterator must be true or false".into()),
+                }
+            }
+        }
+    }
+}
+
+fn is_iterator(value: &LiteralValue) -> bool {
+    ["next", "done"]
+        .iter()
+        .all(|name| property(value.clone(), name).is_ok())
+}
@@ -0,0 +1,425 @@
+use crate::LoxErr;
+use std::error::Error;
+use std::fmt::Display;
+use std::io::{BufRead, Read, Write};
+
+/// How deeply arrays and objects may nest, so that a long run of `[` is an
+/// error rather than running out of native stack.
+const MAX_DEPTH: usize = 128;
+
+/// Just enough JSON for the editor protocols. Objects keep their insertion
+/// order so that responses are written the way they were built.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn object(fields: Vec<(&str, Json)>) -> Json {
+        Json::Object(
+            fields
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    pub fn string(value: &str) -> Json {
+        Json::String(value.to_string())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    /// Follows a dotted path such as `params.textDocument.uri`.
+    pub fn path(&self, path: &str) -> Option<&Json> {
+        path.split('.').try_fold(self, |json, key| json.get(key))
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Json::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Json::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_array(&self) -> Option<&Vec<Json>> {
+        match self {
+            Json::Array(items) => Some(items),
+            _ => None,
+        }
+    }
+
+    pub fn parse(source: &str) -> Result<Json, LoxErr> {
+        let mut reader = JsonReader {
+            chars: source.chars().collect(),
+            current: 0,
+            depth: 0,
+        };
+        let value = reader.value()?;
+        reader.whitespace();
+        if reader.current < reader.chars.len() {
+            return Err("Trailing characters after JSON value".into());
+        }
+        Ok(value)
+    }
+}
+
+impl Display for Json {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{}", b),
+            Json::Number(n) => {
+                if n.fract() == 0.0 && n.abs() < 1e15 {
+                    write!(f, "{}", *n as i64)
+                } else {
+                    write!(f, "{}", n)
+                }
+            }
+            Json::String(s) => write_escaped(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(fields) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in fields.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_escaped(f, key)?;
+                    write!(f, ":{}", value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_escaped(f: &mut std::fmt::Formatter<'_>, s: &str) -> std::fmt::Result {
+    write!(f, "\"")?;
+    for c in s.chars() {
+        match c {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => write!(f, "{}", c)?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct JsonReader {
+    chars: Vec<char>,
+    current: usize,
+    /// How many arrays and objects the value being read is inside.
+    depth: usize,
+}
+
+impl JsonReader {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.current).copied()
+    }
+
+    fn advance(&mut self) -> Result<char, LoxErr> {
+        let c = self.peek().ok_or("Unexpected end of JSON")?;
+        self.current += 1;
+        Ok(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), LoxErr> {
+        let c = self.advance()?;
+        if c != expected {
+            return Err(format!("Expected '{}' in JSON but found '{}'", expected, c).into());
+        }
+        Ok(())
+    }
+
+    fn whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\n' | '\r' | '\t')) {
+            self.current += 1;
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, LoxErr> {
+        for expected in word.chars() {
+            self.expect(expected)?;
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, LoxErr> {
+        self.whitespace();
+        match self.peek() {
+            Some('{' | '[') if self.depth == MAX_DEPTH => {
+                Err(format!("JSON nested more than {} deep", MAX_DEPTH).into())
+            }
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::array),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('n') => self.keyword("null", Json::Null),
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            Some(c) => Err(format!("Unexpected '{}' in JSON", c).into()),
+            None => Err("Unexpected end of JSON".into()),
+        }
+    }
+
+    fn nested(&mut self, read: fn(&mut Self) -> Result<Json, LoxErr>) -> Result<Json, LoxErr> {
+        self.depth += 1;
+        let value = read(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<Json, LoxErr> {
+        self.expect('{')?;
+        let mut fields = vec![];
+        self.whitespace();
+        if self.peek() == Some('}') {
+            self.current += 1;
+            return Ok(Json::Object(fields));
+        }
+        loop {
+            self.whitespace();
+            let key = self.string()?;
+            self.whitespace();
+            self.expect(':')?;
+            let value = self.value()?;
+            fields.push((key, value));
+            self.whitespace();
+            match self.advance()? {
+                ',' => continue,
+                '}' => return Ok(Json::Object(fields)),
+                c => return Err(format!("Expected ',' or '}}' in JSON but found '{}'", c).into()),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, LoxErr> {
+        self.expect('[')?;
+        let mut items = vec![];
+        self.whitespace();
+        if self.peek() == Some(']') {
+            self.current += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.whitespace();
+            match self.advance()? {
+                ',' => continue,
+                ']' => return Ok(Json::Array(items)),
+                c => return Err(format!("Expected ',' or ']' in JSON but found '{}'", c).into()),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, LoxErr> {
+        self.expect('"')?;
+        let mut value = String::new();
+        loop {
+            match self.advance()? {
+                '"' => return Ok(value),
+                '\\' => match self.advance()? {
+                    'n' => value.push('\n'),
+                    'r' => value.push('\r'),
+                    't' => value.push('\t'),
+                    'b' => value.push('\u{8}'),
+                    'f' => value.push('\u{c}'),
+                    'u' => {
+                        let mut code = self.hex4()?;
+                        if (0xD800..0xDC00).contains(&code) {
+                            self.expect('\\')?;
+                            self.expect('u')?;
+                            let low = self.hex4()?;
+                            if !(0xDC00..0xE000).contains(&low) {
+                                return Err("Invalid surrogate pair in JSON".into());
+                            }
+                            code = 0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00);
+                        }
+                        value.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                    }
+                    c => value.push(c),
+                },
+                c => value.push(c),
+            }
+        }
+    }
+
+    fn hex4(&mut self) -> Result<u32, LoxErr> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .advance()?
+                .to_digit(16)
+                .ok_or("Invalid \\u escape in JSON")?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Json, LoxErr> {
+        let start = self.current;
+        while matches!(self.peek(), Some(c) if c.is_ascii_digit() || "+-.eE".contains(c)) {
+            self.current += 1;
+        }
+        let text: String = self.chars[start..self.current].iter().collect();
+        Ok(Json::Number(text.parse()?))
+    }
+}
+
+/// A message that couldn't be read, after which the next one can still be.
+#[derive(Debug)]
+pub struct Malformed(String);
+
+impl Display for Malformed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for Malformed {}
+
+/// Reads one `Content-Length` framed message, the base protocol shared by
+/// the language server and debug adapter. Returns `None` at end of input.
+/// A bad header or body is a `Malformed` error, which the caller can answer
+/// and carry on from.
+pub fn read_message(reader: &mut impl BufRead) -> Result<Option<Json>, LoxErr> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        // The header is looked for at the end of the line, since after a
+        // bad length the body that wasn't read runs into the next header.
+        const NAME: &str = "content-length:";
+        if let Some(index) = header.to_ascii_lowercase().rfind(NAME) {
+            length = Some(header[index + NAME.len()..].trim().to_string());
+        }
+    }
+    let length = length.ok_or_else(|| Malformed("Missing Content-Length header".into()))?;
+    let length: u64 = length
+        .parse()
+        .map_err(|_| Malformed(format!("Invalid Content-Length '{}'", length)))?;
+    // Read as much as arrives rather than allocating `length` bytes up
+    // front, since it may be far more than is sent.
+    let mut body = vec![];
+    reader.take(length).read_to_end(&mut body)?;
+    if (body.len() as u64) < length {
+        return Err("Unexpected end of input in message body".into());
+    }
+    let body = String::from_utf8(body).map_err(|_| Malformed("Message body isn't UTF-8".into()))?;
+    match Json::parse(&body) {
+        Ok(message) => Ok(Some(message)),
+        Err(error) => Err(Malformed(error.to_string()).into()),
+    }
+}
+
+pub fn write_message(writer: &mut impl Write, message: &Json) -> Result<(), LoxErr> {
+    let body = message.to_string();
+    write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body)?;
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_message, write_message, Json, Malformed};
+
+    #[test]
+    fn test_round_trip() {
+        let source = r#"{"id":1,"params":{"text":"a \"b\"\né","list":[true,null,-2.5]}}"#;
+        let json = Json::parse(source).unwrap();
+        assert_eq!(
+            Some("a \"b\"\né"),
+            json.path("params.text").and_then(|t| t.as_str())
+        );
+        assert_eq!(
+            r#"{"id":1,"params":{"text":"a \"b\"\né","list":[true,null,-2.5]}}"#,
+            json.to_string()
+        );
+    }
+
+    #[test]
+    fn test_surrogates() {
+        let json = Json::parse(r#""\ud83d\ude00""#).unwrap();
+        assert_eq!(Some("\u{1F600}"), json.as_str());
+        assert_eq!(
+            "Invalid surrogate pair in JSON",
+            Json::parse(r#""\ud800\u0041""#).unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_framing() {
+        let mut buffer = vec![];
+        write_message(&mut buffer, &Json::object(vec![("id", Json::Number(7.0))])).unwrap();
+        assert_eq!(b"Content-Length: 8\r\n\r\n{\"id\":7}".to_vec(), buffer);
+        let message = read_message(&mut buffer.as_slice()).unwrap().unwrap();
+        assert_eq!(Some(7.0), message.get("id").and_then(|id| id.as_f64()));
+    }
+
+    #[test]
+    fn test_malformed() {
+        let input = "Content-Length: 3\r\n\r\n{x}\
+                     Content-Length: x\r\n\r\n{}\
+                     Content-Length: 8\r\n\r\n{\"id\":7}";
+        let mut reader = input.as_bytes();
+        let mut errors = vec![];
+        let message = loop {
+            match read_message(&mut reader) {
+                Err(error) if error.is::<Malformed>() => errors.push(error.to_string()),
+                result => break result.unwrap().unwrap(),
+            }
+        };
+        assert_eq!(
+            vec![
+                "Expected '\"' in JSON but found 'x'",
+                "Invalid Content-Length 'x'",
+            ],
+            errors
+        );
+        assert_eq!(Some(7.0), message.get("id").and_then(|id| id.as_f64()));
+        assert!(read_message(&mut reader).unwrap().is_none());
+
+        let deep = "[".repeat(100_000);
+        assert_eq!(
+            "JSON nested more than 128 deep",
+            Json::parse(&deep).unwrap_err().to_string()
+        );
+        assert!(Json::parse(&format!("{}{}", "[".repeat(128), "]".repeat(128))).is_ok());
+    }
+}
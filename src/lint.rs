@@ -0,0 +1,648 @@
+use crate::expr::Expr;
+use crate::scanner::{LiteralValue, Span, Token, TokenType};
+use crate::statement::Statement;
+use crate::LoxErr;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::fs;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LintCode {
+    UnusedVariable,
+    ShadowedVariable,
+    AssignmentInCondition,
+    ConstantCondition,
+    SelfAssignment,
+    UnreachableCode,
+}
+
+impl LintCode {
+    pub const ALL: [LintCode; 6] = [
+        LintCode::UnusedVariable,
+        LintCode::ShadowedVariable,
+        LintCode::AssignmentInCondition,
+        LintCode::ConstantCondition,
+        LintCode::SelfAssignment,
+        LintCode::UnreachableCode,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            LintCode::UnusedVariable => "unused_variable",
+            LintCode::ShadowedVariable => "shadowed_variable",
+            LintCode::AssignmentInCondition => "assignment_in_condition",
+            LintCode::ConstantCondition => "constant_condition",
+            LintCode::SelfAssignment => "self_assignment",
+            LintCode::UnreachableCode => "unreachable_code",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<LintCode> {
+        LintCode::ALL.into_iter().find(|code| code.name() == name)
+    }
+}
+
+impl Display for LintCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Warning {
+    pub code: LintCode,
+    pub span: Span,
+    pub message: String,
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: warning[{}]: {}", self.span, self.code, self.message)
+    }
+}
+
+/// Which lints are switched off. The config file holds one `name = allow`
+/// or `name = warn` entry per line, and `#` starts a comment.
+#[derive(Debug, Clone, Default)]
+pub struct LintConfig {
+    disabled: HashSet<LintCode>,
+}
+
+impl LintConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: &str) -> Result<Self, LoxErr> {
+        let contents = fs::read_to_string(path)?;
+        Self::parse(&contents)
+    }
+
+    pub fn parse(contents: &str) -> Result<Self, LoxErr> {
+        let mut config = Self::new();
+        for (index, line) in contents.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if line.is_empty() {
+                continue;
+            }
+            let (name, level) = match line.split_once('=') {
+                Some((name, level)) => (name.trim(), level.trim()),
+                None => return Err(format!("line {}: expected 'lint = level'", index + 1).into()),
+            };
+            let code = match LintCode::from_name(name) {
+                Some(code) => code,
+                None => return Err(format!("line {}: unknown lint '{}'", index + 1, name).into()),
+            };
+            match level {
+                "allow" => {
+                    config.disabled.insert(code);
+                }
+                "warn" => {
+                    config.disabled.remove(&code);
+                }
+                _ => return Err(format!("line {}: unknown level '{}'", index + 1, level).into()),
+            }
+        }
+        Ok(config)
+    }
+
+    pub fn is_enabled(&self, code: LintCode) -> bool {
+        !self.disabled.contains(&code)
+    }
+}
+
+struct Binding {
+    span: Span,
+    used: bool,
+}
+
+/// Walks a parsed program and collects warnings. Every enabled lint is run,
+/// then anything silenced by a `// lint: allow(...)` comment is dropped: one
+/// after code silences its own line, and one alone on its line silences the
+/// next. `tokens` and `comments` are what the scanner found.
+pub fn lint(
+    tokens: &[Token],
+    comments: &[Token],
+    statements: &[Statement],
+    config: &LintConfig,
+) -> Vec<Warning> {
+    let mut linter = Linter {
+        scopes: vec![HashMap::new()],
+        warnings: vec![],
+    };
+    linter.statements(statements);
+    linter.end_scope();
+
+    let allowed = inline_allows(tokens, comments);
+    let mut warnings: Vec<Warning> = linter
+        .warnings
+        .into_iter()
+        .filter(|w| config.is_enabled(w.code))
+        .filter(|w| {
+            let codes = allowed.get(&w.span.line);
+            !codes.is_some_and(|codes| codes.contains(&w.code))
+        })
+        .collect();
+    warnings.sort_by_key(|w| (w.span.line, w.span.column));
+    warnings
+}
+
+/// The lints silenced on each line by `// lint: allow(...)` comments.
+fn inline_allows(tokens: &[Token], comments: &[Token]) -> HashMap<usize, HashSet<LintCode>> {
+    let mut first_columns: HashMap<usize, usize> = HashMap::new();
+    for token in tokens.iter().filter(|t| t.token_type != TokenType::EOF) {
+        let column = first_columns
+            .entry(token.line_number)
+            .or_insert(token.column);
+        *column = (*column).min(token.column);
+    }
+    let mut allowed: HashMap<usize, HashSet<LintCode>> = HashMap::new();
+    for comment in comments {
+        let text = match &comment.literal {
+            Some(LiteralValue::StringValue(text)) => text.trim(),
+            _ => continue,
+        };
+        let list = match text
+            .strip_prefix("lint:")
+            .map(str::trim)
+            .and_then(|rest| rest.strip_prefix("allow("))
+            .and_then(|rest| rest.split(')').next())
+        {
+            Some(list) => list,
+            None => continue,
+        };
+        let codes: Vec<LintCode> = list
+            .split(',')
+            .filter_map(|name| LintCode::from_name(name.trim()))
+            .collect();
+        let line = comment.line_number;
+        let alone = first_columns
+            .get(&line)
+            .is_none_or(|column| *column > comment.column);
+        let lines = match alone {
+            true => line..=line + 1,
+            false => line..=line,
+        };
+        for line in lines {
+            allowed
+                .entry(line)
+                .or_default()
+                .extend(codes.iter().copied());
+        }
+    }
+    allowed
+}
+
+struct Linter {
+    scopes: Vec<HashMap<String, Binding>>,
+    warnings: Vec<Warning>,
+}
+
+impl Linter {
+    fn warn(&mut self, code: LintCode, span: Span, message: String) {
+        self.warnings.push(Warning {
+            code,
+            span,
+            message,
+        });
+    }
+
+    fn end_scope(&mut self) {
+        if let Some(scope) = self.scopes.pop() {
+            let mut unused: Vec<(String, Span)> = scope
+                .into_iter()
+                .filter(|(name, binding)| !binding.used && !name.starts_with('_'))
+                .map(|(name, binding)| (name, binding.span))
+                .collect();
+            unused.sort_by_key(|(_, span)| (span.line, span.column));
+            for (name, span) in unused {
+                self.unused(&name, span);
+            }
+        }
+    }
+
+    fn unused(&mut self, name: &str, span: Span) {
+        self.warn(
+            LintCode::UnusedVariable,
+            span,
+            format!("variable '{}' is never used", name),
+        );
+    }
+
+    fn declare(&mut self, name: &str, span: Span) {
+        let depth = self.scopes.len() - 1;
+        let outer = self.scopes[..depth]
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).map(|b| b.span));
+        if let Some(outer) = outer {
+            self.warn(
+                LintCode::ShadowedVariable,
+                span,
+                format!("variable '{}' shadows the one declared at {}", name, outer),
+            );
+        }
+        let old = self.scopes[depth].insert(name.to_string(), Binding { span, used: false });
+        if let Some(old) = old {
+            if !old.used && !name.starts_with('_') {
+                self.unused(name, old.span);
+            }
+        }
+    }
+
+    fn mark_used(&mut self, name: &str) {
+        for scope in self.scopes.iter_mut().rev() {
+            if let Some(binding) = scope.get_mut(name) {
+                binding.used = true;
+                return;
+            }
+        }
+    }
+
+    /// Lints a list of statements run one after another, warning about the
+    /// first one that can't be reached.
+    fn statements(&mut self, statements: &[Statement]) {
+        let mut exit = None;
+        for stmt in statements {
+            if let Some(keyword) = exit.take() {
+                self.warn(
+                    LintCode::UnreachableCode,
+                    stmt.span(),
+                    format!("unreachable code after '{}'", keyword),
+                );
+            }
+            self.statement(stmt);
+            exit = exit.or(always_leaves(stmt));
+        }
+    }
+
+    fn statement(&mut self, stmt: &Statement) {
+        match stmt {
+            Statement::Expression { expression, .. }
+            | Statement::Print { expression, .. }
+            | Statement::Assert {
+                expression_a: expression,
+                ..
+            } => self.expression(expression),
+            Statement::Var {
+                indentifier,
+                expression,
+                span,
+                ..
+            } => {
+                self.expression(expression);
+                self.declare(indentifier, *span);
+            }
+            Statement::Import { name, span, .. } => self.declare(name, *span),
+            Statement::Throw { expression, .. } => self.expression(expression),
+            Statement::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.statement(body);
+                if let Some(catch) = catch {
+                    self.scopes.push(HashMap::new());
+                    self.declare(&catch.name, catch.span);
+                    self.statement(&catch.body);
+                    self.end_scope();
+                }
+                if let Some(finally) = finally {
+                    self.statement(finally);
+                }
+            }
+            Statement::Match { subject, arms, .. } => {
+                self.expression(subject);
+                for arm in arms {
+                    self.scopes.push(HashMap::new());
+                    for (name, span) in arm.pattern.names() {
+                        self.declare(name, span);
+                    }
+                    if let Some(guard) = &arm.guard {
+                        self.expression(guard);
+                    }
+                    self.statement(&arm.body);
+                    self.end_scope();
+                }
+            }
+            Statement::Enum { name, span, .. } => self.declare(name, *span),
+            Statement::Break { .. } => {}
+            Statement::Return { value, .. } | Statement::Yield { value, .. } => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Statement::Block { statements, .. } => {
+                self.scopes.push(HashMap::new());
+                self.statements(statements);
+                self.end_scope();
+            }
+            Statement::If {
+                conditional,
+                happy_path,
+                sad_path,
+                span,
+            } => {
+                self.condition(conditional, *span);
+                if let Some(value) = constant(conditional) {
+                    let taken = if value { "always" } else { "never" };
+                    self.warn(
+                        LintCode::ConstantCondition,
+                        *span,
+                        format!("condition is constant, this branch is {} taken", taken),
+                    );
+                }
+                self.statement(happy_path);
+                if let Some(sad_path) = sad_path {
+                    self.statement(sad_path);
+                }
+            }
+            Statement::While {
+                predicate,
+                happy_path,
+                span,
+            } => {
+                self.condition(predicate, *span);
+                match constant(predicate) {
+                    Some(true) if !leaves_loop(happy_path, false) => self.warn(
+                        LintCode::ConstantCondition,
+                        *span,
+                        "loop condition is always true and the body never exits".to_string(),
+                    ),
+                    Some(false) => self.warn(
+                        LintCode::ConstantCondition,
+                        *span,
+                        "loop condition is always false, the body never runs".to_string(),
+                    ),
+                    _ => {}
+                }
+                self.statement(happy_path);
+            }
+            Statement::ForIn {
+                variable,
+                iterable,
+                body,
+                ..
+            } => {
+                self.expression(iterable);
+                self.scopes.push(HashMap::new());
+                self.declare(&variable.lexeme, variable.span());
+                self.statement(body);
+                self.end_scope();
+            }
+        }
+    }
+
+    fn condition(&mut self, condition: &Expr, span: Span) {
+        if let Expr::Assignment { name, .. } = ungroup(condition) {
+            self.warn(
+                LintCode::AssignmentInCondition,
+                span,
+                format!(
+                    "assignment to '{}' used as a condition, did you mean '=='?",
+                    name
+                ),
+            );
+        }
+        self.expression(condition);
+    }
+
+    fn expression(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Grouping { expression } => self.expression(expression),
+            Expr::Unary { right, .. } => self.expression(right),
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expression(condition);
+                self.expression(then_branch);
+                self.expression(else_branch);
+            }
+            Expr::Coalesce { left, right, .. } => {
+                self.expression(left);
+                self.expression(right);
+            }
+            Expr::Interpolation { parts } => {
+                for part in parts {
+                    self.expression(part);
+                }
+            }
+            Expr::LiteralExpr { .. } => {}
+            Expr::Get { object, .. } => self.expression(object),
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expression(callee);
+                for argument in arguments {
+                    self.expression(argument);
+                }
+            }
+            Expr::Range {
+                start, end, step, ..
+            } => {
+                self.expression(start);
+                self.expression(end);
+                if let Some(step) = step {
+                    self.expression(step);
+                }
+            }
+            Expr::Index { object, index } => {
+                self.expression(object);
+                self.expression(index);
+            }
+            Expr::Function { params, body, .. } => {
+                self.scopes.push(HashMap::new());
+                for param in params {
+                    self.declare(&param.lexeme, param.span());
+                }
+                self.statements(body);
+                self.end_scope();
+            }
+            Expr::Var { identifier, .. } => self.mark_used(identifier),
+            Expr::Update { name, value, .. } => {
+                self.mark_used(name);
+                self.expression(value);
+            }
+            Expr::Assignment { name, value, span } => {
+                if let Expr::Var { identifier, .. } = ungroup(value) {
+                    if identifier == name {
+                        self.warn(
+                            LintCode::SelfAssignment,
+                            *span,
+                            format!("'{}' is assigned to itself", name),
+                        );
+                    }
+                }
+                self.expression(value);
+            }
+        }
+    }
+}
+
+pub fn ungroup(expr: &Expr) -> &Expr {
+    match expr {
+        Expr::Grouping { expression } => ungroup(expression),
+        _ => expr,
+    }
+}
+
+/// The keyword that makes `stmt` always jump away rather than carry on to
+/// the statement after it, if it does.
+pub fn always_leaves(stmt: &Statement) -> Option<&'static str> {
+    match stmt {
+        Statement::Return { .. } => Some("return"),
+        Statement::Break { .. } => Some("break"),
+        Statement::Throw { .. } => Some("throw"),
+        Statement::Block { statements, .. } => statements.iter().find_map(always_leaves),
+        Statement::If {
+            happy_path,
+            sad_path: Some(sad_path),
+            ..
+        } => always_leaves(sad_path).and(always_leaves(happy_path)),
+        _ => None,
+    }
+}
+
+/// Whether running `stmt` in a loop's body can end the loop: a `break` of
+/// that loop, or a `return`, `yield` or `throw`. `nested` is true inside an
+/// inner loop, whose `break`s only end that loop.
+fn leaves_loop(stmt: &Statement, nested: bool) -> bool {
+    match stmt {
+        Statement::Break { .. } => !nested,
+        Statement::Return { .. } | Statement::Yield { .. } | Statement::Throw { .. } => true,
+        Statement::Block { statements, .. } => statements.iter().any(|s| leaves_loop(s, nested)),
+        Statement::If {
+            happy_path,
+            sad_path,
+            ..
+        } => {
+            leaves_loop(happy_path, nested)
+                || sad_path.as_ref().is_some_and(|s| leaves_loop(s, nested))
+        }
+        Statement::Try {
+            body,
+            catch,
+            finally,
+            ..
+        } => {
+            leaves_loop(body, nested)
+                || catch.as_ref().is_some_and(|c| leaves_loop(&c.body, nested))
+                || finally.as_ref().is_some_and(|f| leaves_loop(f, nested))
+        }
+        Statement::Match { arms, .. } => arms.iter().any(|arm| leaves_loop(&arm.body, nested)),
+        Statement::While { happy_path, .. } => leaves_loop(happy_path, true),
+        Statement::ForIn { body, .. } => leaves_loop(body, true),
+        _ => false,
+    }
+}
+
+fn constant(expr: &Expr) -> Option<bool> {
+    match ungroup(expr) {
+        Expr::LiteralExpr {
+            literal: LiteralValue::True,
+        } => Some(true),
+        Expr::LiteralExpr {
+            literal: LiteralValue::False,
+        } => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lint, LintCode, LintConfig};
+    use crate::parser::Parser;
+    use crate::Scanner;
+
+    fn codes(source: &str, config: &LintConfig) -> Vec<(LintCode, usize)> {
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let statements = Parser::new(tokens.clone()).parse();
+        lint(&tokens, scanner.comments(), &statements, config)
+            .into_iter()
+            .map(|w| (w.code, w.span.line))
+            .collect()
+    }
+
+    #[test]
+    fn test_reports_each_lint() {
+        let source = "var a = 1;\n\
+                      var b = 2;\n\
+                      { var a = 3; print a; }\n\
+                      if (b = 3) print b;\n\
+                      while (true) { b = b; }\n\
+                      var f = fun () { return 1; print 2; };\n\
+                      print f;\n";
+        let found = codes(source, &LintConfig::new());
+        assert_eq!(
+            vec![
+                (LintCode::UnusedVariable, 1),
+                (LintCode::ShadowedVariable, 3),
+                (LintCode::AssignmentInCondition, 4),
+                (LintCode::ConstantCondition, 5),
+                (LintCode::SelfAssignment, 5),
+                (LintCode::UnreachableCode, 6),
+            ],
+            found
+        );
+    }
+
+    #[test]
+    fn test_loops_and_unreachable_code() {
+        let source = "var i = 0;\n\
+                      while (true) { if (i > 3) break; i = i + 1; }\n\
+                      var g = fun () { while (true) { yield i; } };\n\
+                      while (true) { while (true) { break; } }\n\
+                      while (true) {\n\
+                        if (i > 5) { break; } else { throw i; }\n\
+                        print i;\n\
+                        print i;\n\
+                      }\n\
+                      print g;\n";
+        assert_eq!(
+            vec![
+                (LintCode::ConstantCondition, 4),
+                (LintCode::UnreachableCode, 7),
+            ],
+            codes(source, &LintConfig::new())
+        );
+    }
+
+    #[test]
+    fn test_config_disables_lint() {
+        let config = LintConfig::parse("# quiet\nunused_variable = allow\n").unwrap();
+        assert!(codes("var a = 1;", &config).is_empty());
+        assert!(LintConfig::parse("no_such_lint = allow").is_err());
+    }
+
+    #[test]
+    fn test_inline_allow() {
+        let source = "// lint: allow(unused_variable)\nvar a = 1;\nvar b = 2; // lint: allow(shadowed_variable)\n\
+                      var c = \"// lint: allow(unused_variable)\";\n";
+        assert_eq!(
+            vec![(LintCode::UnusedVariable, 3), (LintCode::UnusedVariable, 4)],
+            codes(source, &LintConfig::new())
+        );
+
+        // A trailing comment only covers its own line.
+        let source = "var a = 1; // lint: allow(unused_variable)\nvar b = 2;\n";
+        assert_eq!(
+            vec![(LintCode::UnusedVariable, 2)],
+            codes(source, &LintConfig::new())
+        );
+
+        // A comment above and one after the code both apply.
+        let source = "var a = 1; // lint: allow(unused_variable)\n\
+                      // lint: allow(unused_variable)\n\
+                      { var a = 2; } // lint: allow(shadowed_variable)\n";
+        assert!(codes(source, &LintConfig::new()).is_empty());
+    }
+}
@@ -0,0 +1,754 @@
+use crate::expr::Expr;
+use crate::json::{read_message, write_message, Json, Malformed};
+use crate::parser::Parser;
+use crate::statement::Statement;
+use crate::{LiteralValue, LoxErr, Scanner, Span, SyntaxError, Token, TokenType};
+use std::collections::HashMap;
+use std::io;
+
+const PARSE_ERROR: f64 = -32700.0;
+const METHOD_NOT_FOUND: f64 = -32601.0;
+const SYMBOL_VARIABLE: f64 = 13.0;
+const COMPLETION_VARIABLE: f64 = 6.0;
+const COMPLETION_KEYWORD: f64 = 14.0;
+
+/// Serves the Language Server Protocol over stdin/stdout until the client
+/// sends `exit`.
+pub fn run_stdio() -> Result<(), LoxErr> {
+    let stdin = io::stdin();
+    let mut reader = stdin.lock();
+    let mut writer = io::stdout();
+    let mut server = LanguageServer::new();
+    loop {
+        let message = match read_message(&mut reader) {
+            Ok(Some(message)) => message,
+            Ok(None) => break,
+            Err(error) if error.is::<Malformed>() => {
+                let reply = error_response(Json::Null, PARSE_ERROR, error.to_string());
+                write_message(&mut writer, &reply)?;
+                continue;
+            }
+            Err(error) => return Err(error),
+        };
+        for reply in server.handle(&message) {
+            write_message(&mut writer, &reply)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(())
+}
+
+pub struct LanguageServer {
+    documents: HashMap<String, String>,
+    exited: bool,
+}
+
+impl LanguageServer {
+    pub fn new() -> Self {
+        Self {
+            documents: HashMap::new(),
+            exited: false,
+        }
+    }
+
+    /// Handles one incoming message and returns the responses and
+    /// notifications to send back, in order.
+    pub fn handle(&mut self, message: &Json) -> Vec<Json> {
+        let method = message.get("method").and_then(|m| m.as_str()).unwrap_or("");
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let id = message.get("id");
+        let uri = params
+            .path("textDocument.uri")
+            .and_then(|u| u.as_str())
+            .unwrap_or("")
+            .to_string();
+
+        let result = match method {
+            "initialize" => capabilities(),
+            "shutdown" => Json::Null,
+            "exit" => {
+                self.exited = true;
+                return vec![];
+            }
+            "textDocument/didOpen" => {
+                let text = params.path("textDocument.text").and_then(|t| t.as_str());
+                self.documents
+                    .insert(uri.clone(), text.unwrap_or("").to_string());
+                return vec![self.diagnostics(&uri)];
+            }
+            "textDocument/didChange" => {
+                let changes = params.get("contentChanges").and_then(|c| c.as_array());
+                let text = changes
+                    .and_then(|c| c.last())
+                    .and_then(|c| c.get("text"))
+                    .and_then(|t| t.as_str());
+                if let Some(text) = text {
+                    self.documents.insert(uri.clone(), text.to_string());
+                }
+                return vec![self.diagnostics(&uri)];
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                return vec![notification(
+                    "textDocument/publishDiagnostics",
+                    Json::object(vec![
+                        ("uri", Json::string(&uri)),
+                        ("diagnostics", Json::Array(vec![])),
+                    ]),
+                )];
+            }
+            "textDocument/hover" => {
+                self.with_position(&uri, params, |analysis, token| analysis.hover(token))
+            }
+            "textDocument/definition" => self.with_position(&uri, params, |analysis, token| {
+                analysis.definition(token).map(|decl| {
+                    let range = analysis.range(decl.span, decl.name.chars().count());
+                    location(&uri, range)
+                })
+            }),
+            "textDocument/documentSymbol" => match self.documents.get(&uri) {
+                Some(text) => Analysis::new(text).symbols(),
+                None => Json::Array(vec![]),
+            },
+            "textDocument/completion" => match self.documents.get(&uri) {
+                Some(text) => Analysis::new(text).completions(),
+                None => Json::Array(vec![]),
+            },
+            _ => {
+                let message = format!("Unknown method {}", method);
+                return match id {
+                    Some(id) => vec![error_response(id.clone(), METHOD_NOT_FOUND, message)],
+                    None => vec![],
+                };
+            }
+        };
+
+        match id {
+            Some(id) => vec![Json::object(vec![
+                ("jsonrpc", Json::string("2.0")),
+                ("id", id.clone()),
+                ("result", result),
+            ])],
+            None => vec![],
+        }
+    }
+
+    fn with_position(
+        &self,
+        uri: &str,
+        params: &Json,
+        answer: impl Fn(&Analysis, &Token) -> Option<Json>,
+    ) -> Json {
+        let text = match self.documents.get(uri) {
+            Some(text) => text,
+            None => return Json::Null,
+        };
+        let line = params.path("position.line").and_then(|l| l.as_f64());
+        let character = params.path("position.character").and_then(|c| c.as_f64());
+        let (line, character) = match (line, character) {
+            (Some(line), Some(character)) => (line as usize, character as usize),
+            _ => return Json::Null,
+        };
+        let analysis = Analysis::new(text);
+        analysis
+            .token_at(line + 1, analysis.column(line + 1, character))
+            .and_then(|token| answer(&analysis, token))
+            .unwrap_or(Json::Null)
+    }
+
+    fn diagnostics(&self, uri: &str) -> Json {
+        let text = self.documents.get(uri).map(|t| t.as_str()).unwrap_or("");
+        let analysis = Analysis::new(text);
+        let diagnostics = analysis
+            .errors
+            .iter()
+            .map(|(span, message)| {
+                let length = analysis
+                    .tokens
+                    .iter()
+                    .find(|t| t.span() == *span)
+                    .map(|t| t.lexeme.chars().count().max(1))
+                    .unwrap_or(1);
+                Json::object(vec![
+                    ("range", analysis.range(*span, length)),
+                    ("severity", Json::Number(1.0)),
+                    ("source", Json::string("muslox")),
+                    ("message", Json::string(message)),
+                ])
+            })
+            .collect();
+        notification(
+            "textDocument/publishDiagnostics",
+            Json::object(vec![
+                ("uri", Json::string(uri)),
+                ("diagnostics", Json::Array(diagnostics)),
+            ]),
+        )
+    }
+}
+
+fn capabilities() -> Json {
+    Json::object(vec![
+        (
+            "capabilities",
+            Json::object(vec![
+                ("textDocumentSync", Json::Number(1.0)),
+                ("hoverProvider", Json::Bool(true)),
+                ("definitionProvider", Json::Bool(true)),
+                ("documentSymbolProvider", Json::Bool(true)),
+                ("completionProvider", Json::object(vec![])),
+            ]),
+        ),
+        (
+            "serverInfo",
+            Json::object(vec![("name", Json::string("muslox"))]),
+        ),
+    ])
+}
+
+fn error_response(id: Json, code: f64, message: String) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::string("2.0")),
+        ("id", id),
+        (
+            "error",
+            Json::object(vec![
+                ("code", Json::Number(code)),
+                ("message", Json::String(message)),
+            ]),
+        ),
+    ])
+}
+
+fn notification(method: &str, params: Json) -> Json {
+    Json::object(vec![
+        ("jsonrpc", Json::string("2.0")),
+        ("method", Json::string(method)),
+        ("params", params),
+    ])
+}
+
+/// A position from a 1-based line and the 0-based UTF-16 offset into it
+/// that the protocol counts characters in.
+fn position(line: usize, character: usize) -> Json {
+    Json::object(vec![
+        ("line", Json::Number(line.saturating_sub(1) as f64)),
+        ("character", Json::Number(character as f64)),
+    ])
+}
+
+fn location(uri: &str, range: Json) -> Json {
+    Json::object(vec![("uri", Json::string(uri)), ("range", range)])
+}
+
+struct Declaration {
+    name: String,
+    span: Span,
+    /// `var`, `const`, `import`, `catch`, `match` or `enum`.
+    keyword: &'static str,
+    /// Missing for imports.
+    initializer: Option<Expr>,
+    doc: Option<String>,
+}
+
+/// What the server knows about one version of a document: its tokens, the
+/// `var` and `const` declarations, and which declaration each variable use refers to.
+struct Analysis {
+    lines: Vec<String>,
+    tokens: Vec<Token>,
+    errors: Vec<(Span, String)>,
+    keywords: Vec<String>,
+    declarations: Vec<Declaration>,
+    references: HashMap<Span, usize>,
+}
+
+impl Analysis {
+    fn new(source: &str) -> Self {
+        let mut scanner = Scanner::new(source);
+        let mut keywords: Vec<String> = scanner.keywords().map(|k| k.to_string()).collect();
+        keywords.sort();
+        let mut analysis = Self {
+            lines: source.lines().map(|line| line.to_string()).collect(),
+            tokens: vec![],
+            errors: vec![],
+            keywords,
+            declarations: vec![],
+            references: HashMap::new(),
+        };
+        match scanner.scan_tokens() {
+            Ok(tokens) => {
+                let mut parser = Parser::new(tokens.clone());
+                let statements = parser.parse();
+                for error in parser.errors() {
+                    analysis.errors.push(located(error.as_ref()));
+                }
+                analysis.tokens = tokens;
+                let mut scopes = vec![HashMap::new()];
+                for stmt in &statements {
+                    analysis.statement(stmt, &mut scopes);
+                }
+            }
+            Err(error) => analysis.errors.push(located(error.as_ref())),
+        }
+        analysis
+    }
+
+    fn statement(&mut self, stmt: &Statement, scopes: &mut Vec<HashMap<String, usize>>) {
+        match stmt {
+            Statement::Expression { expression, .. }
+            | Statement::Print { expression, .. }
+            | Statement::Assert {
+                expression_a: expression,
+                ..
+            } => self.expression(expression, scopes),
+            Statement::Var {
+                indentifier,
+                expression,
+                span,
+                doc,
+                constant,
+                ..
+            } => {
+                self.expression(expression, scopes);
+                let keyword = match constant {
+                    true => "const",
+                    false => "var",
+                };
+                self.declare(
+                    indentifier,
+                    *span,
+                    keyword,
+                    Some(expression.clone()),
+                    doc.clone(),
+                    scopes,
+                );
+            }
+            Statement::Import { name, span, .. } => {
+                self.declare(name, *span, "import", None, None, scopes)
+            }
+            Statement::Throw { expression, .. } => self.expression(expression, scopes),
+            Statement::Try {
+                body,
+                catch,
+                finally,
+                ..
+            } => {
+                self.statement(body, scopes);
+                if let Some(catch) = catch {
+                    scopes.push(HashMap::new());
+                    self.declare(&catch.name, catch.span, "catch", None, None, scopes);
+                    self.statement(&catch.body, scopes);
+                    scopes.pop();
+                }
+                if let Some(finally) = finally {
+                    self.statement(finally, scopes);
+                }
+            }
+            Statement::Match { subject, arms, .. } => {
+                self.expression(subject, scopes);
+                for arm in arms {
+                    scopes.push(HashMap::new());
+                    for (name, span) in arm.pattern.names() {
+                        self.declare(name, span, "match", None, None, scopes);
+                    }
+                    if let Some(guard) = &arm.guard {
+                        self.expression(guard, scopes);
+                    }
+                    self.statement(&arm.body, scopes);
+                    scopes.pop();
+                }
+            }
+            Statement::Enum { name, span, .. } => {
+                self.declare(name, *span, "enum", None, None, scopes)
+            }
+            Statement::Break { .. } => {}
+            Statement::Return { value, .. } | Statement::Yield { value, .. } => {
+                if let Some(value) = value {
+                    self.expression(value, scopes);
+                }
+            }
+            Statement::Block { statements, .. } => {
+                scopes.push(HashMap::new());
+                for stmt in statements {
+                    self.statement(stmt, scopes);
+                }
+                scopes.pop();
+            }
+            Statement::If {
+                conditional,
+                happy_path,
+                sad_path,
+                ..
+            } => {
+                self.expression(conditional, scopes);
+                self.statement(happy_path, scopes);
+                if let Some(sad_path) = sad_path {
+                    self.statement(sad_path, scopes);
+                }
+            }
+            Statement::While {
+                predicate,
+                happy_path,
+                ..
+            } => {
+                self.expression(predicate, scopes);
+                self.statement(happy_path, scopes);
+            }
+            Statement::ForIn {
+                variable,
+                iterable,
+                body,
+                ..
+            } => {
+                self.expression(iterable, scopes);
+                scopes.push(HashMap::new());
+                self.declare(&variable.lexeme, variable.span(), "for", None, None, scopes);
+                self.statement(body, scopes);
+                scopes.pop();
+            }
+        }
+    }
+
+    fn expression(&mut self, expr: &Expr, scopes: &[HashMap<String, usize>]) {
+        let (name, span) = match expr {
+            Expr::Binary { left, right, .. } | Expr::Logical { left, right, .. } => {
+                self.expression(left, scopes);
+                self.expression(right, scopes);
+                return;
+            }
+            Expr::Grouping { expression } => return self.expression(expression, scopes),
+            Expr::Unary { right, .. } => return self.expression(right, scopes),
+            Expr::Interpolation { parts } => {
+                for part in parts {
+                    self.expression(part, scopes);
+                }
+                return;
+            }
+            Expr::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                self.expression(condition, scopes);
+                self.expression(then_branch, scopes);
+                self.expression(else_branch, scopes);
+                return;
+            }
+            Expr::Coalesce { left, right, .. } => {
+                self.expression(left, scopes);
+                self.expression(right, scopes);
+                return;
+            }
+            Expr::Get { object, .. } => {
+                self.expression(object, scopes);
+                return;
+            }
+            Expr::Call {
+                callee, arguments, ..
+            } => {
+                self.expression(callee, scopes);
+                for argument in arguments {
+                    self.expression(argument, scopes);
+                }
+                return;
+            }
+            Expr::Range {
+                start, end, step, ..
+            } => {
+                self.expression(start, scopes);
+                self.expression(end, scopes);
+                if let Some(step) = step {
+                    self.expression(step, scopes);
+                }
+                return;
+            }
+            Expr::Index { object, index } => {
+                self.expression(object, scopes);
+                self.expression(index, scopes);
+                return;
+            }
+            Expr::Function { params, body, .. } => {
+                let mut scopes = scopes.to_vec();
+                scopes.push(HashMap::new());
+                for param in params {
+                    self.declare(
+                        &param.lexeme,
+                        param.span(),
+                        "param",
+                        None,
+                        None,
+                        &mut scopes,
+                    );
+                }
+                for stmt in body {
+                    self.statement(stmt, &mut scopes);
+                }
+                return;
+            }
+            Expr::LiteralExpr { .. } => return,
+            Expr::Var { identifier, span } => (identifier, span),
+            Expr::Assignment { name, value, span }
+            | Expr::Update {
+                name, value, span, ..
+            } => {
+                self.expression(value, scopes);
+                (name, span)
+            }
+        };
+        let declaration = scopes.iter().rev().find_map(|scope| scope.get(name));
+        if let Some(index) = declaration {
+            self.references.insert(*span, *index);
+        }
+    }
+
+    fn declare(
+        &mut self,
+        name: &str,
+        span: Span,
+        keyword: &'static str,
+        initializer: Option<Expr>,
+        doc: Option<String>,
+        scopes: &mut [HashMap<String, usize>],
+    ) {
+        self.declarations.push(Declaration {
+            name: name.to_string(),
+            span,
+            keyword,
+            initializer,
+            doc,
+        });
+        let index = self.declarations.len() - 1;
+        self.references.insert(span, index);
+        if let Some(scope) = scopes.last_mut() {
+            scope.insert(name.to_string(), index);
+        }
+    }
+
+    /// The UTF-16 offset of the 1-based char `column` of `line`. Columns
+    /// past the end of the line count one each.
+    fn utf16(&self, line: usize, column: usize) -> usize {
+        let text = self
+            .lines
+            .get(line.wrapping_sub(1))
+            .map_or("", |l| l.as_str());
+        text.chars()
+            .chain(std::iter::repeat(' '))
+            .take(column.saturating_sub(1))
+            .map(char::len_utf16)
+            .sum()
+    }
+
+    /// The 1-based char column at the UTF-16 offset `character` of `line`.
+    fn column(&self, line: usize, character: usize) -> usize {
+        let text = self
+            .lines
+            .get(line.wrapping_sub(1))
+            .map_or("", |l| l.as_str());
+        let mut units = 0;
+        let mut column = 1;
+        for c in text.chars() {
+            if units >= character {
+                return column;
+            }
+            units += c.len_utf16();
+            column += 1;
+        }
+        column + character.saturating_sub(units)
+    }
+
+    fn range(&self, span: Span, length: usize) -> Json {
+        Json::object(vec![
+            (
+                "start",
+                position(span.line, self.utf16(span.line, span.column)),
+            ),
+            (
+                "end",
+                position(span.line, self.utf16(span.line, span.column + length)),
+            ),
+        ])
+    }
+
+    fn token_at(&self, line: usize, column: usize) -> Option<&Token> {
+        self.tokens.iter().find(|t| {
+            t.line_number == line
+                && t.column <= column
+                && column < t.column + t.lexeme.chars().count().max(1)
+                && t.token_type != TokenType::EOF
+        })
+    }
+
+    fn definition(&self, token: &Token) -> Option<&Declaration> {
+        self.references
+            .get(&token.span())
+            .map(|index| &self.declarations[*index])
+    }
+
+    fn hover(&self, token: &Token) -> Option<Json> {
+        let text = match token.token_type {
+            TokenType::IDENTIFIER => {
+                let declaration = self.definition(token)?;
+                let kind = declaration
+                    .initializer
+                    .as_ref()
+                    .and_then(|e| self.infer(e, 0));
+                let signature = match kind {
+                    Some(kind) => format!("{} {}: {}", declaration.keyword, declaration.name, kind),
+                    None => format!("{} {}", declaration.keyword, declaration.name),
+                };
+                match &declaration.doc {
+                    Some(doc) => format!("{}\n\n{}", signature, doc),
+                    None => signature,
+                }
+            }
+            TokenType::DOCCOMMENT => return None,
+            _ => literal_type(token.literal.as_ref(), &token.token_type)?.to_string(),
+        };
+        Some(Json::object(vec![
+            (
+                "contents",
+                Json::object(vec![
+                    ("kind", Json::string("plaintext")),
+                    ("value", Json::String(text)),
+                ]),
+            ),
+            (
+                "range",
+                self.range(token.span(), token.lexeme.chars().count()),
+            ),
+        ]))
+    }
+
+    /// Best-effort type of an expression, following variables back to the
+    /// literal they were initialised with.
+    fn infer(&self, expr: &Expr, depth: usize) -> Option<&'static str> {
+        if depth > 32 {
+            return None;
+        }
+        match expr {
+            Expr::LiteralExpr { literal } => literal_type(Some(literal), &TokenType::NIL),
+            Expr::Grouping { expression } => self.infer(expression, depth + 1),
+            Expr::Assignment { value, .. } | Expr::Update { value, .. } => {
+                self.infer(value, depth + 1)
+            }
+            Expr::Logical { .. } => Some("bool"),
+            Expr::Get { .. } | Expr::Call { .. } => None,
+            Expr::Interpolation { .. } => Some("str"),
+            Expr::Function { .. } => Some("function"),
+            Expr::Range { .. } => Some("range"),
+            Expr::Index { .. } => Some("str"),
+            Expr::Conditional {
+                then_branch,
+                else_branch,
+                ..
+            } => {
+                let kind = self.infer(then_branch, depth + 1)?;
+                (self.infer(else_branch, depth + 1) == Some(kind)).then_some(kind)
+            }
+            Expr::Coalesce { left, right, .. } => match self.infer(left, depth + 1) {
+                Some("nil") => self.infer(right, depth + 1),
+                Some(kind) if self.infer(right, depth + 1) == Some(kind) => Some(kind),
+                _ => None,
+            },
+            Expr::Unary { operator, .. } => match operator.token_type {
+                TokenType::MINUS | TokenType::TILDE => Some("num"),
+                _ => Some("bool"),
+            },
+            Expr::Var { span, .. } => {
+                let index = self.references.get(span)?;
+                let initializer = self.declarations[*index].initializer.as_ref()?;
+                self.infer(initializer, depth + 1)
+            }
+            Expr::Binary { left, op, right } => {
+                let left = self.infer(left, depth + 1);
+                let right = self.infer(right, depth + 1);
+                match (left, right, &op.token_type) {
+                    (
+                        _,
+                        _,
+                        TokenType::EQUALEQUAL
+                        | TokenType::BANGEQUAL
+                        | TokenType::GREATER
+                        | TokenType::GREATEREQUAL
+                        | TokenType::LESS
+                        | TokenType::LESSEQUAL
+                        | TokenType::IS
+                        | TokenType::IN,
+                    ) => Some("bool"),
+                    (Some("num"), Some("num"), _) => Some("num"),
+                    (Some("str"), Some("str"), TokenType::PLUS) => Some("str"),
+                    (Some("str"), Some("num"), TokenType::STAR) => Some("str"),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    fn symbols(&self) -> Json {
+        Json::Array(
+            self.declarations
+                .iter()
+                .map(|decl| {
+                    let mut fields = vec![
+                        ("name", Json::string(&decl.name)),
+                        ("kind", Json::Number(SYMBOL_VARIABLE)),
+                        ("range", self.range(decl.span, decl.name.chars().count())),
+                        (
+                            "selectionRange",
+                            self.range(decl.span, decl.name.chars().count()),
+                        ),
+                    ];
+                    let kind = decl.initializer.as_ref().and_then(|e| self.infer(e, 0));
+                    if let Some(kind) = kind {
+                        fields.insert(1, ("detail", Json::string(kind)));
+                    }
+                    Json::object(fields)
+                })
+                .collect(),
+        )
+    }
+
+    fn completions(&self) -> Json {
+        let mut names: Vec<&str> = self.declarations.iter().map(|d| d.name.as_str()).collect();
+        names.sort();
+        names.dedup();
+        let keywords = self
+            .keywords
+            .iter()
+            .map(|k| (k.as_str(), COMPLETION_KEYWORD));
+        let names = names.into_iter().map(|n| (n, COMPLETION_VARIABLE));
+        Json::Array(
+            keywords
+                .chain(names)
+                .map(|(label, kind)| {
+                    Json::object(vec![
+                        ("label", Json::string(label)),
+                        ("kind", Json::Number(kind)),
+                    ])
+                })
+                .collect(),
+        )
+    }
+}
+
+fn literal_type(literal: Option<&LiteralValue>, token_type: &TokenType) -> Option<&'static str> {
+    match (literal, token_type) {
+        (Some(LiteralValue::FValue(_) | LiteralValue::IValue(_)), _) => Some("num"),
+        (Some(LiteralValue::StringValue(_)), _) => Some("str"),
+        (Some(LiteralValue::True | LiteralValue::False), _) => Some("bool"),
+        (Some(LiteralValue::Nil), _) => Some("nil"),
+        (None, TokenType::TRUE | TokenType::FALSE) => Some("bool"),
+        (None, TokenType::NIL) => Some("nil"),
+        _ => None,
+    }
+}
+
+fn located(error: &(dyn std::error::Error + 'static)) -> (Span, String) {
+    match error.downcast_ref::<SyntaxError>() {
+        Some(error) => (error.span, error.message.clone()),
+        None => (Span { line: 1, column: 1 }, error.to_string()),
+    }
+}
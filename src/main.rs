@@ -1,27 +1,91 @@
+mod check;
+mod coverage;
+mod dap;
+mod debugger;
+mod enums;
 mod environment;
 mod expr;
+mod function;
+mod generator;
+mod integer;
 mod interpreter;
+mod iteration;
+mod json;
+mod lint;
+mod lsp;
+mod module;
 mod parser;
+mod profiler;
+mod range;
 mod scanner;
 mod statement;
-use interpreter::Interpreter;
+#[cfg(test)]
+mod testing;
+mod types;
+use coverage::Coverage;
+use debugger::{Debugger, Quit};
+use interpreter::{Interpreter, Limits};
+use lint::LintConfig;
 use parser::Parser;
+use profiler::Profiler;
 
 use crate::scanner::*;
 
+use std::cell::RefCell;
 use std::error::Error;
 use std::fs;
 use std::io::Write;
+use std::path::Path;
 use std::process::exit;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::thread;
+use std::time::Duration;
 use std::{env, io, io::BufRead};
 
 type LoxErr = Box<dyn Error>;
 
-pub fn run_file(path: &str) -> Result<(), LoxErr> {
-    let mut interpreter = Interpreter::new();
+/// Instrumentation to attach when running a script.
+#[derive(Default)]
+pub struct RunOptions {
+    /// Report time per line on stderr and write folded stacks to a
+    /// `.folded` file beside the script.
+    pub profile: bool,
+    /// Write line and branch coverage to this file in LCOV format.
+    pub coverage: Option<String>,
+    pub limits: Limits,
+}
+
+pub fn run_file(path: &str, options: &RunOptions) -> Result<(), LoxErr> {
     let contents = fs::read_to_string(path)?;
-    run(&mut interpreter, &contents)?;
-    Ok(())
+    let mut interpreter = Interpreter::new();
+    interpreter.set_script(Path::new(path))?;
+    interpreter.set_limits(options.limits.clone());
+    let profiler = options
+        .profile
+        .then(|| Rc::new(RefCell::new(Profiler::new(&contents))));
+    let coverage = options
+        .coverage
+        .as_ref()
+        .map(|out| (out, Rc::new(RefCell::new(Coverage::new(path, &contents)))));
+    if let Some(profiler) = &profiler {
+        interpreter.add_hook(Box::new(profiler.clone()));
+    }
+    if let Some((_, coverage)) = &coverage {
+        interpreter.add_hook(Box::new(coverage.clone()));
+    }
+    let result = run(&mut interpreter, &contents);
+    if let Some(profiler) = profiler {
+        let folded_path = Path::new(path).with_extension("folded");
+        fs::write(&folded_path, profiler.borrow().folded())?;
+        eprint!("{}", profiler.borrow().report());
+        eprintln!("folded stacks written to {}", folded_path.display());
+    }
+    if let Some((out, coverage)) = coverage {
+        fs::write(out, coverage.borrow().lcov())?;
+        eprintln!("coverage written to {}", out);
+    }
+    result
 }
 
 pub fn run(interpreter: &mut Interpreter, contents: &str) -> Result<(), LoxErr> {
@@ -29,10 +93,67 @@ pub fn run(interpreter: &mut Interpreter, contents: &str) -> Result<(), LoxErr>
     let tokens = scanner.scan_tokens()?;
     let mut parser = Parser::new(tokens);
     let statements = parser.parse();
+    for error in parser.errors() {
+        eprintln!("{}", error);
+    }
     interpreter.interpret(statements)?;
     Ok(())
 }
 
+/// Runs a script under the terminal debugger, reading commands from stdin.
+pub fn debug_file(path: &str) -> Result<(), LoxErr> {
+    let contents = fs::read_to_string(path)?;
+    let mut interpreter = Interpreter::new();
+    interpreter.set_script(Path::new(path))?;
+    let debugger = Debugger::new(&contents, io::stdin().lock(), io::stdout());
+    interpreter.add_hook(Box::new(debugger));
+    match run(&mut interpreter, &contents) {
+        Err(e) if e.is::<Quit>() => Ok(()),
+        result => result,
+    }
+}
+
+/// Lints a script and prints any warnings. Lints can be switched off with a
+/// `muslox-lint.conf` file next to the script. Returns the warning count.
+pub fn lint_file(path: &str) -> Result<usize, LoxErr> {
+    let contents = fs::read_to_string(path)?;
+    let config_path = Path::new(path).with_file_name("muslox-lint.conf");
+    let config = match config_path.exists() {
+        true => LintConfig::load(&config_path.to_string_lossy())?,
+        false => LintConfig::new(),
+    };
+    let mut scanner = Scanner::new(&contents);
+    let tokens = scanner.scan_tokens()?;
+    let mut parser = Parser::new(tokens.clone());
+    let statements = parser.parse();
+    for error in parser.errors() {
+        eprintln!("{}:{}", path, error);
+    }
+    let warnings = lint::lint(&tokens, scanner.comments(), &statements, &config);
+    for warning in &warnings {
+        println!("{}:{}", path, warning);
+    }
+    Ok(warnings.len())
+}
+
+/// Prints the syntax and type errors in a script and returns how many
+/// there were.
+pub fn check_file(path: &str) -> Result<usize, LoxErr> {
+    let contents = fs::read_to_string(path)?;
+    let mut scanner = Scanner::new(&contents);
+    let tokens = scanner.scan_tokens()?;
+    let mut parser = Parser::new(tokens);
+    let statements = parser.parse();
+    for error in parser.errors() {
+        eprintln!("{}:{}", path, error);
+    }
+    let errors = check::check(&statements);
+    for error in &errors {
+        println!("{}:{}", path, error);
+    }
+    Ok(parser.errors().len() + errors.len())
+}
+
 pub fn run_prompt() -> Result<(), LoxErr> {
     let mut interpreter = Interpreter::new();
     loop {
@@ -49,16 +170,119 @@ pub fn run_prompt() -> Result<(), LoxErr> {
         run(&mut interpreter, &buffer)?;
     }
 }
-fn main() -> Result<(), LoxErr> {
+fn usage() -> ! {
+    println!(
+        "Usage: muslox [--profile] [--coverage out.info] [--max-steps N] [--max-depth N] \
+         [--max-string BYTES] [--max-calls N] [--timeout SECONDS] [script] | muslox lint|check|debug [script] | \
+         muslox lsp|dap"
+    );
+    exit(64)
+}
+
+/// The value after a numeric flag, or the usage message if it is missing
+/// or not a number.
+fn number<T: FromStr>(arg: Option<&String>) -> T {
+    match arg.map(|arg| arg.parse()) {
+        Some(Ok(n)) => n,
+        _ => usage(),
+    }
+}
+
+/// What the command line asked for.
+enum Command {
+    Lsp,
+    Dap,
+    Debug(String),
+    Check(String),
+    Lint(String),
+    Run(String, RunOptions),
+    Prompt,
+}
+
+fn main() {
     let args: Vec<String> = env::args().collect();
+    let command = parse_args(&args);
+    // Scripts run on a thread with room for as many calls as the limits allow.
+    let stack_size = match &command {
+        Command::Run(_, options) => options.limits.stack_size(),
+        _ => Limits::default().stack_size(),
+    };
+    let muslox = thread::Builder::new()
+        .stack_size(stack_size)
+        .spawn(|| {
+            if let Err(e) = run_command(command) {
+                eprintln!("{}", interpreter::describe(e.as_ref()));
+                exit(1)
+            }
+        })
+        .expect("failed to start the interpreter thread");
+    if muslox.join().is_err() {
+        exit(101)
+    }
+}
 
-    if args.len() > 2 {
-        println!("Usage: jlox [script]");
-        exit(64)
-    } else if args.len() == 2 {
-        run_file(&args[1])?;
+fn parse_args(args: &[String]) -> Command {
+    if args.len() == 2 && args[1] == "lsp" {
+        Command::Lsp
+    } else if args.len() == 2 && args[1] == "dap" {
+        Command::Dap
+    } else if args.len() == 3 && args[1] == "debug" {
+        Command::Debug(args[2].clone())
+    } else if args.len() == 3 && args[1] == "check" {
+        Command::Check(args[2].clone())
+    } else if args.len() == 3 && args[1] == "lint" {
+        Command::Lint(args[2].clone())
     } else {
-        run_prompt()?;
+        let mut options = RunOptions::default();
+        let mut script = None;
+        let mut rest = args[1..].iter();
+        while let Some(arg) = rest.next() {
+            match arg.as_str() {
+                "--profile" => options.profile = true,
+                "--coverage" => match rest.next() {
+                    Some(out) => options.coverage = Some(out.clone()),
+                    None => usage(),
+                },
+                "--max-steps" => options.limits.max_steps = Some(number(rest.next())),
+                "--max-depth" => options.limits.max_depth = Some(number(rest.next())),
+                "--max-string" => options.limits.max_string = Some(number(rest.next())),
+                "--max-calls" => options.limits.max_calls = number(rest.next()),
+                "--timeout" => {
+                    let seconds: f64 = number(rest.next());
+                    match Duration::try_from_secs_f64(seconds) {
+                        Ok(timeout) => options.limits.timeout = Some(timeout),
+                        Err(_) => usage(),
+                    }
+                }
+                _ if arg.starts_with("--") || script.is_some() => usage(),
+                _ => script = Some(arg),
+            }
+        }
+        match script {
+            Some(path) => Command::Run(path.clone(), options),
+            None if args.len() == 1 => Command::Prompt,
+            None => usage(),
+        }
+    }
+}
+
+fn run_command(command: Command) -> Result<(), LoxErr> {
+    match command {
+        Command::Lsp => lsp::run_stdio()?,
+        Command::Dap => dap::run_stdio()?,
+        Command::Debug(path) => debug_file(&path)?,
+        Command::Check(path) => {
+            if check_file(&path)? > 0 {
+                exit(1)
+            }
+        }
+        Command::Lint(path) => {
+            if lint_file(&path)? > 0 {
+                exit(1)
+            }
+        }
+        Command::Run(path, options) => run_file(&path, &options)?,
+        Command::Prompt => run_prompt()?,
     }
     Ok(())
 }
@@ -0,0 +1,30 @@
+use crate::environment::Environment;
+use std::cell::RefCell;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+/// A script loaded by `import`. Its globals are its exports, reached as
+/// `alias.name` from the importing script.
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub path: PathBuf,
+    pub env: Rc<RefCell<Environment>>,
+}
+
+impl Module {
+    /// The file name, for messages about the module.
+    pub fn name(&self) -> String {
+        match self.path.file_name() {
+            Some(name) => name.to_string_lossy().into_owned(),
+            None => self.path.display().to_string(),
+        }
+    }
+}
+
+/// Every import of the same file shares one module, so two modules are
+/// equal only if they are the same one.
+impl PartialEq for Module {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.env, &other.env)
+    }
+}
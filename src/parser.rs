@@ -1,143 +1,307 @@
+use crate::environment::{constant_error, redeclare_error};
 use crate::expr::*;
-use crate::statement;
-use crate::statement::Statement;
+use crate::integer::Integer;
+use crate::statement::{Arm, Catch, Pattern, Statement, Variant};
+use crate::types::Type;
 use crate::LiteralValue;
 use crate::LoxErr;
+use crate::Span;
+use crate::SyntaxError;
 use crate::Token;
 use crate::TokenType;
 use core::panic;
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::slice::Iter;
 
 pub struct Parser {
     tokens: Vec<Token>,
     current: usize,
+    errors: Vec<LoxErr>,
+    /// The names declared in each enclosing block, with where they were
+    /// declared if they are constants.
+    scopes: Vec<HashMap<String, Option<Span>>>,
+    /// How many loops the statement being parsed is inside, for `break`.
+    /// Counted from the innermost function, since `break` can't leave one.
+    loops: usize,
+    /// How many functions the statement being parsed is inside, for
+    /// `return`.
+    functions: usize,
+    /// How many `try` statements the statement being parsed is inside,
+    /// counted from the innermost function, since `yield` can't pause one.
+    tries: usize,
+    /// Whether the function being parsed has a `yield`, which makes it a
+    /// generator.
+    generator: bool,
+    /// How many statements and expressions the one being parsed is inside,
+    /// kept under `MAX_NESTING`.
+    depth: usize,
+}
+
+/// How deeply statements and expressions may nest, so that a long run of
+/// `(` or `{` is a syntax error rather than running out of native stack.
+const MAX_NESTING: usize = 256;
+
+/// The parameters of a function with the types they were annotated with,
+/// and the type annotated after the `)`.
+struct Signature {
+    params: Vec<Token>,
+    annotations: Vec<Option<Type>>,
+    returns: Option<Type>,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
-        Parser { tokens, current: 0 }
+        // Doc comments only mean something just before a declaration, so
+        // the others are dropped here instead of being allowed everywhere in
+        // the grammar.
+        let tokens = tokens
+            .iter()
+            .enumerate()
+            .filter(|(i, token)| {
+                token.token_type != TokenType::DOCCOMMENT
+                    || tokens[*i..]
+                        .iter()
+                        .find(|t| t.token_type != TokenType::DOCCOMMENT)
+                        .is_some_and(|t| matches!(t.token_type, TokenType::VAR | TokenType::CONST))
+            })
+            .map(|(_, token)| token.clone())
+            .collect();
+        Parser {
+            tokens,
+            current: 0,
+            errors: Vec::new(),
+            scopes: vec![HashMap::new()],
+            loops: 0,
+            functions: 0,
+            tries: 0,
+            generator: false,
+            depth: 0,
+        }
     }
 
+    /// Parses every statement it can. Statements with syntax errors are
+    /// skipped and the errors are kept for `errors()`.
     pub fn parse(&mut self) -> Vec<Statement> {
         let mut statements: Vec<Statement> = Vec::new();
-        let mut errors: Vec<LoxErr> = Vec::new();
         while !self.is_at_end() {
             let statement = self.statement();
             match statement {
                 Ok(stmt) => statements.push(stmt),
                 Err(e) => {
-                    println!("{}", e);
-                    errors.push(e)
+                    self.errors.push(e);
+                    self.synchronize();
                 }
             }
         }
         statements
     }
 
+    pub fn errors(&self) -> &[LoxErr] {
+        &self.errors
+    }
+
+    /// Hands over the errors found so far.
+    pub fn take_errors(&mut self) -> Vec<LoxErr> {
+        std::mem::take(&mut self.errors)
+    }
+
+    /// Parses a single expression that must make up the whole input.
+    pub fn parse_expression(&mut self) -> Result<Expr, LoxErr> {
+        let expr = self.expression()?;
+        if !self.is_at_end() {
+            let token = self.peek();
+            return Err(self.error(&token, "Expected end of expression"));
+        }
+        Ok(expr)
+    }
+
     fn statement(&mut self) -> Result<Statement, LoxErr> {
+        self.nested(Self::declaration)
+    }
+
+    /// Runs `parse` one level deeper, failing at the next token if that
+    /// goes past `MAX_NESTING`.
+    fn nested<T>(
+        &mut self,
+        parse: impl FnOnce(&mut Self) -> Result<T, LoxErr>,
+    ) -> Result<T, LoxErr> {
+        if self.depth == MAX_NESTING {
+            let token = self.peek();
+            return Err(self.error(&token, "Too deeply nested"));
+        }
+        self.depth += 1;
+        let result = parse(self);
+        self.depth -= 1;
+        result
+    }
+
+    fn declaration(&mut self) -> Result<Statement, LoxErr> {
+        let doc = self.doc_comment();
         let variac = vec![TokenType::PRINT];
         if self.match_token(&variac) {
-            return Ok(self.print_statement()?);
+            return self.print_statement();
         }
         let variac = vec![TokenType::VAR];
         if self.match_token(&variac) {
-            return Ok(self.assignment_statement()?);
+            return self.assignment_statement(doc, false);
+        }
+        let variac = vec![TokenType::CONST];
+        if self.match_token(&variac) {
+            return self.assignment_statement(doc, true);
+        }
+        let variac = vec![TokenType::IMPORT];
+        if self.match_token(&variac) {
+            return self.import_statement();
+        }
+        let variac = vec![TokenType::THROW];
+        if self.match_token(&variac) {
+            return self.throw_statement();
+        }
+        let variac = vec![TokenType::TRY];
+        if self.match_token(&variac) {
+            self.tries += 1;
+            let statement = self.try_statement();
+            self.tries -= 1;
+            return statement;
+        }
+        let variac = vec![TokenType::BREAK];
+        if self.match_token(&variac) {
+            return self.break_statement();
+        }
+        let variac = vec![TokenType::RETURN];
+        if self.match_token(&variac) {
+            return self.return_statement();
+        }
+        let variac = vec![TokenType::YIELD];
+        if self.match_token(&variac) {
+            return self.yield_statement();
+        }
+        let variac = vec![TokenType::MATCH];
+        if self.match_token(&variac) {
+            return self.match_statement();
+        }
+        let variac = vec![TokenType::ENUM];
+        if self.match_token(&variac) {
+            return self.enum_declaration();
         }
         let variac = vec![TokenType::LEFTBRACE];
         if self.match_token(&variac) {
-            return Ok(self.block_statement()?);
+            return self.block_statement();
         }
         let variac = vec![TokenType::ASSERT];
         if self.match_token(&variac) {
-            return Ok(self.assert_statement()?);
+            return self.assert_statement();
         }
         let variac = vec![TokenType::IF];
         if self.match_token(&variac) {
-            return Ok(self.if_statement()?);
+            return self.if_statement();
         }
         let variac = vec![TokenType::WHILE];
         if self.match_token(&variac) {
-            return Ok(self.while_statement()?);
+            return self.while_statement();
         }
         let variac = vec![TokenType::FOR];
         if self.match_token(&variac) {
-            return Ok(self.for_loop()?);
+            return self.for_loop();
         }
-        Ok(self.expression_statement()?)
+        self.expression_statement()
     }
 
     fn for_loop(&mut self) -> Result<Statement, LoxErr> {
+        let span = self.previous().span();
         self.consume(TokenType::LEFTPAREN, "Expected '(' after for")?;
-        Ok(self.for_loop_init()?)
+        self.scopes.push(HashMap::new());
+        let in_ahead = matches!(
+            self.tokens.get(self.current + 1).map(|t| &t.token_type),
+            Some(TokenType::IN)
+        );
+        let result = match self.check(&TokenType::IDENTIFIER) && in_ahead {
+            true => self.for_in(span),
+            false => self.for_loop_init(span),
+        };
+        self.scopes.pop();
+        result
     }
 
-    fn for_loop_init(&mut self) -> Result<Statement, LoxErr> {
-        let mut init;
-        let variac = vec![TokenType::SEMICOLON];
-        if self.match_token(&variac) {
-            init = None;
-        }
-        let variac = vec![TokenType::VAR];
-        if self.match_token(&variac) {
-            init = Some(self.declaration()?);
-        } else {
-            let expr = Some(self.expression_statement()?);
-            init = expr;
+    /// `for (name in iterable) body`, after the `(`.
+    fn for_in(&mut self, span: Span) -> Result<Statement, LoxErr> {
+        let variable = self.advance();
+        self.advance();
+        let iterable = self.expression()?;
+        self.consume(TokenType::RIGHTPAREN, "Expected ')' after iterable")?;
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(variable.lexeme.clone(), None);
         }
+        let body = self.loop_body()?;
+        Ok(Statement::ForIn {
+            variable,
+            iterable,
+            body: Box::new(body),
+            span,
+        })
+    }
 
-        println!("VAR {:?}", init);
-
-        let condition;
-        if !self.check(&TokenType::SEMICOLON) {
-            condition = Some(self.expression()?);
+    fn for_loop_init(&mut self, span: Span) -> Result<Statement, LoxErr> {
+        let variac = vec![TokenType::SEMICOLON];
+        let var_variac = vec![TokenType::VAR, TokenType::CONST];
+        let doc = self.doc_comment();
+        let init = if self.match_token(&variac) {
+            None
+        } else if self.match_token(&var_variac) {
+            let constant = self.previous().token_type == TokenType::CONST;
+            Some(self.assignment_statement(doc, constant)?)
         } else {
-            condition = None
-        }
+            Some(self.expression_statement()?)
+        };
 
-        println!("Condition {:?}", condition);
+        let condition = if !self.check(&TokenType::SEMICOLON) {
+            Some(self.expression()?)
+        } else {
+            None
+        };
 
         self.consume(TokenType::SEMICOLON, "Expected ; after condition")?;
 
-        let increment;
-        if !self.check(&TokenType::RIGHTPAREN) {
-            increment = Some(self.expression()?);
+        let increment = if !self.check(&TokenType::RIGHTPAREN) {
+            Some(self.expression()?)
         } else {
-            increment = None
-        }
+            None
+        };
         self.consume(TokenType::RIGHTPAREN, "Expected ) after condition")?;
 
-        println!("Increment {:?}", increment);
-
-        let mut body = self.statement()?;
+        let mut body = self.loop_body()?;
 
-        if increment.is_some() {
+        if let Some(increment) = increment {
             body = Statement::Block {
                 statements: vec![
-                    *Box::new(body),
+                    body,
                     Statement::Expression {
-                        expression: *Box::new(increment.unwrap()),
+                        expression: increment,
+                        span,
                     },
                 ],
+                span,
             };
         }
 
-        let cond;
-        match condition {
-            None => {
-                cond = Expr::LiteralExpr {
-                    literal: LiteralValue::True,
-                }
-            }
-            Some(c) => cond = c,
-        }
+        let cond = match condition {
+            None => Expr::LiteralExpr {
+                literal: LiteralValue::True,
+            },
+            Some(c) => c,
+        };
 
         body = Statement::While {
             predicate: cond,
             happy_path: Box::new(body),
+            span,
         };
 
-        if init.is_some() {
+        if let Some(init) = init {
             body = Statement::Block {
-                statements: vec![*Box::new(init.unwrap()), *Box::new(body)],
+                statements: vec![init, body],
+                span,
             };
         }
 
@@ -145,17 +309,27 @@ impl Parser {
     }
 
     fn while_statement(&mut self) -> Result<Statement, LoxErr> {
+        let span = self.previous().span();
         self.consume(TokenType::LEFTPAREN, "Expected '(' after while")?;
         let expr = self.expression()?;
         self.consume(TokenType::RIGHTPAREN, "Expected ')' after condition")?;
-        let happy_path = self.statement()?;
+        let happy_path = self.loop_body()?;
         Ok(Statement::While {
             predicate: expr,
             happy_path: Box::new(happy_path),
+            span,
         })
     }
 
+    fn loop_body(&mut self) -> Result<Statement, LoxErr> {
+        self.loops += 1;
+        let body = self.statement();
+        self.loops -= 1;
+        body
+    }
+
     fn if_statement(&mut self) -> Result<Statement, LoxErr> {
+        let span = self.previous().span();
         self.consume(TokenType::LEFTPAREN, "Expected '(' after if")?;
         let expr = self.expression()?;
         self.consume(TokenType::RIGHTPAREN, "Expected ')' after if")?;
@@ -170,50 +344,82 @@ impl Parser {
             conditional: expr,
             happy_path: Box::new(happy_path),
             sad_path: els,
+            span,
         })
     }
 
     fn block_statement(&mut self) -> Result<Statement, LoxErr> {
+        self.scopes.push(HashMap::new());
+        let block = self.block_body();
+        self.scopes.pop();
+        block
+    }
+
+    fn block_body(&mut self) -> Result<Statement, LoxErr> {
+        let span = self.previous().span();
+        let statements = self.block_statements()?;
+        Ok(Statement::Block { statements, span })
+    }
+
+    /// The statements of a block and its `}`, after the `{`.
+    fn block_statements(&mut self) -> Result<Vec<Statement>, LoxErr> {
         let mut statements = vec![];
         while !self.check(&TokenType::RIGHTBRACE) && !self.is_at_end() {
-            let decl = self.declaration()?;
+            let decl = self.statement()?;
             statements.push(decl);
         }
         self.consume(TokenType::RIGHTBRACE, "Expected } after {")?;
-        Ok(Statement::Block { statements })
+        Ok(statements)
     }
 
     fn print_statement(&mut self) -> Result<Statement, LoxErr> {
+        let span = self.previous().span();
         let expr = self.expression()?;
         self.consume(TokenType::SEMICOLON, "Expected ; after statement")?;
-        Ok(Statement::Print { expression: expr })
+        Ok(Statement::Print {
+            expression: expr,
+            span,
+        })
     }
 
     fn assert_statement(&mut self) -> Result<Statement, LoxErr> {
+        let span = self.previous().span();
         let val1 = self.expression()?;
         self.consume(
             TokenType::SEMICOLON,
             "Expected ';' after variable declaration",
         )?;
-        Ok(Statement::Assert { expression_a: val1 })
+        Ok(Statement::Assert {
+            expression_a: val1,
+            span,
+        })
     }
 
-    fn declaration(&mut self) -> Result<Statement, LoxErr> {
-        if self.match_token(&vec![TokenType::VAR]) {
-            match self.assignment_statement() {
-                Ok(stmt) => Ok(stmt),
-                Err(msg) => {
-                    // self.synchronize();
-                    Err(msg)
-                }
+    /// Consumes the doc comment lines before a declaration, if there are
+    /// any, and joins them.
+    fn doc_comment(&mut self) -> Option<String> {
+        let mut lines = vec![];
+        while self.match_token(&vec![TokenType::DOCCOMMENT]) {
+            if let Some(LiteralValue::StringValue(line)) = self.previous().literal {
+                lines.push(line);
             }
-        } else {
-            self.statement()
+        }
+        match lines.is_empty() {
+            true => None,
+            false => Some(lines.join("\n")),
         }
     }
 
-    fn assignment_statement(&mut self) -> Result<Statement, LoxErr> {
+    fn assignment_statement(
+        &mut self,
+        doc: Option<String>,
+        constant: bool,
+    ) -> Result<Statement, LoxErr> {
         let token = self.consume(TokenType::IDENTIFIER, "Expected Variable Name")?;
+        let annotation = match self.match_token(&vec![TokenType::COLON]) {
+            true => Some(self.type_annotation()?),
+            false => None,
+        };
 
         if self.match_token(&vec![TokenType::EQUAL]) {
             let initializer = self.expression()?;
@@ -222,22 +428,342 @@ impl Parser {
                 "Expected ';' after variable declaration",
             )?;
 
+            self.declare(&token, constant);
             return Ok(Statement::Var {
-                indentifier: token.lexeme,
+                indentifier: token.lexeme.clone(),
                 expression: initializer,
+                span: token.span(),
+                doc,
+                constant,
+                annotation,
+            });
+        }
+        let token = self.peek();
+        Err(self.error(&token, "Expected '=' after variable name"))
+    }
+
+    /// `name | name ...`, where each name may be followed by `?` to also
+    /// allow nil.
+    fn type_annotation(&mut self) -> Result<Type, LoxErr> {
+        let mut annotation = Type::NONE;
+        loop {
+            let token = self.peek();
+            if !self.match_token(&vec![TokenType::IDENTIFIER, TokenType::NIL]) {
+                return Err(self.error(&token, "Expected a type"));
+            }
+            match Type::named(&token.lexeme) {
+                Some(named) => annotation = annotation.union(named),
+                None => return Err(self.error(&token, "Unknown type")),
+            }
+            if self.match_token(&vec![TokenType::QUESTION]) {
+                annotation = annotation.union(Type::NIL);
+            }
+            if !self.match_token(&vec![TokenType::PIPE]) {
+                return Ok(annotation);
+            }
+        }
+    }
+
+    fn throw_statement(&mut self) -> Result<Statement, LoxErr> {
+        let span = self.previous().span();
+        let expression = self.expression()?;
+        self.consume(TokenType::SEMICOLON, "Expected ';' after thrown value")?;
+        Ok(Statement::Throw { expression, span })
+    }
+
+    fn try_statement(&mut self) -> Result<Statement, LoxErr> {
+        let span = self.previous().span();
+        self.consume(TokenType::LEFTBRACE, "Expected '{' after try")?;
+        let body = self.block_statement()?;
+        let mut catch = None;
+        if self.match_token(&vec![TokenType::CATCH]) {
+            self.consume(TokenType::LEFTPAREN, "Expected '(' after catch")?;
+            let name = self.consume(TokenType::IDENTIFIER, "Expected error name")?;
+            self.consume(TokenType::RIGHTPAREN, "Expected ')' after error name")?;
+            self.consume(TokenType::LEFTBRACE, "Expected '{' after catch")?;
+            self.scopes
+                .push(HashMap::from([(name.lexeme.clone(), None)]));
+            let body = self.block_statement();
+            self.scopes.pop();
+            catch = Some(Catch {
+                name: name.lexeme.clone(),
+                span: name.span(),
+                body: Box::new(body?),
             });
         }
-        panic!("Cannot reach here for now")
+        let mut finally = None;
+        if self.match_token(&vec![TokenType::FINALLY]) {
+            self.consume(TokenType::LEFTBRACE, "Expected '{' after finally")?;
+            finally = Some(Box::new(self.block_statement()?));
+        }
+        if catch.is_none() && finally.is_none() {
+            let token = self.peek();
+            return Err(self.error(&token, "Expected catch or finally after try"));
+        }
+        Ok(Statement::Try {
+            body: Box::new(body),
+            catch,
+            finally,
+            span,
+        })
+    }
+
+    fn break_statement(&mut self) -> Result<Statement, LoxErr> {
+        let token = self.previous();
+        if self.loops == 0 {
+            return Err(self.error(&token, "Can't use 'break' outside of a loop"));
+        }
+        self.consume(TokenType::SEMICOLON, "Expected ';' after break")?;
+        Ok(Statement::Break { span: token.span() })
+    }
+
+    fn return_statement(&mut self) -> Result<Statement, LoxErr> {
+        let token = self.previous();
+        if self.functions == 0 {
+            return Err(self.error(&token, "Can't return from top-level code"));
+        }
+        let mut value = None;
+        if !self.check(&TokenType::SEMICOLON) {
+            value = Some(self.expression()?);
+        }
+        self.consume(TokenType::SEMICOLON, "Expected ';' after return value")?;
+        Ok(Statement::Return {
+            value,
+            span: token.span(),
+        })
+    }
+
+    fn yield_statement(&mut self) -> Result<Statement, LoxErr> {
+        let token = self.previous();
+        if self.functions == 0 {
+            return Err(self.error(&token, "Can't yield from top-level code"));
+        }
+        if self.tries > 0 {
+            return Err(self.error(&token, "Can't yield inside 'try'"));
+        }
+        let mut value = None;
+        if !self.check(&TokenType::SEMICOLON) {
+            value = Some(self.expression()?);
+        }
+        self.consume(TokenType::SEMICOLON, "Expected ';' after yield value")?;
+        self.generator = true;
+        Ok(Statement::Yield {
+            value,
+            span: token.span(),
+        })
+    }
+
+    /// `enum Name { A, B(field, ...), }`. The name is a constant, like one
+    /// declared with `const`.
+    fn enum_declaration(&mut self) -> Result<Statement, LoxErr> {
+        let name = self.consume(TokenType::IDENTIFIER, "Expected enum name")?;
+        self.consume(TokenType::LEFTBRACE, "Expected '{' after enum name")?;
+        let mut variants: Vec<Variant> = vec![];
+        while !self.check(&TokenType::RIGHTBRACE) && !self.is_at_end() {
+            let variant = self.consume(TokenType::IDENTIFIER, "Expected variant name")?;
+            if variants.iter().any(|v| v.name == variant.lexeme) {
+                return Err(self.error(&variant, "Variant is declared twice"));
+            }
+            let mut fields = vec![];
+            if self.match_token(&vec![TokenType::LEFTPAREN]) {
+                loop {
+                    let field = self.consume(TokenType::IDENTIFIER, "Expected field name")?;
+                    if fields.contains(&field.lexeme) {
+                        return Err(self.error(&field, "Field is declared twice"));
+                    }
+                    fields.push(field.lexeme);
+                    if !self.match_token(&vec![TokenType::COMMA]) {
+                        break;
+                    }
+                }
+                self.consume(TokenType::RIGHTPAREN, "Expected ')' after fields")?;
+            }
+            variants.push(Variant {
+                span: variant.span(),
+                name: variant.lexeme,
+                fields,
+            });
+            if !self.match_token(&vec![TokenType::COMMA]) {
+                break;
+            }
+        }
+        self.consume(TokenType::RIGHTBRACE, "Expected '}' after variants")?;
+        self.declare(&name, true);
+        Ok(Statement::Enum {
+            span: name.span(),
+            name: name.lexeme,
+            variants,
+        })
+    }
+
+    fn match_statement(&mut self) -> Result<Statement, LoxErr> {
+        let span = self.previous().span();
+        self.consume(TokenType::LEFTPAREN, "Expected '(' after match")?;
+        let subject = self.expression()?;
+        self.consume(TokenType::RIGHTPAREN, "Expected ')' after match subject")?;
+        self.consume(TokenType::LEFTBRACE, "Expected '{' before match arms")?;
+        let mut arms = vec![];
+        while !self.check(&TokenType::RIGHTBRACE) && !self.is_at_end() {
+            self.scopes.push(HashMap::new());
+            let arm = self.arm();
+            self.scopes.pop();
+            arms.push(arm?);
+        }
+        self.consume(TokenType::RIGHTBRACE, "Expected '}' after match arms")?;
+        Ok(Statement::Match {
+            subject,
+            arms,
+            span,
+        })
+    }
+
+    fn arm(&mut self) -> Result<Arm, LoxErr> {
+        let span = self.peek().span();
+        let pattern = self.pattern()?;
+        let guard = match self.match_token(&vec![TokenType::IF]) {
+            true => Some(self.expression()?),
+            false => None,
+        };
+        self.consume(TokenType::FATARROW, "Expected '=>' after pattern")?;
+        let body = self.arm_body()?;
+        Ok(Arm {
+            pattern,
+            guard,
+            body: Box::new(body),
+            span,
+        })
+    }
+
+    /// A block, or a `print` or expression ended by `,`, `;` or the end of
+    /// the `match`. A comma after a block is optional.
+    fn arm_body(&mut self) -> Result<Statement, LoxErr> {
+        if self.match_token(&vec![TokenType::LEFTBRACE]) {
+            let block = self.block_statement()?;
+            self.match_token(&vec![TokenType::COMMA]);
+            return Ok(block);
+        }
+        let span = self.peek().span();
+        let body = match self.match_token(&vec![TokenType::PRINT]) {
+            true => Statement::Print {
+                expression: self.expression()?,
+                span,
+            },
+            false => Statement::Expression {
+                expression: self.expression()?,
+                span,
+            },
+        };
+        if !self.match_token(&vec![TokenType::COMMA, TokenType::SEMICOLON])
+            && !self.check(&TokenType::RIGHTBRACE)
+        {
+            let token = self.peek();
+            return Err(self.error(&token, "Expected ',' after match arm"));
+        }
+        Ok(body)
+    }
+
+    fn pattern(&mut self) -> Result<Pattern, LoxErr> {
+        if self.match_token(&vec![TokenType::IDENTIFIER]) {
+            let name = self.previous();
+            if self.match_token(&vec![TokenType::DOT]) {
+                return self.variant_pattern(name.lexeme);
+            }
+            if name.lexeme == "_" {
+                return Ok(Pattern::Wildcard);
+            }
+            if let Some(scope) = self.scopes.last_mut() {
+                scope.insert(name.lexeme.clone(), None);
+            }
+            return Ok(Pattern::Binding {
+                span: name.span(),
+                name: name.lexeme,
+            });
+        }
+        let start = self.pattern_literal()?;
+        let inclusive = match self.match_token(&vec![TokenType::DOTDOT, TokenType::DOTDOTEQUAL]) {
+            true => self.previous().token_type == TokenType::DOTDOTEQUAL,
+            false => return Ok(Pattern::Literal(start)),
+        };
+        let end = self.pattern_literal()?;
+        Ok(Pattern::Range {
+            start,
+            end,
+            inclusive,
+        })
+    }
+
+    /// The rest of `Enum.Variant` or `Enum.Variant(pattern, ...)`.
+    fn variant_pattern(&mut self, enum_name: String) -> Result<Pattern, LoxErr> {
+        let variant = self.consume(TokenType::IDENTIFIER, "Expected variant name after '.'")?;
+        let mut fields = None;
+        if self.match_token(&vec![TokenType::LEFTPAREN]) {
+            let mut patterns = vec![];
+            if !self.check(&TokenType::RIGHTPAREN) {
+                loop {
+                    patterns.push(self.pattern()?);
+                    if !self.match_token(&vec![TokenType::COMMA]) {
+                        break;
+                    }
+                }
+            }
+            self.consume(TokenType::RIGHTPAREN, "Expected ')' after payload patterns")?;
+            fields = Some(patterns);
+        }
+        Ok(Pattern::Variant {
+            enum_name,
+            variant: variant.lexeme,
+            fields,
+        })
+    }
+
+    /// A literal in a pattern, where numbers may have a leading `-`.
+    fn pattern_literal(&mut self) -> Result<LiteralValue, LoxErr> {
+        let negative = self.match_token(&vec![TokenType::MINUS]);
+        let token = self.advance();
+        match (&token.token_type, token.literal.clone(), negative) {
+            (TokenType::NUMBER, Some(LiteralValue::IValue(x)), true) => {
+                Ok(LiteralValue::IValue(x.neg()))
+            }
+            (TokenType::NUMBER, Some(LiteralValue::FValue(x)), true) => {
+                Ok(LiteralValue::FValue(-x))
+            }
+            (TokenType::NUMBER | TokenType::STRINGLIT, Some(literal), false) => Ok(literal),
+            (TokenType::TRUE, _, false) => Ok(LiteralValue::True),
+            (TokenType::FALSE, _, false) => Ok(LiteralValue::False),
+            (TokenType::NIL, _, false) => Ok(LiteralValue::Nil),
+            _ => Err(self.error(&token, "Expected a pattern")),
+        }
+    }
+
+    fn import_statement(&mut self) -> Result<Statement, LoxErr> {
+        let path = self.consume(TokenType::STRINGLIT, "Expected module path after import")?;
+        self.consume(TokenType::AS, "Expected 'as' after module path")?;
+        let name = self.consume(TokenType::IDENTIFIER, "Expected module name after 'as'")?;
+        self.consume(TokenType::SEMICOLON, "Expected ';' after import")?;
+        self.declare(&name, false);
+        let path = match path.literal {
+            Some(LiteralValue::StringValue(path)) => path,
+            _ => path.lexeme,
+        };
+        Ok(Statement::Import {
+            path,
+            span: name.span(),
+            name: name.lexeme,
+        })
     }
 
     fn expression_statement(&mut self) -> Result<Statement, LoxErr> {
+        let span = self.peek().span();
         let expr = self.expression()?;
         self.consume(TokenType::SEMICOLON, "Expected ; after statement")?;
-        Ok(Statement::Expression { expression: expr })
+        Ok(Statement::Expression {
+            expression: expr,
+            span,
+        })
     }
 
     fn expression(&mut self) -> Result<Expr, LoxErr> {
-        Ok(self.assignment()?)
+        self.nested(Self::assignment)
     }
 
     fn or(&mut self) -> Result<Expr, LoxErr> {
@@ -269,24 +795,137 @@ impl Parser {
     }
 
     fn assignment(&mut self) -> Result<Expr, LoxErr> {
-        let expr = self.or()?;
+        let expr = self.conditional()?;
         let variac = vec![TokenType::EQUAL];
+        let compound = vec![
+            TokenType::PLUSEQUAL,
+            TokenType::MINUSEQUAL,
+            TokenType::STAREQUAL,
+            TokenType::SLASHEQUAL,
+            TokenType::PERCENTEQUAL,
+        ];
+        if self.match_token(&compound) {
+            let operator = self.previous();
+            let value = self.expression()?;
+            return self.update(expr, &operator, value, false);
+        }
         if self.match_token(&variac) {
-            let _ = self.previous();
-            let value = self.assignment()?;
+            let equals = self.previous();
+            let value = self.expression()?;
 
             match expr {
-                Expr::Var { identifier } => Ok(Expr::Assignment {
-                    name: identifier,
-                    value: Box::new(value),
-                }),
-                _ => Err("Invalid assignment target".into()),
+                Expr::Var { identifier, span } => {
+                    self.check_assignable(&identifier, span);
+                    Ok(Expr::Assignment {
+                        name: identifier,
+                        value: Box::new(value),
+                        span,
+                    })
+                }
+                _ => Err(self.error(&equals, "Invalid assignment target")),
             }
         } else {
             Ok(expr)
         }
     }
 
+    /// `condition ? then : otherwise`, right-associative so that chains read
+    /// like `else if`.
+    fn conditional(&mut self) -> Result<Expr, LoxErr> {
+        let condition = self.coalesce()?;
+        if self.match_token(&vec![TokenType::QUESTION]) {
+            let question = self.previous();
+            let then_branch = self.expression()?;
+            self.consume(TokenType::COLON, "Expect ':' in conditional expression")?;
+            let else_branch = self.nested(Self::conditional)?;
+            return Ok(Expr::Conditional {
+                condition: Box::new(condition),
+                then_branch: Box::new(then_branch),
+                else_branch: Box::new(else_branch),
+                span: question.span(),
+            });
+        }
+        Ok(condition)
+    }
+
+    fn coalesce(&mut self) -> Result<Expr, LoxErr> {
+        let expr = self.or()?;
+        if self.match_token(&vec![TokenType::QUESTIONQUESTION]) {
+            let op = self.previous();
+            let right = self.coalesce()?;
+            return Ok(Expr::Coalesce {
+                left: Box::new(expr),
+                right: Box::new(right),
+                span: op.span(),
+            });
+        }
+        Ok(expr)
+    }
+
+    /// Reports an assignment to a name whose nearest declaration is a
+    /// constant, without stopping the parse, since the statement is still
+    /// well formed. Names declared outside the script, such as in an earlier
+    /// REPL line, are left for `Environment::assign` to check.
+    fn check_assignable(&mut self, name: &str, span: Span) {
+        let declared = self.scopes.iter().rev().find_map(|scope| scope.get(name));
+        if let Some(Some(declared)) = declared {
+            let error = constant_error(name, *declared, span);
+            self.errors.push(error);
+        }
+    }
+
+    /// Adds `name` to the innermost scope, reporting it if that scope
+    /// already has a constant of the same name.
+    fn declare(&mut self, name: &Token, constant: bool) {
+        let Some(scope) = self.scopes.last_mut() else {
+            return;
+        };
+        let declared = constant.then_some(name.span());
+        if let Some(Some(previous)) = scope.insert(name.lexeme.clone(), declared) {
+            scope.insert(name.lexeme.clone(), Some(previous));
+            let error = redeclare_error(&name.lexeme, previous, name.span());
+            self.errors.push(error);
+        }
+    }
+
+    /// Builds an `Expr::Update` from a compound assignment or `++`/`--`
+    /// token, which must follow or precede a variable.
+    fn update(
+        &mut self,
+        target: Expr,
+        operator: &Token,
+        value: Expr,
+        postfix: bool,
+    ) -> Result<Expr, LoxErr> {
+        let (token_type, lexeme) = match operator.token_type {
+            TokenType::PLUSEQUAL | TokenType::PLUSPLUS => (TokenType::PLUS, "+"),
+            TokenType::MINUSEQUAL | TokenType::MINUSMINUS => (TokenType::MINUS, "-"),
+            TokenType::STAREQUAL => (TokenType::STAR, "*"),
+            TokenType::SLASHEQUAL => (TokenType::SLASH, "/"),
+            _ => (TokenType::PERCENT, "%"),
+        };
+        let op = Token::new(
+            token_type,
+            lexeme.to_string(),
+            None,
+            operator.line_number,
+            operator.column,
+        );
+        match target {
+            Expr::Var { identifier, span } => {
+                self.check_assignable(&identifier, span);
+                Ok(Expr::Update {
+                    name: identifier,
+                    op,
+                    value: Box::new(value),
+                    postfix,
+                    span,
+                })
+            }
+            _ => Err(self.error(operator, "Invalid assignment target")),
+        }
+    }
+
     fn equality(&mut self) -> Result<Expr, LoxErr> {
         let mut expr = self.comparision()?;
         let variac = vec![TokenType::BANGEQUAL, TokenType::EQUALEQUAL];
@@ -334,17 +973,97 @@ impl Parser {
         if self.is_at_end() {
             return false;
         }
-        return self.peek().token_type == *ttype;
+        self.peek().token_type == *ttype
     }
 
     fn comparision(&mut self) -> Result<Expr, LoxErr> {
-        let mut expr = self.term()?;
+        let mut expr = self.range()?;
         let variac = vec![
             TokenType::GREATER,
             TokenType::GREATEREQUAL,
             TokenType::LESS,
             TokenType::LESSEQUAL,
+            TokenType::IS,
+            TokenType::IN,
         ];
+        while self.match_token(&variac) {
+            let operator = self.previous();
+            let right = self.range()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                op: operator,
+                right: Box::new(right),
+            }
+        }
+        Ok(expr)
+    }
+
+    /// `start..end` or `start..=end`, with an optional `by step`. Ranges
+    /// don't chain, so `a..b..c` is an error.
+    fn range(&mut self) -> Result<Expr, LoxErr> {
+        let start = self.bitwise_or()?;
+        if !self.match_token(&vec![TokenType::DOTDOT, TokenType::DOTDOTEQUAL]) {
+            return Ok(start);
+        }
+        let inclusive = self.previous().token_type == TokenType::DOTDOTEQUAL;
+        let end = self.bitwise_or()?;
+        let mut step = None;
+        if self.match_token(&vec![TokenType::BY]) {
+            step = Some(Box::new(self.bitwise_or()?));
+        }
+        Ok(Expr::Range {
+            start: Box::new(start),
+            end: Box::new(end),
+            step,
+            inclusive,
+        })
+    }
+
+    fn bitwise_or(&mut self) -> Result<Expr, LoxErr> {
+        let mut expr = self.bitwise_xor()?;
+        while self.match_token(&vec![TokenType::PIPE]) {
+            let operator = self.previous();
+            let right = self.bitwise_xor()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                op: operator,
+                right: Box::new(right),
+            }
+        }
+        Ok(expr)
+    }
+
+    fn bitwise_xor(&mut self) -> Result<Expr, LoxErr> {
+        let mut expr = self.bitwise_and()?;
+        while self.match_token(&vec![TokenType::CARET]) {
+            let operator = self.previous();
+            let right = self.bitwise_and()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                op: operator,
+                right: Box::new(right),
+            }
+        }
+        Ok(expr)
+    }
+
+    fn bitwise_and(&mut self) -> Result<Expr, LoxErr> {
+        let mut expr = self.shift()?;
+        while self.match_token(&vec![TokenType::AMPERSAND]) {
+            let operator = self.previous();
+            let right = self.shift()?;
+            expr = Expr::Binary {
+                left: Box::new(expr),
+                op: operator,
+                right: Box::new(right),
+            }
+        }
+        Ok(expr)
+    }
+
+    fn shift(&mut self) -> Result<Expr, LoxErr> {
+        let mut expr = self.term()?;
+        let variac = vec![TokenType::LESSLESS, TokenType::GREATERGREATER];
         while self.match_token(&variac) {
             let operator = self.previous();
             let right = self.term()?;
@@ -373,21 +1092,52 @@ impl Parser {
     }
 
     fn unary(&mut self) -> Result<Expr, LoxErr> {
-        if self.match_token(&vec![TokenType::BANG, TokenType::MINUS]) {
+        self.nested(Self::prefix)
+    }
+
+    fn prefix(&mut self) -> Result<Expr, LoxErr> {
+        if self.match_token(&vec![TokenType::PLUSPLUS, TokenType::MINUSMINUS]) {
+            let operator = self.previous();
+            let target = self.unary()?;
+            return self.update(target, &operator, one(), false);
+        }
+        if self.match_token(&vec![TokenType::BANG, TokenType::MINUS, TokenType::TILDE]) {
             let op = self.previous();
             let rhs = self.unary()?;
-            return Ok(Expr::Unary {
+            Ok(Expr::Unary {
                 operator: op,
                 right: Box::new(rhs),
-            });
+            })
         } else {
-            self.primary()
+            self.power()
+        }
+    }
+
+    /// `**` binds tighter than a unary minus on its left, so `-2 ** 2` is
+    /// `-4`, and is right-associative because its right operand can itself
+    /// be a power.
+    fn power(&mut self) -> Result<Expr, LoxErr> {
+        let expr = self.postfix()?;
+        if self.match_token(&vec![TokenType::STARSTAR]) {
+            let operator = self.previous();
+            let right = self.unary()?;
+            return Ok(Expr::Binary {
+                left: Box::new(expr),
+                op: operator,
+                right: Box::new(right),
+            });
         }
+        Ok(expr)
     }
 
     fn factor(&mut self) -> Result<Expr, LoxErr> {
         let mut expr = self.unary()?;
-        let variac = vec![TokenType::SLASH, TokenType::STAR];
+        let variac = vec![
+            TokenType::SLASH,
+            TokenType::STAR,
+            TokenType::PERCENT,
+            TokenType::DIV,
+        ];
         while self.match_token(&variac) {
             let operator = self.previous();
             let right = self.unary()?;
@@ -401,6 +1151,57 @@ impl Parser {
         Ok(expr)
     }
 
+    fn postfix(&mut self) -> Result<Expr, LoxErr> {
+        let mut expr = self.primary()?;
+        let variac = vec![TokenType::DOT, TokenType::LEFTPAREN, TokenType::LEFTBRACKET];
+        while self.match_token(&variac) {
+            if self.previous().token_type == TokenType::LEFTPAREN {
+                expr = self.call(expr)?;
+                continue;
+            }
+            if self.previous().token_type == TokenType::LEFTBRACKET {
+                let index = self.expression()?;
+                self.consume(TokenType::RIGHTBRACKET, "Expected ']' after index")?;
+                expr = Expr::Index {
+                    object: Box::new(expr),
+                    index: Box::new(index),
+                };
+                continue;
+            }
+            let name = self.consume(TokenType::IDENTIFIER, "Expected name after '.'")?;
+            expr = Expr::Get {
+                object: Box::new(expr),
+                span: name.span(),
+                name: name.lexeme,
+            };
+        }
+        if self.match_token(&vec![TokenType::PLUSPLUS, TokenType::MINUSMINUS]) {
+            let operator = self.previous();
+            return self.update(expr, &operator, one(), true);
+        }
+        Ok(expr)
+    }
+
+    /// The arguments of a call, after its `(`.
+    fn call(&mut self, callee: Expr) -> Result<Expr, LoxErr> {
+        let span = self.previous().span();
+        let mut arguments = vec![];
+        if !self.check(&TokenType::RIGHTPAREN) {
+            loop {
+                arguments.push(self.expression()?);
+                if !self.match_token(&vec![TokenType::COMMA]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RIGHTPAREN, "Expected ')' after arguments")?;
+        Ok(Expr::Call {
+            callee: Box::new(callee),
+            arguments,
+            span,
+        })
+    }
+
     fn primary(&mut self) -> Result<Expr, LoxErr> {
         if self.match_token(&vec![TokenType::FALSE]) {
             return Ok(Expr::LiteralExpr {
@@ -418,9 +1219,27 @@ impl Parser {
             });
         }
         if self.match_token(&vec![TokenType::NUMBER, TokenType::STRINGLIT]) {
-            return Ok(Expr::LiteralExpr {
-                literal: self.previous().literal.unwrap(),
-            });
+            let token = self.previous();
+            return match token.literal {
+                Some(literal) => Ok(Expr::LiteralExpr { literal }),
+                None => Err(self.error(&token, "Expected a value for literal")),
+            };
+        }
+        if self.match_token(&vec![TokenType::STRINGPART]) {
+            return self.interpolation();
+        }
+        if self.match_token(&vec![TokenType::FUN]) {
+            let span = self.previous().span();
+            self.consume(TokenType::LEFTPAREN, "Expected '(' after fun")?;
+            let signature = self.signature()?;
+            self.consume(TokenType::LEFTBRACE, "Expected '{' before function body")?;
+            return self.function_body(signature, span, false);
+        }
+        if self.check(&TokenType::LEFTPAREN) && self.arrow_ahead() {
+            let span = self.advance().span();
+            let signature = self.signature()?;
+            self.consume(TokenType::FATARROW, "Expected '=>' after parameters")?;
+            return self.function_body(signature, span, true);
         }
         if self.match_token(&vec![TokenType::LEFTPAREN]) {
             let expr = self.expression()?;
@@ -432,10 +1251,139 @@ impl Parser {
         if self.match_token(&vec![TokenType::IDENTIFIER]) {
             let identifier = self.tokens[self.current - 1].clone();
             return Ok(Expr::Var {
-                identifier: identifier.lexeme,
+                identifier: identifier.lexeme.clone(),
+                span: identifier.span(),
             });
         }
-        panic!("Should never reach this point")
+        let token = self.peek();
+        Err(self.error(&token, "Expected expression"))
+    }
+
+    /// Whether the `(` about to be read starts the parameters of an arrow
+    /// function rather than a grouping: `(a, b: num): str =>`.
+    fn arrow_ahead(&self) -> bool {
+        let mut tokens = self.tokens[self.current + 1..].iter().peekable();
+        let mut expect_name = true;
+        loop {
+            match tokens.next().map(|token| &token.token_type) {
+                Some(TokenType::IDENTIFIER) if expect_name => expect_name = false,
+                Some(TokenType::COLON) if !expect_name => skip_type(&mut tokens),
+                Some(TokenType::COMMA) if !expect_name => expect_name = true,
+                Some(TokenType::RIGHTPAREN) => break,
+                _ => return false,
+            }
+        }
+        if tokens
+            .next_if(|token| token.token_type == TokenType::COLON)
+            .is_some()
+        {
+            skip_type(&mut tokens);
+        }
+        matches!(
+            tokens.next().map(|token| &token.token_type),
+            Some(TokenType::FATARROW)
+        )
+    }
+
+    /// The parameters of a function and its `)`, after the `(`, followed by
+    /// the type it returns if one is given.
+    fn signature(&mut self) -> Result<Signature, LoxErr> {
+        let mut params: Vec<Token> = vec![];
+        let mut annotations = vec![];
+        if !self.check(&TokenType::RIGHTPAREN) {
+            loop {
+                let param = self.consume(TokenType::IDENTIFIER, "Expected parameter name")?;
+                if params.iter().any(|p| p.lexeme == param.lexeme) {
+                    return Err(self.error(&param, "Duplicate parameter"));
+                }
+                params.push(param);
+                annotations.push(match self.match_token(&vec![TokenType::COLON]) {
+                    true => Some(self.type_annotation()?),
+                    false => None,
+                });
+                if !self.match_token(&vec![TokenType::COMMA]) {
+                    break;
+                }
+            }
+        }
+        self.consume(TokenType::RIGHTPAREN, "Expected ')' after parameters")?;
+        let returns = match self.match_token(&vec![TokenType::COLON]) {
+            true => Some(self.type_annotation()?),
+            false => None,
+        };
+        Ok(Signature {
+            params,
+            annotations,
+            returns,
+        })
+    }
+
+    /// The statements of a function, with its parameters in scope. A block
+    /// body's `{` has already been read. An arrow function may instead have
+    /// a single expression, which is returned.
+    fn function_body(
+        &mut self,
+        signature: Signature,
+        span: Span,
+        arrow: bool,
+    ) -> Result<Expr, LoxErr> {
+        let Signature {
+            params,
+            annotations,
+            returns,
+        } = signature;
+        let scope = params.iter().map(|p| (p.lexeme.clone(), None)).collect();
+        self.scopes.push(scope);
+        let loops = std::mem::replace(&mut self.loops, 0);
+        let tries = std::mem::replace(&mut self.tries, 0);
+        let generator = std::mem::replace(&mut self.generator, false);
+        self.functions += 1;
+        let body = match arrow && !self.match_token(&vec![TokenType::LEFTBRACE]) {
+            true => {
+                let span = self.peek().span();
+                self.expression().map(|value| {
+                    vec![Statement::Return {
+                        value: Some(value),
+                        span,
+                    }]
+                })
+            }
+            false => self.block_statements(),
+        };
+        self.functions -= 1;
+        self.loops = loops;
+        self.tries = tries;
+        let generator = std::mem::replace(&mut self.generator, generator);
+        self.scopes.pop();
+        Ok(Expr::Function {
+            params,
+            body: body?,
+            generator,
+            span,
+            annotations,
+            returns,
+        })
+    }
+
+    /// The rest of a string whose first `STRINGPART` has just been matched:
+    /// embedded expressions, each followed by another part or the final
+    /// `STRINGLIT`.
+    fn interpolation(&mut self) -> Result<Expr, LoxErr> {
+        let mut parts = vec![];
+        loop {
+            let literal = self.previous().literal.unwrap();
+            if literal != LiteralValue::StringValue(String::new()) {
+                parts.push(Expr::LiteralExpr { literal });
+            }
+            if self.previous().token_type == TokenType::STRINGLIT {
+                return Ok(Expr::Interpolation { parts });
+            }
+            parts.push(self.expression()?);
+            if !self.match_token(&vec![TokenType::STRINGPART, TokenType::STRINGLIT]) {
+                let token = self.peek();
+                return Err(self.error(&token, "Expect '}' after interpolated expression"));
+            }
+        }
     }
 
     fn consume(&mut self, ttype: TokenType, message: &str) -> Result<Token, LoxErr> {
@@ -443,7 +1391,16 @@ impl Parser {
             self.advance();
             return Ok(self.previous());
         }
-        Err(format!("{message}").into())
+        let token = self.peek();
+        Err(self.error(&token, message))
+    }
+
+    fn error(&self, token: &Token, message: &str) -> LoxErr {
+        let message = match token.token_type {
+            TokenType::EOF => format!("{} at end", message),
+            _ => format!("{} at '{}'", message, token.lexeme),
+        };
+        SyntaxError::new(message, token.span()).into()
     }
 
     fn previous(&mut self) -> Token {
@@ -452,31 +1409,66 @@ impl Parser {
             None => panic!("Undefined token"),
         }
     }
-    #[allow(dead_code)]
     fn synchronize(&mut self) {
         self.advance();
         while !self.is_at_end() {
-            if self.previous().token_type == TokenType::EOF {
+            if self.previous().token_type == TokenType::SEMICOLON {
                 return;
             }
+            match self.peek().token_type {
+                TokenType::CLASS
+                | TokenType::FUN
+                | TokenType::VAR
+                | TokenType::CONST
+                | TokenType::IMPORT
+                | TokenType::THROW
+                | TokenType::TRY
+                | TokenType::MATCH
+                | TokenType::ENUM
+                | TokenType::FOR
+                | TokenType::IF
+                | TokenType::WHILE
+                | TokenType::PRINT
+                | TokenType::RETURN
+                | TokenType::YIELD => return,
+                _ => (),
+            }
+            self.advance();
+        }
+    }
+}
+
+/// The amount `++` and `--` change a variable by.
+fn one() -> Expr {
+    Expr::LiteralExpr {
+        literal: LiteralValue::IValue(Integer::from(1)),
+    }
+}
+
+/// Steps over a type annotation after its `:`, for looking ahead without
+/// parsing.
+fn skip_type(tokens: &mut Peekable<Iter<Token>>) {
+    loop {
+        let name = [TokenType::IDENTIFIER, TokenType::NIL];
+        if tokens
+            .next_if(|token| name.contains(&token.token_type))
+            .is_none()
+        {
+            return;
         }
-        match self.peek().token_type {
-            TokenType::CLASS
-            | TokenType::FUN
-            | TokenType::VAR
-            | TokenType::FOR
-            | TokenType::IF
-            | TokenType::WHILE
-            | TokenType::PRINT
-            | TokenType::RETURN => return,
-            _ => (),
+        tokens.next_if(|token| token.token_type == TokenType::QUESTION);
+        if tokens
+            .next_if(|token| token.token_type == TokenType::PIPE)
+            .is_none()
+        {
+            return;
         }
-        self.advance();
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use crate::statement::Statement;
     use crate::LiteralValue;
     use crate::Scanner;
     use crate::Token;
@@ -492,24 +1484,28 @@ mod tests {
                 lexeme: "1".to_string(),
                 literal: Some(LiteralValue::FValue(1.0)),
                 line_number: 0,
+                column: 0,
             },
             Token {
                 token_type: TokenType::PLUS,
                 lexeme: "+".to_string(),
                 literal: None,
                 line_number: 0,
+                column: 0,
             },
             Token {
                 token_type: TokenType::NUMBER,
                 lexeme: "5".to_string(),
                 literal: Some(LiteralValue::FValue(5.0)),
                 line_number: 0,
+                column: 0,
             },
             Token {
                 token_type: TokenType::SEMICOLON,
                 lexeme: ";".to_string(),
                 literal: None,
                 line_number: 0,
+                column: 0,
             },
         ];
         let mut parser = Parser::new(tokens);
@@ -536,4 +1532,200 @@ mod tests {
         let parsed_expression = parser.expression().unwrap().to_string();
         assert_eq!("(== (+ 1 2) (group (+ 5 7)))", parsed_expression);
     }
+
+    #[test]
+    fn test_interpolation() {
+        let source = r#""count = ${i + 1}, ${"nested ${true}"}!""#;
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let mut expr = Parser::new(tokens).parse_expression().unwrap();
+        assert_eq!(
+            "(interpolate count =  (+ var i  1) ,  (interpolate nested  true) !)",
+            expr.to_string()
+        );
+
+        let mut interpreter = crate::Interpreter::new();
+        interpreter
+            .env()
+            .borrow_mut()
+            .define("i".to_string(), LiteralValue::FValue(2.5));
+        assert_eq!(
+            LiteralValue::StringValue("count = 3.5, nested true!".to_string()),
+            expr.evaluate(&mut interpreter).unwrap()
+        );
+
+        let tokens = Scanner::new(r#""${1 2}""#).scan_tokens().unwrap();
+        let error = Parser::new(tokens).parse_expression().unwrap_err();
+        assert_eq!(
+            "[line 1:6] Error: Expect '}' after interpolated expression at '2'",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let source = "/// The answer.\n/// Computed slowly.\nvar a = 42;\n/// Not attached\nprint a;\n{ /// Inner\n var b = 1; }";
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let statements = parser.parse();
+        assert!(parser.errors().is_empty());
+        let docs: Vec<Option<String>> = statements
+            .iter()
+            .flat_map(|stmt| match stmt {
+                Statement::Block { statements, .. } => statements.clone(),
+                stmt => vec![stmt.clone()],
+            })
+            .filter_map(|stmt| match stmt {
+                Statement::Var { doc, .. } => Some(doc),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            vec![
+                Some("The answer.\nComputed slowly.".to_string()),
+                Some("Inner".to_string())
+            ],
+            docs
+        );
+    }
+
+    #[test]
+    fn test_constants() {
+        let errors = |source: &str| {
+            let tokens = Scanner::new(source).scan_tokens().unwrap();
+            let mut parser = Parser::new(tokens);
+            parser.parse();
+            let errors: Vec<String> = parser.errors().iter().map(|e| e.to_string()).collect();
+            errors
+        };
+        assert_eq!(
+            vec![
+                "[line 2:1] Error: Cannot assign to constant 'a' declared at [line 1:7]",
+                "[line 3:3] Error: Cannot assign to constant 'a' declared at [line 1:7]",
+            ],
+            errors("const a = 1;\na = 2;\n  a += 1;")
+        );
+        assert_eq!(
+            vec!["[line 1:26] Error: Cannot assign to constant 'i' declared at [line 1:12]"],
+            errors("for (const i = 0; i < 1; i++) {}")
+        );
+        assert!(errors("const a = 1; { var a = 2; a = 3; } var b = a;").is_empty());
+        assert_eq!(
+            vec!["[line 1:28] Error: Cannot assign to constant 'a' declared at [line 1:7]"],
+            errors("const a = 1; var f = () => a = 2;")
+        );
+        assert_eq!(
+            vec!["[line 1:8] Error: Expected '=' after variable name at ';'"],
+            errors("const a;")
+        );
+        // The statement around a bad assignment is still parsed.
+        assert_eq!(
+            vec!["[line 1:16] Error: Cannot assign to constant 'a' declared at [line 1:7]"],
+            errors("const a = 1; { a = 2; }")
+        );
+        let tokens = Scanner::new("const a = 1; var f = fun () { a = 3; };")
+            .scan_tokens()
+            .unwrap();
+        let mut parser = Parser::new(tokens);
+        assert_eq!(2, parser.parse().len());
+        assert_eq!(1, parser.errors().len());
+
+        assert_eq!(
+            vec![
+                "[line 1:18] Error: Cannot redeclare constant 'a' declared at [line 1:7]",
+                "[line 2:32] Error: Cannot redeclare constant 'E' declared at [line 2:6]",
+            ],
+            errors("const a = 1; var a = 2;\nenum E { A } import \"e.lox\" as E;")
+        );
+
+        // A constant from an earlier REPL line is only known at runtime.
+        let mut interpreter = crate::Interpreter::new();
+        let mut run = |source: &str| {
+            let tokens = Scanner::new(source).scan_tokens().unwrap();
+            interpreter.interpret(Parser::new(tokens).parse())
+        };
+        run("const c = 1;").unwrap();
+        assert_eq!(
+            "[line 1:3] Error: Cannot assign to constant 'c' declared at [line 1:7]",
+            run("{ c++; }").unwrap_err().to_string()
+        );
+        assert_eq!(
+            "[line 1:5] Error: Cannot redeclare constant 'c' declared at [line 1:7]",
+            run("var c = 2;").unwrap_err().to_string()
+        );
+        run("{ var c = 2; }").unwrap();
+    }
+
+    #[test]
+    fn test_enum_duplicates() {
+        let errors = |source: &str| {
+            let tokens = Scanner::new(source).scan_tokens().unwrap();
+            let mut parser = Parser::new(tokens);
+            parser.parse();
+            let errors: Vec<String> = parser.errors().iter().map(|e| e.to_string()).collect();
+            errors
+        };
+        assert_eq!(
+            vec!["[line 1:13] Error: Variant is declared twice at 'A'"],
+            errors("enum C { A, A }")
+        );
+        assert_eq!(
+            vec!["[line 1:15] Error: Field is declared twice at 'x'"],
+            errors("enum C { A(x, x) }")
+        );
+        assert!(errors("enum C { A(x), B(x) }").is_empty());
+    }
+
+    #[test]
+    fn test_annotations() {
+        let parse = |source: &str| {
+            let tokens = Scanner::new(source).scan_tokens().unwrap();
+            Parser::new(tokens)
+                .parse_expression()
+                .map(|e| e.to_string())
+        };
+        assert_eq!(
+            "(fun a b)",
+            parse("fun (a: num, b: str? | bool): num { }").unwrap()
+        );
+        assert_eq!("(fun x)", parse("(x: num): num? => x * 2").unwrap());
+        assert_eq!(
+            "(? var c  (group var a ) var b )",
+            parse("c ? (a) : b").unwrap()
+        );
+        assert_eq!(
+            "[line 1:10] Error: Unknown type at 'integer'",
+            parse("fun (a): integer { }").unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        // Parsing to the limit needs more native stack than a test thread
+        // has in a debug build.
+        let parser = std::thread::Builder::new()
+            .stack_size(64 * 1024 * 1024)
+            .spawn(|| {
+                let errors = |source: &str| {
+                    let tokens = Scanner::new(source).scan_tokens().unwrap();
+                    let mut parser = Parser::new(tokens);
+                    parser.parse();
+                    let errors: Vec<String> =
+                        parser.errors().iter().map(|e| e.to_string()).collect();
+                    errors
+                };
+                let parens = format!("print {}1{};", "(".repeat(50_000), ")".repeat(50_000));
+                assert_eq!(
+                    vec!["[line 1:134] Error: Too deeply nested at '('"],
+                    errors(&parens)
+                );
+                let blocks = format!("{}{}", "{".repeat(50_000), "}".repeat(50_000));
+                assert_eq!(1, errors(&blocks).len());
+                let negations = format!("print {}1;", "!".repeat(50_000));
+                assert_eq!(1, errors(&negations).len());
+                let nested = format!("print {}1{};", "(".repeat(100), ")".repeat(100));
+                assert!(errors(&nested).is_empty());
+            })
+            .unwrap();
+        parser.join().unwrap();
+    }
 }
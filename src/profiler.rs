@@ -0,0 +1,258 @@
+use crate::environment::Environment;
+use crate::function::Function;
+use crate::interpreter::Hook;
+use crate::statement::Statement;
+use crate::LoxErr;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+#[derive(Default)]
+struct LineStats {
+    hits: u64,
+    /// Time from entering a statement on this line to leaving it, counted
+    /// once even when statements on the same line are nested.
+    total: Duration,
+    /// Time spent in statements on this line but not in their children.
+    own: Duration,
+}
+
+struct Frame {
+    name: String,
+    /// The line of a statement, or `None` for a function call, whose time
+    /// is already counted on the line that made it.
+    line: Option<usize>,
+    start: Instant,
+    children: Duration,
+}
+
+/// Records how often each line runs and where the time goes. Every
+/// statement is a frame, named after its kind and line, and so is every
+/// function call, named after the line the function was written on, so the
+/// folded stacks show which loop, block or function the time was spent
+/// under.
+pub struct Profiler {
+    source: Vec<String>,
+    lines: BTreeMap<usize, LineStats>,
+    folded: HashMap<String, Duration>,
+    stack: Vec<Frame>,
+}
+
+impl Profiler {
+    pub fn new(source: &str) -> Self {
+        Self {
+            source: source.lines().map(|l| l.trim().to_string()).collect(),
+            lines: BTreeMap::new(),
+            folded: HashMap::new(),
+            stack: vec![],
+        }
+    }
+
+    /// Lines ordered from most to least total time.
+    pub fn report(&self) -> String {
+        let mut lines: Vec<(&usize, &LineStats)> = self.lines.iter().collect();
+        lines.sort_by(|a, b| b.1.total.cmp(&a.1.total).then(a.0.cmp(b.0)));
+        let mut report = format!(
+            "{:>6} {:>10} {:>12} {:>12}  source\n",
+            "line", "hits", "total ms", "self ms"
+        );
+        for (line, stats) in lines {
+            let source = self.source.get(line - 1).map(|s| s.as_str()).unwrap_or("");
+            report.push_str(&format!(
+                "{:>6} {:>10} {:>12.3} {:>12.3}  {}\n",
+                line,
+                stats.hits,
+                stats.total.as_secs_f64() * 1000.0,
+                stats.own.as_secs_f64() * 1000.0,
+                source
+            ));
+        }
+        report
+    }
+
+    /// One `frame;frame;frame microseconds` line per distinct stack, the
+    /// format read by flamegraph.pl, inferno and speedscope.
+    pub fn folded(&self) -> String {
+        let mut stacks: Vec<(&String, &Duration)> = self.folded.iter().collect();
+        stacks.sort();
+        stacks
+            .into_iter()
+            .map(|(stack, time)| format!("{} {}\n", stack, time.as_micros()))
+            .collect()
+    }
+
+    /// Ends the innermost frame, charging its time to its line and stack.
+    fn pop(&mut self) {
+        let frame = match self.stack.pop() {
+            Some(frame) => frame,
+            None => return,
+        };
+        let elapsed = frame.start.elapsed();
+        let own = elapsed.saturating_sub(frame.children);
+        if let Some(parent) = self.stack.last_mut() {
+            parent.children += elapsed;
+        }
+
+        if let Some(line) = frame.line {
+            let nested = self.stack.iter().any(|f| f.line == frame.line);
+            let stats = self.lines.entry(line).or_default();
+            stats.own += own;
+            if !nested {
+                stats.total += elapsed;
+            }
+        }
+
+        let mut path = String::from("main");
+        for parent in &self.stack {
+            path.push(';');
+            path.push_str(&parent.name);
+        }
+        path.push(';');
+        path.push_str(&frame.name);
+        *self.folded.entry(path).or_default() += own;
+    }
+}
+
+fn frame_name(stmt: &Statement) -> String {
+    let kind = match stmt {
+        Statement::Expression { .. } => "expression",
+        Statement::Print { .. } => "print",
+        Statement::Var {
+            constant: false, ..
+        } => "var",
+        Statement::Var { constant: true, .. } => "const",
+        Statement::Import { .. } => "import",
+        Statement::Throw { .. } => "throw",
+        Statement::Try { .. } => "try",
+        Statement::Break { .. } => "break",
+        Statement::Return { .. } => "return",
+        Statement::Yield { .. } => "yield",
+        Statement::Match { .. } => "match",
+        Statement::Enum { .. } => "enum",
+        Statement::Assert { .. } => "assert",
+        Statement::Block { .. } => "block",
+        Statement::If { .. } => "if",
+        Statement::While { .. } => "while",
+        Statement::ForIn { .. } => "for",
+    };
+    format!("{}:{}", kind, stmt.span().line)
+}
+
+impl Hook for Profiler {
+    fn before_statement(
+        &mut self,
+        stmt: &Statement,
+        _env: &Rc<RefCell<Environment>>,
+        _depth: usize,
+    ) -> Result<(), LoxErr> {
+        let line = stmt.span().line;
+        self.lines.entry(line).or_default().hits += 1;
+        self.stack.push(Frame {
+            name: frame_name(stmt),
+            line: Some(line),
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+        Ok(())
+    }
+
+    fn after_statement(&mut self, _depth: usize) -> Result<(), LoxErr> {
+        self.pop();
+        Ok(())
+    }
+
+    fn enter_function(&mut self, function: &Function) -> Result<(), LoxErr> {
+        self.stack.push(Frame {
+            name: format!("fn:{}", function.span.line),
+            line: None,
+            start: Instant::now(),
+            children: Duration::ZERO,
+        });
+        Ok(())
+    }
+
+    fn leave_function(&mut self) -> Result<(), LoxErr> {
+        self.pop();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Profiler;
+    use crate::parser::Parser;
+    use crate::{Interpreter, Scanner};
+    use std::cell::RefCell;
+    use std::io;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_counts_and_stacks() {
+        let source = "var i = 0;\nwhile (i < 3) {\n  i = i + 1;\n}\n";
+        let profiler = Rc::new(RefCell::new(Profiler::new(source)));
+        let mut interpreter = Interpreter::new();
+        interpreter.add_hook(Box::new(profiler.clone()));
+        interpreter.set_output(Box::new(io::sink()));
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        interpreter.interpret(Parser::new(tokens).parse()).unwrap();
+
+        let profiler = profiler.borrow();
+        let hits: Vec<(usize, u64)> = profiler.lines.iter().map(|(l, s)| (*l, s.hits)).collect();
+        assert_eq!(vec![(1, 1), (2, 4), (3, 3)], hits);
+
+        let stacks: Vec<String> = profiler
+            .folded()
+            .lines()
+            .map(|l| l.rsplit_once(' ').unwrap().0.to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "main;var:1",
+                "main;while:2",
+                "main;while:2;block:2",
+                "main;while:2;block:2;expression:3",
+            ],
+            stacks
+        );
+        assert!(profiler
+            .report()
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("     2          4"));
+    }
+
+    #[test]
+    fn test_function_frames() {
+        let source = "var f = fun (n) {\n  return n;\n};\nf(1);\nprint f(f(2));\n";
+        let profiler = Rc::new(RefCell::new(Profiler::new(source)));
+        let mut interpreter = Interpreter::new();
+        interpreter.add_hook(Box::new(profiler.clone()));
+        interpreter.set_output(Box::new(io::sink()));
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        interpreter.interpret(Parser::new(tokens).parse()).unwrap();
+
+        let profiler = profiler.borrow();
+        assert!(profiler.stack.is_empty());
+        let hits: Vec<(usize, u64)> = profiler.lines.iter().map(|(l, s)| (*l, s.hits)).collect();
+        assert_eq!(vec![(1, 1), (2, 3), (4, 1), (5, 1)], hits);
+        let stacks: Vec<String> = profiler
+            .folded()
+            .lines()
+            .map(|l| l.rsplit_once(' ').unwrap().0.to_string())
+            .collect();
+        assert_eq!(
+            vec![
+                "main;expression:4",
+                "main;expression:4;fn:1",
+                "main;expression:4;fn:1;return:2",
+                "main;print:5",
+                "main;print:5;fn:1",
+                "main;print:5;fn:1;return:2",
+                "main;var:1",
+            ],
+            stacks
+        );
+    }
+}
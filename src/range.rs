@@ -0,0 +1,138 @@
+use crate::integer::Integer;
+use crate::{LiteralValue, LoxErr};
+use std::cmp::Ordering;
+use std::fmt::Display;
+
+/// `start..end`, or `start..=end` when `inclusive`, counting by `step`. The
+/// values are worked out as they are asked for rather than stored.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Range {
+    pub start: LiteralValue,
+    pub end: LiteralValue,
+    pub step: LiteralValue,
+    pub inclusive: bool,
+}
+
+impl Range {
+    /// Fails unless the bounds and step are numbers and the step isn't 0.
+    /// Without a step the range counts up by 1.
+    pub fn new(
+        start: LiteralValue,
+        end: LiteralValue,
+        step: Option<LiteralValue>,
+        inclusive: bool,
+    ) -> Result<Self, LoxErr> {
+        let step = step.unwrap_or(LiteralValue::IValue(Integer::from(1)));
+        for value in [&start, &end, &step] {
+            if !matches!(value, LiteralValue::IValue(_) | LiteralValue::FValue(_)) {
+                return Err(
+                    format!("Range bounds must be numbers, not {}", value.type_name()).into(),
+                );
+            }
+        }
+        if number(&step) == 0.0 {
+            return Err("Step of a range can't be 0".into());
+        }
+        Ok(Range {
+            start,
+            end,
+            step,
+            inclusive,
+        })
+    }
+
+    /// The value `index` steps from the start, or `None` if that is past
+    /// the end.
+    pub fn get(&self, index: u64) -> Option<LiteralValue> {
+        let value = match (&self.start, &self.step) {
+            (LiteralValue::IValue(start), LiteralValue::IValue(step)) => {
+                LiteralValue::IValue(start.add(&step.mul(&Integer::from(index as i64))))
+            }
+            (start, step) => LiteralValue::FValue(number(start) + number(step) * index as f64),
+        };
+        self.before_end(&value).then_some(value)
+    }
+
+    /// Whether `value` is one of the values of the range.
+    pub fn contains(&self, value: &LiteralValue) -> bool {
+        let from_start = match value.compare(&self.start) {
+            Some(ordering) => ordering,
+            None => return false,
+        };
+        if from_start == Ordering::Equal {
+            return self.before_end(value);
+        }
+        if (from_start == Ordering::Greater) != self.ascending() || !self.before_end(value) {
+            return false;
+        }
+        match (value, &self.start, &self.step) {
+            (LiteralValue::IValue(x), LiteralValue::IValue(start), LiteralValue::IValue(step)) => x
+                .sub(start)
+                .div_rem(step)
+                .is_some_and(|(_, rem)| rem == Integer::from(0)),
+            _ => ((number(value) - number(&self.start)) / number(&self.step)).fract() == 0.0,
+        }
+    }
+
+    fn ascending(&self) -> bool {
+        number(&self.step) > 0.0
+    }
+
+    /// Whether `value` hasn't gone past the end, in the direction the range
+    /// counts.
+    fn before_end(&self, value: &LiteralValue) -> bool {
+        match (value.compare(&self.end), self.ascending(), self.inclusive) {
+            (Some(Ordering::Less), true, _) | (Some(Ordering::Greater), false, _) => true,
+            (Some(Ordering::Equal), _, inclusive) => inclusive,
+            _ => false,
+        }
+    }
+}
+
+/// As written: `0..10`, `0..=10` or `10..0 by -2`.
+impl Display for Range {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let dots = if self.inclusive { "..=" } else { ".." };
+        write!(
+            f,
+            "{}{}{}",
+            self.start.to_string(),
+            dots,
+            self.end.to_string()
+        )?;
+        if self.step != LiteralValue::IValue(Integer::from(1)) {
+            write!(f, " by {}", self.step.to_string())?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `value` is `in` `collection`: one of the values of a range, or
+/// part of a string. A string can only be looked for in a string, and only
+/// a string can be looked for in one.
+pub fn contains(value: &LiteralValue, collection: &LiteralValue) -> Result<bool, LoxErr> {
+    match (value, collection) {
+        (LiteralValue::StringValue(part), LiteralValue::StringValue(s)) => Ok(s.contains(part)),
+        (LiteralValue::StringValue(_), LiteralValue::Range(_))
+        | (_, LiteralValue::StringValue(_)) => Err(format!(
+            "Cannot apply 'in' to {} and {}",
+            value.type_name(),
+            collection.type_name()
+        )
+        .into()),
+        (_, LiteralValue::Range(range)) => Ok(range.contains(value)),
+        _ => Err(format!(
+            "Right side of 'in' must be a range or string, not {}",
+            collection.type_name()
+        )
+        .into()),
+    }
+}
+
+fn number(value: &LiteralValue) -> f64 {
+    match value {
+        LiteralValue::IValue(x) => x.to_f64(),
+        LiteralValue::FValue(x) => *x,
+        _ => f64::NAN,
+    }
+}
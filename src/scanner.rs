@@ -1,15 +1,27 @@
 use core::panic;
-use std::{collections::HashMap, fmt::Display};
+use std::{cmp::Ordering, collections::HashMap, fmt::Display};
 
-use crate::LoxErr;
+use crate::enums::{EnumType, Tag};
+use crate::function::Function;
+use crate::generator::Generator;
+use crate::range::Range;
+use crate::{integer::Integer, module::Module, LoxErr};
+use std::rc::Rc;
 
 pub struct Scanner {
-    source: String,
+    source: Vec<char>,
     tokens: Vec<Token>,
     start: usize,
     current: usize,
     keywords: HashMap<String, TokenType>,
     line: usize,
+    line_start: usize,
+    column: usize,
+    /// One entry per `${` still open, holding where it started and how many
+    /// unclosed `{`s the embedded expression has so far.
+    interpolations: Vec<(Span, usize)>,
+    /// Plain `//` comments, which are left out of the tokens.
+    comments: Vec<Token>,
 }
 
 impl Scanner {
@@ -31,34 +43,70 @@ impl Scanner {
         keyword_map.insert("this".to_string(), TokenType::THIS);
         keyword_map.insert("true".to_string(), TokenType::TRUE);
         keyword_map.insert("var".to_string(), TokenType::VAR);
+        keyword_map.insert("const".to_string(), TokenType::CONST);
         keyword_map.insert("while".to_string(), TokenType::WHILE);
         keyword_map.insert("assert".to_string(), TokenType::ASSERT);
+        keyword_map.insert("div".to_string(), TokenType::DIV);
+        keyword_map.insert("import".to_string(), TokenType::IMPORT);
+        keyword_map.insert("as".to_string(), TokenType::AS);
+        keyword_map.insert("throw".to_string(), TokenType::THROW);
+        keyword_map.insert("try".to_string(), TokenType::TRY);
+        keyword_map.insert("catch".to_string(), TokenType::CATCH);
+        keyword_map.insert("finally".to_string(), TokenType::FINALLY);
+        keyword_map.insert("break".to_string(), TokenType::BREAK);
+        keyword_map.insert("match".to_string(), TokenType::MATCH);
+        keyword_map.insert("enum".to_string(), TokenType::ENUM);
+        keyword_map.insert("is".to_string(), TokenType::IS);
+        keyword_map.insert("in".to_string(), TokenType::IN);
+        keyword_map.insert("by".to_string(), TokenType::BY);
+        keyword_map.insert("yield".to_string(), TokenType::YIELD);
 
         Self {
-            source: contents.to_string(),
+            source: contents.chars().collect(),
             tokens: vec![],
             start: 0,
             current: 0,
             keywords: keyword_map,
             line: 1,
+            line_start: 0,
+            column: 1,
+            interpolations: vec![],
+            comments: vec![],
         }
     }
     pub fn scan_tokens(&mut self) -> Result<Vec<Token>, LoxErr> {
         while !self.is_at_end() {
             self.start = self.current;
+            self.column = self.start - self.line_start + 1;
             self.scan_token()?;
         }
 
+        if let Some((span, _)) = self.interpolations.first() {
+            return Err(
+                SyntaxError::new("Unterminated string interpolation".to_string(), *span).into(),
+            );
+        }
+
         self.tokens.push(Token {
             token_type: TokenType::EOF,
             lexeme: "".to_string(),
             literal: None,
             line_number: self.line,
+            column: self.current - self.line_start + 1,
         });
 
         Ok(self.tokens.clone())
     }
 
+    /// The `//` comments found by `scan_tokens`, as `COMMENT` tokens.
+    pub fn comments(&self) -> &[Token] {
+        &self.comments
+    }
+
+    pub fn keywords(&self) -> impl Iterator<Item = &str> {
+        self.keywords.keys().map(|k| k.as_str())
+    }
+
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
@@ -66,15 +114,85 @@ impl Scanner {
     fn scan_token(&mut self) -> Result<(), LoxErr> {
         let c = self.advance();
         match c {
-            '{' => self.add_token(TokenType::LEFTBRACE),
-            '}' => self.add_token(TokenType::RIGHTBRACE),
+            '{' => {
+                if let Some((_, braces)) = self.interpolations.last_mut() {
+                    *braces += 1;
+                }
+                self.add_token(TokenType::LEFTBRACE)
+            }
+            '}' => match self.interpolations.last_mut() {
+                Some((_, 0)) => {
+                    self.interpolations.pop();
+                    self.string()?;
+                }
+                Some((_, braces)) => {
+                    *braces -= 1;
+                    self.add_token(TokenType::RIGHTBRACE)
+                }
+                None => self.add_token(TokenType::RIGHTBRACE),
+            },
             '(' => self.add_token(TokenType::LEFTPAREN),
             ')' => self.add_token(TokenType::RIGHTPAREN),
+            '[' => self.add_token(TokenType::LEFTBRACKET),
+            ']' => self.add_token(TokenType::RIGHTBRACKET),
             ',' => self.add_token(TokenType::COMMA),
-            '-' => self.add_token(TokenType::MINUS),
-            '.' => self.add_token(TokenType::DOT),
-            '*' => self.add_token(TokenType::STAR),
-            '+' => self.add_token(TokenType::PLUS),
+            '-' => {
+                if self.char_match('-') {
+                    self.add_token(TokenType::MINUSMINUS)
+                } else if self.char_match('=') {
+                    self.add_token(TokenType::MINUSEQUAL)
+                } else {
+                    self.add_token(TokenType::MINUS)
+                }
+            }
+            '.' => {
+                if self.char_match('.') {
+                    if self.char_match('=') {
+                        self.add_token(TokenType::DOTDOTEQUAL)
+                    } else {
+                        self.add_token(TokenType::DOTDOT)
+                    }
+                } else {
+                    self.add_token(TokenType::DOT)
+                }
+            }
+            '*' => {
+                if self.char_match('*') {
+                    self.add_token(TokenType::STARSTAR)
+                } else if self.char_match('=') {
+                    self.add_token(TokenType::STAREQUAL)
+                } else {
+                    self.add_token(TokenType::STAR)
+                }
+            }
+            '%' => {
+                if self.char_match('=') {
+                    self.add_token(TokenType::PERCENTEQUAL)
+                } else {
+                    self.add_token(TokenType::PERCENT)
+                }
+            }
+            '&' => self.add_token(TokenType::AMPERSAND),
+            '|' => self.add_token(TokenType::PIPE),
+            '^' => self.add_token(TokenType::CARET),
+            '~' => self.add_token(TokenType::TILDE),
+            ':' => self.add_token(TokenType::COLON),
+            '?' => {
+                if self.char_match('?') {
+                    self.add_token(TokenType::QUESTIONQUESTION)
+                } else {
+                    self.add_token(TokenType::QUESTION)
+                }
+            }
+            '+' => {
+                if self.char_match('+') {
+                    self.add_token(TokenType::PLUSPLUS)
+                } else if self.char_match('=') {
+                    self.add_token(TokenType::PLUSEQUAL)
+                } else {
+                    self.add_token(TokenType::PLUS)
+                }
+            }
             ';' => self.add_token(TokenType::SEMICOLON),
             '!' => {
                 let is_match = self.char_match('=');
@@ -88,6 +206,8 @@ impl Scanner {
                 let is_match = self.char_match('=');
                 if is_match {
                     self.add_token(TokenType::LESSEQUAL);
+                } else if self.char_match('<') {
+                    self.add_token(TokenType::LESSLESS);
                 } else {
                     self.add_token(TokenType::LESS)
                 }
@@ -96,6 +216,8 @@ impl Scanner {
                 let is_match = self.char_match('=');
                 if is_match {
                     self.add_token(TokenType::GREATEREQUAL);
+                } else if self.char_match('>') {
+                    self.add_token(TokenType::GREATERGREATER);
                 } else {
                     self.add_token(TokenType::GREATER)
                 }
@@ -104,31 +226,55 @@ impl Scanner {
                 let is_match = self.char_match('=');
                 if is_match {
                     self.add_token(TokenType::EQUALEQUAL);
+                } else if self.char_match('>') {
+                    self.add_token(TokenType::FATARROW)
                 } else {
                     self.add_token(TokenType::EQUAL)
                 }
             }
             '/' => {
                 if self.char_match('/') {
-                    loop {
-                        if self.peek() == '\n' || self.is_at_end() {
-                            break;
-                        }
+                    // `///` starts a doc comment, but `////` is a plain one.
+                    let doc = self.peek() == '/' && self.peek_next() != '/';
+                    while self.peek() != '\n' && !self.is_at_end() {
                         self.advance();
                     }
+                    if doc {
+                        let text = self.text(self.start + 3, self.current);
+                        let text = text.strip_prefix(' ').unwrap_or(&text).to_string();
+                        let doc_lit = LiteralValue::StringValue(text);
+                        self.add_token_literal(TokenType::DOCCOMMENT, Some(doc_lit));
+                    } else {
+                        let text = self.text(self.start + 2, self.current);
+                        self.comments.push(Token {
+                            token_type: TokenType::COMMENT,
+                            lexeme: self.text(self.start, self.current),
+                            literal: Some(LiteralValue::StringValue(text)),
+                            line_number: self.line,
+                            column: self.column,
+                        });
+                    }
+                } else if self.char_match('*') {
+                    self.block_comment()?;
+                } else if self.char_match('=') {
+                    self.add_token(TokenType::SLASHEQUAL)
                 } else {
                     self.add_token(TokenType::SLASH)
                 }
             }
             '"' => self.string()?,
-            '\n' => self.line += 1,
+            'r' if self.raw_string_ahead() => self.raw_string()?,
+            '\n' => {
+                self.line += 1;
+                self.line_start = self.current;
+            }
             ' ' => {}
             '\r' => {}
             '\t' => {}
             c => {
                 if self.is_digit(c) {
                     self.number()?;
-                } else if self.is_alpha_numeric(c) {
+                } else if c.is_alphabetic() || c == '_' {
                     self.identifier()?;
                 }
             }
@@ -140,14 +286,14 @@ impl Scanner {
         while self.is_alpha_numeric(self.peek()) {
             self.advance();
         }
-        let ident = &self.source[self.start..self.current];
-        let keyword = self.keywords.get(ident);
+        let ident = self.text(self.start, self.current);
+        let keyword = self.keywords.get(&ident);
         match keyword {
             Some(k) => {
                 self.add_token(k.clone());
             }
             None => {
-                let ident_lit = LiteralValue::IdentifierValue(ident.to_string());
+                let ident_lit = LiteralValue::IdentifierValue(ident);
                 self.add_token_literal(TokenType::IDENTIFIER, Some(ident_lit));
             }
         }
@@ -155,92 +301,293 @@ impl Scanner {
     }
 
     fn is_alpha_numeric(&self, c: char) -> bool {
-        (c >= '0' && c <= '9') || (c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || (c == '_')
+        c.is_alphanumeric() || c == '_'
     }
 
     fn number(&mut self) -> Result<(), LoxErr> {
-        while self.is_digit(self.peek()) {
-            self.advance();
-        }
+        let radix = match (self.source[self.start], self.peek()) {
+            ('0', 'x' | 'X') => Some((16, "hexadecimal")),
+            ('0', 'b' | 'B') => Some((2, "binary")),
+            ('0', 'o' | 'O') => Some((8, "octal")),
+            _ => None,
+        };
 
-        if self.peek() == '.' && self.is_digit(self.peek_next()) {
-            self.advance();
-        }
+        let (value, kind) = match radix {
+            Some((radix, kind)) => {
+                self.advance();
+                let digits = self.digits(radix, kind)?;
+                (LiteralValue::IValue(Integer::parse(&digits, radix)), kind)
+            }
+            None => {
+                self.current = self.start;
+                let mut text = self.digits(10, "number")?;
+                let mut float = false;
+                if self.peek() == '.' && self.is_digit(self.peek_next()) {
+                    float = true;
+                    self.advance();
+                    text.push('.');
+                    text.push_str(&self.digits(10, "number")?);
+                }
+                if matches!(self.peek(), 'e' | 'E') {
+                    float = true;
+                    self.advance();
+                    text.push('e');
+                    if matches!(self.peek(), '+' | '-') {
+                        text.push(self.advance());
+                    }
+                    if !self.is_digit(self.peek()) {
+                        return Err(
+                            self.error("Expected digits after the exponent 'e'", self.start)
+                        );
+                    }
+                    text.push_str(&self.digits(10, "number")?);
+                }
+                let value = match float {
+                    true => {
+                        let value: f64 = text.replace('_', "").parse()?;
+                        if value.is_infinite() {
+                            return Err(self.error("Number literal is too large", self.start));
+                        }
+                        LiteralValue::FValue(value)
+                    }
+                    false => LiteralValue::IValue(Integer::parse(&text, 10)),
+                };
+                (value, "number")
+            }
+        };
 
-        while self.is_digit(self.peek()) {
-            self.advance();
+        // Letters or digits straight after a literal, as in `0b102` or
+        // `12px`, are a mistake rather than the start of another token.
+        if self.is_alpha_numeric(self.peek()) {
+            let message = format!("Invalid digit '{}' in {} literal", self.peek(), kind);
+            return Err(self.error(&message, self.current));
         }
 
-        let num_lit = &self.source[self.start..self.current];
-        let num_lit: f64 = num_lit.parse()?;
-        let num_lit = LiteralValue::FValue(num_lit);
-        self.add_token_literal(TokenType::NUMBER, Some(num_lit));
+        self.add_token_literal(TokenType::NUMBER, Some(value));
 
         Ok(())
     }
 
+    /// Reads a run of digits in `radix`, which may be separated by single
+    /// underscores, and returns it as written.
+    fn digits(&mut self, radix: u32, kind: &str) -> Result<String, LoxErr> {
+        let start = self.current;
+        while self.peek().is_digit(radix) || (self.peek() == '_' && !self.is_at_end()) {
+            self.advance();
+        }
+        let digits = self.text(start, self.current);
+        if digits.is_empty() {
+            let message = format!("Expected digits in {} literal", kind);
+            return Err(self.error(&message, self.start));
+        }
+        if digits.starts_with('_') || digits.ends_with('_') || digits.contains("__") {
+            let message = format!("'_' in a {} literal must be between digits", kind);
+            return Err(self.error(&message, self.start));
+        }
+        Ok(digits)
+    }
+
     fn peek_next(&self) -> char {
         if self.current + 1 >= self.source.len() {
             return '\n';
         }
-        self.source.as_bytes()[self.current + 1] as char
+        self.source[self.current + 1]
     }
 
     fn is_digit(&self, n: char) -> bool {
-        return n >= '0' && n <= '9';
+        n.is_ascii_digit()
+    }
+
+    /// Skips a comment whose `/*` has just been consumed. Comments nest, so
+    /// `/* a /* b */ c */` is a single comment.
+    fn block_comment(&mut self) -> Result<(), LoxErr> {
+        let opening = Span {
+            line: self.line,
+            column: self.column,
+        };
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end() {
+                let message = format!("Unterminated block comment opened on line {}", opening.line);
+                return Err(SyntaxError::new(message, opening).into());
+            }
+            match self.advance() {
+                '/' if self.char_match('*') => depth += 1,
+                '*' if self.char_match('/') => depth -= 1,
+                '\n' => {
+                    self.line += 1;
+                    self.line_start = self.current;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
     }
 
+    /// Scans the text of a string up to its closing quote, or up to a `${`,
+    /// in which case the text so far becomes a `STRINGPART` and the tokens of
+    /// the embedded expression follow. The `}` that closes it carries on
+    /// scanning the string from there.
     fn string(&mut self) -> Result<(), LoxErr> {
+        let mut value = String::new();
         while self.peek() != '"' && !self.is_at_end() {
-            if self.peek() == '\n' {
-                self.line += 1;
+            match self.advance() {
+                '\\' => value.push(self.escape()?),
+                '$' if self.peek() == '{' => {
+                    let span = Span {
+                        line: self.line,
+                        column: self.current - self.line_start,
+                    };
+                    self.advance();
+                    self.interpolations.push((span, 0));
+                    let string_lit = LiteralValue::StringValue(value);
+                    self.add_token_literal(TokenType::STRINGPART, Some(string_lit));
+                    return Ok(());
+                }
+                '\n' => {
+                    self.line += 1;
+                    self.line_start = self.current;
+                    value.push('\n');
+                }
+                c => value.push(c),
             }
-            self.advance();
         }
 
         if self.is_at_end() {
-            return Err("Reached EOF".into());
+            return Err(self.error("Unterminated string", self.current));
         }
 
         // Closing the '"'
         self.advance();
 
-        let val = &self.source[self.start + 1..self.current - 1];
+        let string_lit = LiteralValue::StringValue(value);
 
-        let mut quoted_str = String::new();
+        self.add_token_literal(TokenType::STRINGLIT, Some(string_lit));
 
-        quoted_str.push_str(val);
+        Ok(())
+    }
 
-        let string_lit = LiteralValue::StringValue(quoted_str);
+    /// Reads the rest of an escape sequence whose backslash has just been
+    /// consumed.
+    fn escape(&mut self) -> Result<char, LoxErr> {
+        let backslash = self.current - 1;
+        if self.is_at_end() {
+            return Err(self.error("Unterminated string", self.current));
+        }
+        match self.advance() {
+            'n' => Ok('\n'),
+            't' => Ok('\t'),
+            'r' => Ok('\r'),
+            '0' => Ok('\0'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            '$' => Ok('$'),
+            'u' => {
+                if !self.char_match('{') {
+                    return Err(self.error("Expected '{' after \\u", backslash));
+                }
+                let digits_start = self.current;
+                while self.peek().is_ascii_hexdigit() && !self.is_at_end() {
+                    self.advance();
+                }
+                let digits = self.text(digits_start, self.current);
+                if !self.char_match('}') || digits.is_empty() || digits.len() > 6 {
+                    return Err(self.error(
+                        "Expected 1 to 6 hex digits between braces in \\u{...}",
+                        backslash,
+                    ));
+                }
+                u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .ok_or_else(|| {
+                        self.error(
+                            &format!("\\u{{{}}} is not a unicode scalar value", digits),
+                            backslash,
+                        )
+                    })
+            }
+            c => Err(self.error(&format!("Unknown escape sequence '\\{}'", c), backslash)),
+        }
+    }
 
-        self.add_token_literal(TokenType::STRINGLIT, Some(string_lit));
+    /// Whether the `r` just consumed starts a raw string: `r"..."`, or
+    /// `r#"..."#` with any number of `#`s when the text contains quotes.
+    fn raw_string_ahead(&self) -> bool {
+        let mut next = self.current;
+        while self.source.get(next) == Some(&'#') {
+            next += 1;
+        }
+        self.source.get(next) == Some(&'"')
+    }
 
-        Ok(())
+    /// A raw string takes its text exactly as written, newlines and
+    /// backslashes included, up to a quote followed by as many `#`s as
+    /// opened it.
+    fn raw_string(&mut self) -> Result<(), LoxErr> {
+        let mut hashes = 0;
+        while self.char_match('#') {
+            hashes += 1;
+        }
+        self.advance();
+        let closing = vec!['#'; hashes];
+        let text_start = self.current;
+        loop {
+            if self.is_at_end() {
+                return Err(self.error("Unterminated raw string", self.current));
+            }
+            let c = self.advance();
+            if c == '\n' {
+                self.line += 1;
+                self.line_start = self.current;
+            } else if c == '"' && self.source[self.current..].starts_with(&closing) {
+                let value = self.text(text_start, self.current - 1);
+                self.current += hashes;
+                self.add_token_literal(
+                    TokenType::STRINGLIT,
+                    Some(LiteralValue::StringValue(value)),
+                );
+                return Ok(());
+            }
+        }
+    }
+
+    fn text(&self, start: usize, end: usize) -> String {
+        self.source[start..end].iter().collect()
+    }
+
+    /// A syntax error at character `index` of the current line.
+    fn error(&self, message: &str, index: usize) -> LoxErr {
+        let span = Span {
+            line: self.line,
+            column: index.saturating_sub(self.line_start) + 1,
+        };
+        SyntaxError::new(message.to_string(), span).into()
     }
 
     fn peek(&self) -> char {
         if self.is_at_end() {
             return '\n';
         }
-        self.source.as_bytes()[self.current] as char
+        self.source[self.current]
     }
 
     fn char_match(&mut self, symbol: char) -> bool {
         if self.is_at_end() {
             return false;
         }
-        if self.source.chars().nth(self.current).unwrap() != symbol {
-            return false;
+        if self.source[self.current] != symbol {
+            false
         } else {
             self.current += 1;
-            return true;
+            true
         }
     }
 
     fn advance(&mut self) -> char {
-        let c = self.source.as_bytes()[self.current];
+        let c = self.source[self.current];
         self.current += 1;
-        c as char
+        c
     }
 
     fn add_token(&mut self, token_type: TokenType) {
@@ -248,13 +595,14 @@ impl Scanner {
     }
 
     fn add_token_literal(&mut self, token_type: TokenType, literal: Option<LiteralValue>) {
-        let lexeme: String = self.source[self.start..self.current].to_string();
+        let lexeme = self.text(self.start, self.current);
 
         self.tokens.push(Token {
             token_type,
             lexeme,
             literal,
             line_number: self.line,
+            column: self.column,
         });
     }
 }
@@ -262,11 +610,31 @@ impl Scanner {
 #[derive(Debug, Clone, PartialEq)]
 pub enum LiteralValue {
     FValue(f64),
+    IValue(Integer),
     StringValue(String),
     IdentifierValue(String),
     True,
     False,
     Nil,
+    Module(Module),
+    /// A runtime error caught by `catch`.
+    Error {
+        message: String,
+        line: usize,
+    },
+    Enum(Rc<EnumType>),
+    /// A variant with a payload, not yet called with one.
+    Constructor(Rc<Tag>),
+    /// A value of an enum, equal to another if the variant and payload are.
+    Variant {
+        tag: Rc<Tag>,
+        payload: Vec<LiteralValue>,
+    },
+    Function(Rc<Function>),
+    Range(Rc<Range>),
+    Generator(Rc<Generator>),
+    /// `generator.next`, which resumes the generator when called.
+    Next(Rc<Generator>),
 }
 
 impl LiteralValue {
@@ -277,24 +645,110 @@ impl LiteralValue {
             _ => panic!("Invalid type cannot convert to bool"),
         }
     }
+    /// How two values order: numbers by value whether they are integers or
+    /// not, and strings alphabetically. Other values are only ever equal.
+    pub fn compare(&self, other: &LiteralValue) -> Option<Ordering> {
+        match (self, other) {
+            (LiteralValue::IValue(x), LiteralValue::IValue(y)) => Some(x.cmp(y)),
+            (LiteralValue::IValue(x), LiteralValue::FValue(y)) => x.to_f64().partial_cmp(y),
+            (LiteralValue::FValue(x), LiteralValue::IValue(y)) => x.partial_cmp(&y.to_f64()),
+            (LiteralValue::FValue(x), LiteralValue::FValue(y)) => x.partial_cmp(y),
+            (LiteralValue::StringValue(x), LiteralValue::StringValue(y)) => Some(x.cmp(y)),
+            (x, y) => (x == y).then_some(Ordering::Equal),
+        }
+    }
+
+    /// The name of the value's type, for error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            LiteralValue::FValue(_) | LiteralValue::IValue(_) => "num",
+            LiteralValue::StringValue(_) | LiteralValue::IdentifierValue(_) => "str",
+            LiteralValue::True | LiteralValue::False => "bool",
+            LiteralValue::Nil => "nil",
+            LiteralValue::Module(_) => "module",
+            LiteralValue::Error { .. } => "error",
+            LiteralValue::Enum(_) => "enum",
+            LiteralValue::Constructor(_) => "constructor",
+            LiteralValue::Variant { .. } => "variant",
+            LiteralValue::Function(_) => "function",
+            LiteralValue::Range(_) => "range",
+            LiteralValue::Generator(_) => "generator",
+            LiteralValue::Next(_) => "function",
+        }
+    }
+
+    #[allow(clippy::inherent_to_string)]
     pub fn to_string(&self) -> String {
         match self {
             LiteralValue::FValue(x) => x.to_string(),
+            LiteralValue::IValue(x) => x.to_string(),
             LiteralValue::StringValue(x) => x.clone(),
             LiteralValue::False => "false".to_string(),
             LiteralValue::True => "true".to_string(),
             LiteralValue::Nil => "nil".to_string(),
+            LiteralValue::Module(module) => format!("<module {}>", module.name()),
+            LiteralValue::Error { message, line } => format!("[line {}] Error: {}", line, message),
+            LiteralValue::Enum(enum_type) => format!("<enum {}>", enum_type.name),
+            LiteralValue::Constructor(tag) => format!("<constructor {}>", tag.path()),
+            LiteralValue::Variant { tag, payload } if payload.is_empty() => tag.path(),
+            LiteralValue::Variant { tag, payload } => {
+                let payload: Vec<String> = payload
+                    .iter()
+                    .map(|value| match value {
+                        LiteralValue::StringValue(s) => format!("{:?}", s),
+                        value => value.to_string(),
+                    })
+                    .collect();
+                format!("{}({})", tag.path(), payload.join(", "))
+            }
+            LiteralValue::Function(function) => format!("<fn ({})>", function.params.join(", ")),
+            LiteralValue::Range(range) => range.to_string(),
+            LiteralValue::Generator(_) => "<generator>".to_string(),
+            LiteralValue::Next(_) => "<fn next>".to_string(),
             _ => "".to_string(),
         }
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Span {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxError {
+    pub message: String,
+    pub span: Span,
+}
+
+impl SyntaxError {
+    pub fn new(message: String, span: Span) -> Self {
+        Self { message, span }
+    }
+}
+
+impl Display for SyntaxError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[line {}] Error: {}", self.span, self.message)
+    }
+}
+
+impl std::error::Error for SyntaxError {}
+
 #[derive(Debug, Clone)]
 pub struct Token {
     pub token_type: TokenType,
     pub lexeme: String,
     pub literal: Option<LiteralValue>,
     pub line_number: usize,
+    pub column: usize,
 }
 
 impl Token {
@@ -303,27 +757,40 @@ impl Token {
         lexeme: String,
         literal: Option<LiteralValue>,
         line_number: usize,
+        column: usize,
     ) -> Token {
         Self {
             token_type,
             lexeme,
             literal,
             line_number,
+            column,
         }
     }
 
+    pub fn span(&self) -> Span {
+        Span {
+            line: self.line_number,
+            column: self.column,
+        }
+    }
+
+    #[allow(clippy::inherent_to_string)]
     pub fn to_string(&self) -> String {
         format!("{} {} {:?}", self.token_type, self.lexeme, self.literal)
     }
 }
 
 #[derive(Debug, Clone, PartialEq)]
+#[allow(clippy::upper_case_acronyms)]
 pub enum TokenType {
     // Single Char Tokens
     LEFTPAREN,
     RIGHTPAREN,
     LEFTBRACE,
     RIGHTBRACE,
+    LEFTBRACKET,
+    RIGHTBRACKET,
     COMMA,
     DOT,
     MINUS,
@@ -331,6 +798,13 @@ pub enum TokenType {
     SEMICOLON,
     SLASH,
     STAR,
+    PERCENT,
+    AMPERSAND,
+    PIPE,
+    CARET,
+    TILDE,
+    COLON,
+    QUESTION,
 
     // One to Two char tokens
     BANG,
@@ -341,10 +815,31 @@ pub enum TokenType {
     GREATEREQUAL,
     LESS,
     LESSEQUAL,
+    STARSTAR,
+    LESSLESS,
+    GREATERGREATER,
+    PLUSEQUAL,
+    MINUSEQUAL,
+    STAREQUAL,
+    SLASHEQUAL,
+    PERCENTEQUAL,
+    PLUSPLUS,
+    MINUSMINUS,
+    QUESTIONQUESTION,
+    DOTDOT,
+    DOTDOTEQUAL,
+    FATARROW,
 
     // Literals
     IDENTIFIER,
     STRINGLIT,
+    /// The text of a string literal before an embedded `${expression}`.
+    STRINGPART,
+    /// A `///` comment line, with the text after the slashes as its literal.
+    DOCCOMMENT,
+    /// A plain `//` comment, kept apart from the other tokens, with the text
+    /// after the slashes as its literal.
+    COMMENT,
     NUMBER,
 
     // Keywords
@@ -363,8 +858,23 @@ pub enum TokenType {
     THIS,
     TRUE,
     VAR,
+    CONST,
+    IMPORT,
+    AS,
+    THROW,
+    TRY,
+    CATCH,
+    FINALLY,
+    BREAK,
+    MATCH,
+    ENUM,
+    IS,
+    IN,
+    BY,
+    YIELD,
     WHILE,
     ASSERT,
+    DIV,
 
     EOF,
 }
@@ -374,3 +884,163 @@ impl Display for TokenType {
         write!(f, "{:?}", self)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{LiteralValue, Scanner, TokenType};
+    use crate::integer::Integer;
+
+    fn string_literal(source: &str) -> String {
+        let tokens = Scanner::new(source).scan_tokens().unwrap();
+        match &tokens[0].literal {
+            Some(LiteralValue::StringValue(s)) => s.clone(),
+            other => panic!("expected a string, found {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_unicode_and_escapes() {
+        let tokens = Scanner::new("var café = \"naïve ☕\"; π")
+            .scan_tokens()
+            .unwrap();
+        assert_eq!("café", tokens[1].lexeme);
+        assert_eq!(
+            Some(LiteralValue::StringValue("naïve ☕".to_string())),
+            tokens[3].literal
+        );
+        assert_eq!(TokenType::IDENTIFIER, tokens[5].token_type);
+        assert_eq!((23, "π"), (tokens[5].column, tokens[5].lexeme.as_str()));
+
+        assert_eq!(
+            "tab\there\nquote \" slash \\ é ☃",
+            string_literal(r#""tab\there\nquote \" slash \\ \u{e9} \u{2603}""#)
+        );
+        assert_eq!(
+            "line one\n  C:\\no\\escapes",
+            string_literal("r\"line one\n  C:\\no\\escapes\"")
+        );
+        assert_eq!("say \"hi\"", string_literal("r#\"say \"hi\"\"#"));
+
+        let tokens = Scanner::new("\"a${ {} }b${\"c\"}\\${d}\"")
+            .scan_tokens()
+            .unwrap();
+        let types: Vec<TokenType> = tokens.iter().map(|t| t.token_type.clone()).collect();
+        assert_eq!(
+            vec![
+                TokenType::STRINGPART,
+                TokenType::LEFTBRACE,
+                TokenType::RIGHTBRACE,
+                TokenType::STRINGPART,
+                TokenType::STRINGLIT,
+                TokenType::STRINGLIT,
+                TokenType::EOF
+            ],
+            types
+        );
+        assert_eq!(
+            Some(LiteralValue::StringValue("${d}".to_string())),
+            tokens[5].literal
+        );
+
+        let error = Scanner::new("print \"a\\qb\";").scan_tokens().unwrap_err();
+        assert_eq!(
+            "[line 1:9] Error: Unknown escape sequence '\\q'",
+            error.to_string()
+        );
+        let error = Scanner::new("\"\\u{110000}\"").scan_tokens().unwrap_err();
+        assert_eq!(
+            "[line 1:2] Error: \\u{110000} is not a unicode scalar value",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        let source = "/* a /* nested\n */ still comment */ x /// doc\n//// plain\n// plain\ny";
+        let mut scanner = Scanner::new(source);
+        let tokens = scanner.scan_tokens().unwrap();
+        let kinds: Vec<(TokenType, usize)> = tokens
+            .iter()
+            .map(|t| (t.token_type.clone(), t.line_number))
+            .collect();
+        assert_eq!(
+            vec![
+                (TokenType::IDENTIFIER, 2),
+                (TokenType::DOCCOMMENT, 2),
+                (TokenType::IDENTIFIER, 5),
+                (TokenType::EOF, 5)
+            ],
+            kinds
+        );
+        let comments: Vec<(&str, usize)> = scanner
+            .comments()
+            .iter()
+            .map(|t| (t.lexeme.as_str(), t.line_number))
+            .collect();
+        assert_eq!(vec![("//// plain", 3), ("// plain", 4)], comments);
+        assert_eq!(
+            Some(LiteralValue::StringValue("doc".to_string())),
+            tokens[1].literal
+        );
+
+        let error = Scanner::new("x;\n  /* open /* inner */\n\n")
+            .scan_tokens()
+            .unwrap_err();
+        assert_eq!(
+            "[line 2:3] Error: Unterminated block comment opened on line 2",
+            error.to_string()
+        );
+    }
+
+    #[test]
+    fn test_numbers() {
+        let number = |source: &str| match Scanner::new(source).scan_tokens() {
+            Ok(tokens) => Ok(tokens[0].literal.clone()),
+            Err(e) => Err(e.to_string()),
+        };
+        let value = |n: f64| Ok(Some(LiteralValue::FValue(n)));
+        let int = |n: i64| Ok(Some(LiteralValue::IValue(Integer::from(n))));
+        assert_eq!(int(255), number("0xFF"));
+        assert_eq!(int(10), number("0b1010"));
+        assert_eq!(int(15), number("0o17"));
+        assert_eq!(int(1_000), number("1_000"));
+        assert_eq!(
+            "18446744073709551616",
+            number("0x1_0000_0000_0000_0000")
+                .unwrap()
+                .unwrap()
+                .to_string()
+        );
+        assert_eq!(value(1_000_000.5), number("1_000_000.5"));
+        assert_eq!(value(0.0015), number("1.5e-3"));
+        assert_eq!(value(2e10), number("2E+1_0"));
+        assert_eq!(
+            Err("[line 1:5] Error: Invalid digit '2' in binary literal".to_string()),
+            number("0b102")
+        );
+        assert_eq!(
+            Err("[line 1:1] Error: Expected digits in hexadecimal literal".to_string()),
+            number("0x;")
+        );
+        assert_eq!(
+            Err("[line 1:1] Error: '_' in a number literal must be between digits".to_string()),
+            number("1__0")
+        );
+        assert_eq!(
+            Err("[line 1:1] Error: Expected digits after the exponent 'e'".to_string()),
+            number("1e")
+        );
+        assert_eq!(
+            Err("[line 1:3] Error: Invalid digit 'p' in number literal".to_string()),
+            number("12px")
+        );
+        assert_eq!(
+            Err("[line 1:1] Error: Number literal is too large".to_string()),
+            number("1e400")
+        );
+        assert_eq!(
+            value(1.7976931348623157e308),
+            number("1.7976931348623157e308")
+        );
+    }
+}
@@ -1,32 +1,233 @@
 use crate::expr::Expr;
+use crate::scanner::{LiteralValue, Span, Token};
+use crate::types::Type;
+use std::cmp::Ordering;
 
 #[derive(Debug, Clone)]
 pub enum Statement {
     Expression {
         expression: Expr,
+        span: Span,
     },
     Print {
         expression: Expr,
+        span: Span,
     },
     Var {
         indentifier: String,
         expression: Expr,
+        span: Span,
+        /// The `///` comment lines written just above the declaration.
+        doc: Option<String>,
+        /// Declared with `const`, so it may not be assigned to afterwards.
+        constant: bool,
+        /// The `: type` after the name, which only `muslox check` looks at.
+        annotation: Option<Type>,
+    },
+    /// `import "path" as name;`. The span is the name's.
+    Import {
+        path: String,
+        name: String,
+        span: Span,
+    },
+    /// `throw expression;`. The span is the `throw`.
+    Throw {
+        expression: Expr,
+        span: Span,
+    },
+    /// `try { } catch (name) { } finally { }`, with at least one of the
+    /// `catch` and `finally` clauses. The span is the `try`.
+    Try {
+        body: Box<Statement>,
+        catch: Option<Catch>,
+        finally: Option<Box<Statement>>,
+        span: Span,
+    },
+    Break {
+        span: Span,
+    },
+    /// `return value;`, or `return;` for nil. The span is the `return`.
+    Return {
+        value: Option<Expr>,
+        span: Span,
+    },
+    /// `yield value;`, or `yield;` for nil, pausing the generator it is in.
+    /// The span is the `yield`.
+    Yield {
+        value: Option<Expr>,
+        span: Span,
+    },
+    /// `enum Name { Variant, Variant(field, ...) }`. The span is the name's.
+    Enum {
+        name: String,
+        variants: Vec<Variant>,
+        span: Span,
+    },
+    /// `match (subject) { pattern if guard => body, ... }`. The first arm
+    /// whose pattern and guard match runs. The span is the `match`.
+    Match {
+        subject: Expr,
+        arms: Vec<Arm>,
+        span: Span,
     },
     Assert {
         expression_a: Expr,
+        span: Span,
     },
     Block {
         statements: Vec<Statement>,
+        span: Span,
     },
     If {
         conditional: Expr,
         happy_path: Box<Statement>,
         sad_path: Option<Box<Statement>>,
+        span: Span,
     },
     While {
         predicate: Expr,
         happy_path: Box<Statement>,
+        span: Span,
+    },
+    /// `for (variable in iterable) body`, running the body once per value
+    /// with a new `variable` each time. The span is the `for`.
+    ForIn {
+        variable: Token,
+        iterable: Expr,
+        body: Box<Statement>,
+        span: Span,
+    },
+}
+
+impl Statement {
+    pub fn span(&self) -> Span {
+        match self {
+            Statement::Expression { span, .. }
+            | Statement::Print { span, .. }
+            | Statement::Var { span, .. }
+            | Statement::Import { span, .. }
+            | Statement::Throw { span, .. }
+            | Statement::Try { span, .. }
+            | Statement::Break { span }
+            | Statement::Return { span, .. }
+            | Statement::Yield { span, .. }
+            | Statement::Match { span, .. }
+            | Statement::Enum { span, .. }
+            | Statement::Assert { span, .. }
+            | Statement::Block { span, .. }
+            | Statement::If { span, .. }
+            | Statement::While { span, .. }
+            | Statement::ForIn { span, .. } => *span,
+        }
+    }
+}
+
+/// The `catch (name) { }` clause of a `try`. The span is the name's.
+#[derive(Debug, Clone)]
+pub struct Catch {
+    pub name: String,
+    pub span: Span,
+    pub body: Box<Statement>,
+}
+
+/// One arm of a `match`. The span is the pattern's.
+#[derive(Debug, Clone)]
+pub struct Arm {
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Box<Statement>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// A number, string, boolean or `nil`, matching values equal to it.
+    Literal(LiteralValue),
+    /// `start..end`, or `start..=end` when `inclusive`.
+    Range {
+        start: LiteralValue,
+        end: LiteralValue,
+        inclusive: bool,
+    },
+    /// `_`, matching anything.
+    Wildcard,
+    /// A name, matching anything and binding it for the guard and body.
+    Binding { name: String, span: Span },
+    /// `Enum.Variant`, matching values of that variant, or
+    /// `Enum.Variant(pattern, ...)` to match the payload as well.
+    Variant {
+        enum_name: String,
+        variant: String,
+        fields: Option<Vec<Pattern>>,
     },
 }
 
-impl Statement {}
+impl Pattern {
+    /// Whether `value` fits the pattern. The names it binds are added to
+    /// `bindings`.
+    pub fn bind(&self, value: &LiteralValue, bindings: &mut Vec<(String, LiteralValue)>) -> bool {
+        match self {
+            Pattern::Wildcard => true,
+            Pattern::Binding { name, .. } => {
+                bindings.push((name.clone(), value.clone()));
+                true
+            }
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => match value {
+                LiteralValue::Variant { tag, payload }
+                    if tag.enum_name == *enum_name && tag.variant == *variant =>
+                {
+                    match fields {
+                        Some(fields) if fields.len() != payload.len() => false,
+                        Some(fields) => fields
+                            .iter()
+                            .zip(payload)
+                            .all(|(field, value)| field.bind(value, bindings)),
+                        None => true,
+                    }
+                }
+                _ => false,
+            },
+            Pattern::Literal(literal) => value.compare(literal) == Some(Ordering::Equal),
+            Pattern::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let above = matches!(
+                    value.compare(start),
+                    Some(Ordering::Greater | Ordering::Equal)
+                );
+                let below = match value.compare(end) {
+                    Some(Ordering::Less) => true,
+                    Some(Ordering::Equal) => *inclusive,
+                    _ => false,
+                };
+                above && below
+            }
+        }
+    }
+
+    /// The names the pattern binds, with where they are written.
+    pub fn names(&self) -> Vec<(&str, Span)> {
+        match self {
+            Pattern::Binding { name, span } => vec![(name.as_str(), *span)],
+            Pattern::Variant {
+                fields: Some(fields),
+                ..
+            } => fields.iter().flat_map(|field| field.names()).collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// One variant of an `enum`. The span is the variant's name.
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub name: String,
+    pub fields: Vec<String>,
+    pub span: Span,
+}
@@ -0,0 +1,44 @@
+use crate::parser::Parser;
+use crate::{Interpreter, Scanner};
+use std::cell::RefCell;
+use std::io::{self, Write};
+use std::rc::Rc;
+
+/// A `Write` that keeps what is written to it, shared between clones so a
+/// test can read what an interpreter or debugger printed.
+#[derive(Clone, Default)]
+pub struct Output(Rc<RefCell<Vec<u8>>>);
+
+impl Output {
+    pub fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Runs `source` and returns what it printed along with how it finished.
+/// The source must parse.
+pub fn run_capturing(source: &str) -> (String, Result<(), String>) {
+    run_with(Interpreter::new(), source)
+}
+
+/// Like `run_capturing`, in an interpreter that has been set up already.
+pub fn run_with(mut interpreter: Interpreter, source: &str) -> (String, Result<(), String>) {
+    let output = Output::default();
+    interpreter.set_output(Box::new(output.clone()));
+    let tokens = Scanner::new(source).scan_tokens().unwrap();
+    let mut parser = Parser::new(tokens);
+    let statements = parser.parse();
+    assert!(parser.errors().is_empty(), "{:?}", parser.errors());
+    let result = interpreter.interpret(statements).map_err(|e| e.to_string());
+    (output.text(), result)
+}
@@ -0,0 +1,96 @@
+use crate::LiteralValue;
+use std::fmt::Display;
+
+/// The set of types a value may have, as written in an annotation like
+/// `num | str` or `num?`, or as worked out by `muslox check`. Each basic type
+/// is a bit, so a union is the bits of its members.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Type(u8);
+
+impl Type {
+    /// No values, the start of a union.
+    pub const NONE: Type = Type(0);
+    pub const NUM: Type = Type(1);
+    pub const STR: Type = Type(1 << 1);
+    pub const BOOL: Type = Type(1 << 2);
+    pub const NIL: Type = Type(1 << 3);
+    pub const FUNCTION: Type = Type(1 << 4);
+    pub const RANGE: Type = Type(1 << 5);
+    /// Enums, their values, modules, errors and generators.
+    pub const OTHER: Type = Type(1 << 6);
+    /// Any value at all, which is also what an unknown type is taken to be.
+    pub const ANY: Type = Type((1 << 7) - 1);
+
+    const NAMES: [(&'static str, Type); 7] = [
+        ("num", Type::NUM),
+        ("str", Type::STR),
+        ("bool", Type::BOOL),
+        ("nil", Type::NIL),
+        ("function", Type::FUNCTION),
+        ("range", Type::RANGE),
+        ("other", Type::OTHER),
+    ];
+
+    /// The type a name stands for in an annotation.
+    pub fn named(name: &str) -> Option<Type> {
+        match name {
+            "any" => Some(Type::ANY),
+            "other" => None,
+            _ => Type::NAMES
+                .iter()
+                .find(|(n, _)| *n == name)
+                .map(|(_, ty)| *ty),
+        }
+    }
+
+    pub fn of(value: &LiteralValue) -> Type {
+        match value {
+            LiteralValue::FValue(_) | LiteralValue::IValue(_) => Type::NUM,
+            LiteralValue::StringValue(_) | LiteralValue::IdentifierValue(_) => Type::STR,
+            LiteralValue::True | LiteralValue::False => Type::BOOL,
+            LiteralValue::Nil => Type::NIL,
+            LiteralValue::Function(_) | LiteralValue::Constructor(_) | LiteralValue::Next(_) => {
+                Type::FUNCTION
+            }
+            LiteralValue::Range(_) => Type::RANGE,
+            _ => Type::OTHER,
+        }
+    }
+
+    pub fn union(self, other: Type) -> Type {
+        Type(self.0 | other.0)
+    }
+
+    pub fn without(self, other: Type) -> Type {
+        Type(self.0 & !other.0)
+    }
+
+    /// Whether every value of `other` is also one of this type.
+    pub fn contains(self, other: Type) -> bool {
+        other.0 & !self.0 == 0
+    }
+
+    /// The basic types in the union.
+    pub fn members(self) -> impl Iterator<Item = Type> {
+        Type::NAMES
+            .into_iter()
+            .map(|(_, ty)| ty)
+            .filter(move |ty| self.contains(*ty))
+    }
+}
+
+/// `num?` for a single type or nil, otherwise the members joined by `|`.
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if *self == Type::ANY {
+            return write!(f, "any");
+        }
+        let name = |ty: Type| Type::NAMES.iter().find(|(_, t)| *t == ty).map(|(n, _)| *n);
+        let rest = self.without(Type::NIL);
+        if self.contains(Type::NIL) && rest.members().count() == 1 {
+            return write!(f, "{}?", name(rest).unwrap_or_default());
+        }
+        let names: Vec<&str> = self.members().filter_map(name).collect();
+        write!(f, "{}", names.join(" | "))
+    }
+}
@@ -0,0 +1,69 @@
+use std::io::{BufRead, BufReader, Read, Write};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+/// Drives `muslox <mode>` over stdio with `Content-Length` framed messages.
+pub struct Client {
+    pub child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl Client {
+    pub fn spawn(mode: &str) -> Client {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_muslox"))
+            .arg(mode)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Client {
+            child,
+            stdin,
+            stdout,
+        }
+    }
+
+    pub fn send(&mut self, body: &str) {
+        write!(self.stdin, "Content-Length: {}\r\n\r\n{}", body.len(), body).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    pub fn receive(&mut self) -> String {
+        let mut length = 0;
+        loop {
+            let mut header = String::new();
+            self.stdout.read_line(&mut header).unwrap();
+            let header = header.trim_end();
+            if header.is_empty() {
+                break;
+            }
+            if let Some(value) = header.strip_prefix("Content-Length: ") {
+                length = value.parse().unwrap();
+            }
+        }
+        let mut body = vec![0; length];
+        self.stdout.read_exact(&mut body).unwrap();
+        String::from_utf8(body).unwrap()
+    }
+
+    pub fn request(&mut self, body: &str) -> String {
+        self.send(body);
+        self.receive()
+    }
+
+    /// Reads messages up to and including the first one containing `needle`.
+    #[allow(dead_code)]
+    pub fn receive_until(&mut self, needle: &str) -> Vec<String> {
+        let mut messages = vec![];
+        loop {
+            let message = self.receive();
+            let found = message.contains(needle);
+            messages.push(message);
+            if found {
+                return messages;
+            }
+        }
+    }
+}
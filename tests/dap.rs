@@ -0,0 +1,163 @@
+mod common;
+
+use common::Client;
+use std::fs;
+
+#[test]
+fn test_scripted_session() {
+    let path = std::env::temp_dir().join("muslox_dap_session.lox");
+    fs::write(
+        &path,
+        "var a = 1;\n{\n  var b = a + 1;\n  print b;\n}\nprint a;\n",
+    )
+    .unwrap();
+    let path = path.to_string_lossy().to_string();
+    let mut client = Client::spawn("dap");
+
+    client.send(
+        r#"{"seq":1,"type":"request","command":"initialize","arguments":{"adapterID":"muslox"}}"#,
+    );
+    let messages = client.receive_until(r#""event":"initialized""#);
+    assert!(
+        messages[0].contains(r#""command":"initialize""#),
+        "{:?}",
+        messages
+    );
+
+    let reply = client.request(&format!(
+        r#"{{"seq":2,"type":"request","command":"launch","arguments":{{"program":"{}"}}}}"#,
+        path
+    ));
+    assert!(reply.contains(r#""success":true"#), "{}", reply);
+
+    let reply = client.request(&format!(
+        r#"{{"seq":3,"type":"request","command":"setBreakpoints","arguments":{{"source":{{"path":"{}"}},"breakpoints":[{{"line":4}}]}}}}"#,
+        path
+    ));
+    assert!(reply.contains(r#"{"verified":true,"line":4}"#), "{}", reply);
+
+    client.send(r#"{"seq":4,"type":"request","command":"configurationDone"}"#);
+    let messages = client.receive_until(r#""event":"stopped""#);
+    assert!(
+        messages
+            .last()
+            .unwrap()
+            .contains(r#""reason":"breakpoint""#),
+        "{:?}",
+        messages
+    );
+
+    let reply = client.request(r#"{"seq":5,"type":"request","command":"threads"}"#);
+    assert!(reply.contains(r#"{"id":1,"name":"main"}"#), "{}", reply);
+
+    let reply = client
+        .request(r#"{"seq":6,"type":"request","command":"stackTrace","arguments":{"threadId":1}}"#);
+    assert!(reply.contains(r#""line":4"#), "{}", reply);
+
+    let reply = client
+        .request(r#"{"seq":7,"type":"request","command":"scopes","arguments":{"frameId":1}}"#);
+    assert!(
+        reply.contains(r#""name":"Locals","variablesReference":1"#),
+        "{}",
+        reply
+    );
+    assert!(
+        reply.contains(r#""name":"Globals","variablesReference":2"#),
+        "{}",
+        reply
+    );
+
+    let reply = client.request(
+        r#"{"seq":8,"type":"request","command":"variables","arguments":{"variablesReference":1}}"#,
+    );
+    assert!(
+        reply.contains(r#"{"name":"b","value":"2","variablesReference":0}"#),
+        "{}",
+        reply
+    );
+
+    let reply = client.request(r#"{"seq":9,"type":"request","command":"evaluate","arguments":{"expression":"a + b","frameId":1}}"#);
+    assert!(reply.contains(r#""result":"3""#), "{}", reply);
+
+    client.send(r#"{"seq":10,"type":"request","command":"next","arguments":{"threadId":1}}"#);
+    let messages = client.receive_until(r#""event":"stopped""#);
+    assert!(
+        messages.iter().any(|m| m.contains(r#""output":"2\n""#)),
+        "{:?}",
+        messages
+    );
+    assert!(
+        messages.last().unwrap().contains(r#""reason":"step""#),
+        "{:?}",
+        messages
+    );
+
+    let reply = client.request(
+        r#"{"seq":11,"type":"request","command":"stackTrace","arguments":{"threadId":1}}"#,
+    );
+    assert!(reply.contains(r#""line":6"#), "{}", reply);
+
+    client.send(r#"{"seq":12,"type":"request","command":"continue","arguments":{"threadId":1}}"#);
+    let messages = client.receive_until(r#""event":"terminated""#);
+    assert!(
+        messages.iter().any(|m| m.contains(r#""output":"1\n""#)),
+        "{:?}",
+        messages
+    );
+    assert!(
+        messages.iter().any(|m| m.contains(r#""exitCode":0"#)),
+        "{:?}",
+        messages
+    );
+
+    let reply = client.request(r#"{"seq":13,"type":"request","command":"disconnect"}"#);
+    assert!(reply.contains(r#""command":"disconnect""#), "{}", reply);
+    assert!(client.child.wait().unwrap().success());
+}
+
+#[test]
+fn test_call_frames_and_malformed_message() {
+    let path = std::env::temp_dir().join("muslox_dap_calls.lox");
+    fs::write(&path, "var f = fun (n) {\n  print n;\n};\nf(1);\n").unwrap();
+    let path = path.to_string_lossy().to_string();
+    let mut client = Client::spawn("dap");
+
+    let reply = client.request("{oops");
+    assert!(reply.contains(r#""success":false"#), "{}", reply);
+
+    client.send(r#"{"seq":1,"type":"request","command":"initialize","arguments":{}}"#);
+    client.receive_until(r#""event":"initialized""#);
+    client.request(&format!(
+        r#"{{"seq":2,"type":"request","command":"launch","arguments":{{"program":"{}"}}}}"#,
+        path
+    ));
+    client.request(&format!(
+        r#"{{"seq":3,"type":"request","command":"setBreakpoints","arguments":{{"source":{{"path":"{}"}},"breakpoints":[{{"line":2}}]}}}}"#,
+        path
+    ));
+    client.send(r#"{"seq":4,"type":"request","command":"configurationDone"}"#);
+    client.receive_until(r#""event":"stopped""#);
+
+    let reply = client.request("{oops");
+    assert!(reply.contains(r#""success":false"#), "{}", reply);
+
+    let reply = client
+        .request(r#"{"seq":5,"type":"request","command":"stackTrace","arguments":{"threadId":1}}"#);
+    let source = format!(
+        r#""source":{{"name":"muslox_dap_calls.lox","path":"{}"}}"#,
+        path
+    );
+    assert!(
+        reply.contains(&format!(
+            r#"{{"id":1,"name":"fn:1",{},"line":2,"column":1}},{{"id":2,"name":"main",{},"line":4,"column":1}}],"totalFrames":2"#,
+            source, source
+        )),
+        "{}",
+        reply
+    );
+
+    client.send(r#"{"seq":6,"type":"request","command":"continue","arguments":{"threadId":1}}"#);
+    client.receive_until(r#""event":"terminated""#);
+    client.request(r#"{"seq":7,"type":"request","command":"disconnect"}"#);
+    assert!(client.child.wait().unwrap().success());
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::Client;
+
+#[test]
+fn test_scripted_session() {
+    let mut client = Client::spawn("lsp");
+
+    let reply = client.request(r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#);
+    assert!(reply.contains(r#""hoverProvider":true"#), "{}", reply);
+    client.send(r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#);
+
+    let reply = client.request(
+        r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///a.lox","languageId":"lox","version":1,"text":"var count = 1 + 2;\nprint count;\nprint (;\n"}}}"#,
+    );
+    assert!(reply.contains("publishDiagnostics"), "{}", reply);
+    assert!(
+        reply.contains(
+            r#""range":{"start":{"line":2,"character":7},"end":{"line":2,"character":8}}"#
+        ),
+        "{}",
+        reply
+    );
+    assert!(reply.contains("Expected expression at ';'"), "{}", reply);
+
+    let position = r#""textDocument":{"uri":"file:///a.lox"},"position":{"line":1,"character":8}"#;
+    let reply = client.request(&format!(
+        r#"{{"jsonrpc":"2.0","id":2,"method":"textDocument/hover","params":{{{}}}}}"#,
+        position
+    ));
+    assert!(reply.contains(r#""value":"var count: num""#), "{}", reply);
+
+    let reply = client.request(&format!(
+        r#"{{"jsonrpc":"2.0","id":3,"method":"textDocument/definition","params":{{{}}}}}"#,
+        position
+    ));
+    assert!(
+        reply.contains(
+            r#""range":{"start":{"line":0,"character":4},"end":{"line":0,"character":9}}"#
+        ),
+        "{}",
+        reply
+    );
+
+    let reply = client.request(
+        r#"{"jsonrpc":"2.0","id":4,"method":"textDocument/documentSymbol","params":{"textDocument":{"uri":"file:///a.lox"}}}"#,
+    );
+    assert!(
+        reply.contains(r#""name":"count","detail":"num""#),
+        "{}",
+        reply
+    );
+
+    let reply = client.request(
+        r#"{"jsonrpc":"2.0","id":5,"method":"textDocument/completion","params":{"textDocument":{"uri":"file:///a.lox"},"position":{"line":2,"character":0}}}"#,
+    );
+    assert!(
+        reply.contains(r#"{"label":"while","kind":14}"#),
+        "{}",
+        reply
+    );
+    assert!(reply.contains(r#"{"label":"count","kind":6}"#), "{}", reply);
+
+    let reply = client.request(
+        r#"{"jsonrpc":"2.0","method":"textDocument/didChange","params":{"textDocument":{"uri":"file:///a.lox","version":2},"contentChanges":[{"text":"print 1;\n"}]}}"#,
+    );
+    assert!(reply.contains(r#""diagnostics":[]"#), "{}", reply);
+
+    let reply = client.request(r#"{"jsonrpc":"2.0","id":6,"method":"shutdown"}"#);
+    assert!(reply.contains(r#""id":6,"result":null"#), "{}", reply);
+    client.send(r#"{"jsonrpc":"2.0","method":"exit"}"#);
+    assert!(client.child.wait().unwrap().success());
+}
+
+#[test]
+fn test_malformed_message_and_utf16() {
+    let mut client = Client::spawn("lsp");
+
+    let reply = client.request("{oops");
+    assert!(
+        reply.contains(r#""id":null,"error":{"code":-32700"#),
+        "{}",
+        reply
+    );
+
+    // The emoji is two UTF-16 code units, so `t` is at character 18.
+    let reply = client.request(
+        r#"{"jsonrpc":"2.0","method":"textDocument/didOpen","params":{"textDocument":{"uri":"file:///b.lox","languageId":"lox","version":1,"text":"var s = \"😀\"; var t = s;\n"}}}"#,
+    );
+    assert!(reply.contains(r#""diagnostics":[]"#), "{}", reply);
+
+    let reply = client.request(
+        r#"{"jsonrpc":"2.0","id":1,"method":"textDocument/documentSymbol","params":{"textDocument":{"uri":"file:///b.lox"}}}"#,
+    );
+    assert!(
+        reply.contains(
+            r#""name":"t","detail":"str","kind":13,"range":{"start":{"line":0,"character":18},"end":{"line":0,"character":19}}"#
+        ),
+        "{}",
+        reply
+    );
+
+    let reply = client.request(
+        r#"{"jsonrpc":"2.0","id":2,"method":"textDocument/hover","params":{"textDocument":{"uri":"file:///b.lox"},"position":{"line":0,"character":22}}}"#,
+    );
+    assert!(reply.contains(r#""value":"var s: str""#), "{}", reply);
+    assert!(
+        reply.contains(r#""start":{"line":0,"character":22},"end":{"line":0,"character":23}"#),
+        "{}",
+        reply
+    );
+
+    client.send(r#"{"jsonrpc":"2.0","method":"exit"}"#);
+    assert!(client.child.wait().unwrap().success());
+}